@@ -22,10 +22,17 @@ pub struct PhysicBodyBundle {
 }
 
 impl PhysicBodyBundle {
+    /// Panics if the shape can't be made into a collider, use `try_from` for shapes from users
     pub fn from(body_type: RigidBody, shape: Shape, transform: Transform) -> Self {
-        Self {
+        Self::try_from(body_type, shape, transform)
+            .expect("Shape should be possible to make into a collider")
+    }
+
+    /// None if the shape can't be made into a collider
+    pub fn try_from(body_type: RigidBody, shape: Shape, transform: Transform) -> Option<Self> {
+        Some(Self {
             rigid_body: body_type,
-            collider_shape: shape.into_collider_shape(),
+            collider_shape: shape.into_collider_shape()?,
             collider_physical_properties: ColliderPhysicalProperties::default(),
             impulse: Impulse::default(),
             force: Force::default(),
@@ -36,7 +43,7 @@ impl PhysicBodyBundle {
                 local: transform,
                 ..Default::default()
             },
-        }
+        })
     }
 }
 
@@ -56,6 +63,8 @@ pub struct MeshPhysicBodyBundle {
 }
 
 impl MeshPhysicBodyBundle {
+    /// Panics if the shape can't be made into a mesh and a collider, use `try_from` for shapes
+    /// from users
     pub fn from(
         body_type: RigidBody,
         shape: Shape,
@@ -63,10 +72,22 @@ impl MeshPhysicBodyBundle {
         transform: Transform,
         meshes: &mut Assets<Mesh>,
     ) -> Self {
-        let mesh = shape.into_mesh().unwrap();
-        let collider_shape = shape.into_collider_shape();
+        Self::try_from(body_type, shape, material, transform, meshes)
+            .expect("Shape should be possible to make into a mesh and a collider")
+    }
+
+    /// None if the shape can't be made into a mesh and a collider
+    pub fn try_from(
+        body_type: RigidBody,
+        shape: Shape,
+        material: Handle<StandardMaterial>,
+        transform: Transform,
+        meshes: &mut Assets<Mesh>,
+    ) -> Option<Self> {
+        let mesh = shape.into_mesh()?;
+        let collider_shape = shape.into_collider_shape()?;
 
-        Self {
+        Some(Self {
             rigid_body: body_type,
             collider_shape,
             collider_physical_properties: ColliderPhysicalProperties::default(),
@@ -81,6 +102,6 @@ impl MeshPhysicBodyBundle {
                 transform,
                 ..Default::default()
             },
-        }
+        })
     }
 }
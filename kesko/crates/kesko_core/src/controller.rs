@@ -4,7 +4,8 @@ pub trait Controller {
     type Input;
     type Output;
 
-    fn act(&mut self, val: Self::Input) -> Self::Output;
+    /// Computes the output given the input and the time since the last call
+    fn act(&mut self, val: Self::Input, dt: f32) -> Self::Output;
 }
 
 pub(crate) struct PID<T> {
@@ -32,9 +33,9 @@ impl Controller for PID<Vec3> {
     type Input = Vec3;
     type Output = Vec3;
 
-    fn act(&mut self, val: Self::Input) -> Self::Output {
+    fn act(&mut self, val: Self::Input, dt: f32) -> Self::Output {
         let output = if let (Some(prev_val), Some(sum_val)) = (self.prev_val, self.sum_val) {
-            self.p * val + self.d * 60.0 * (val - prev_val) + self.i * sum_val
+            self.p * val + self.d * (val - prev_val) / dt + self.i * sum_val
         } else {
             Self::Input::ZERO
         };
@@ -50,3 +51,17 @@ impl Controller for PID<Vec3> {
         output
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pid_derivative_uses_dt() {
+        let mut pid = PID::<Vec3>::new(0.0, 0.0, 1.0);
+
+        assert_eq!(pid.act(Vec3::ZERO, 0.1), Vec3::ZERO);
+        let output = pid.act(Vec3::X, 0.1);
+        assert!(output.abs_diff_eq(Vec3::new(10.0, 0.0, 0.0), 1e-5));
+    }
+}
@@ -6,6 +6,7 @@ use crate::controller::{Controller, PID};
 use crate::orbit_camera::PanOrbitCamera;
 use kesko_object_interaction::event::InteractionEvent;
 use kesko_physics::mass::MultibodyMass;
+use kesko_physics::timestep::PhysicsTime;
 use kesko_physics::{force::Force, gravity::GravityScale, mass::Mass};
 use kesko_raycast::RayCastSource;
 
@@ -91,7 +92,14 @@ pub(crate) fn update_tracking_controller_system<T: Component + Default>(
         ),
         With<CursorTrack>,
     >,
+    physics_time: Res<PhysicsTime>,
 ) {
+    // the controller only needs to be updated when the physics has moved forward
+    let dt = physics_time.delta();
+    if dt <= 0.0 {
+        return;
+    }
+
     if let Ok((ray_source, camera_transform)) = ray_query.get_single() {
         if let Some(ray) = &ray_source.ray {
             for (mut track, mut force, mass, multibody_mass, transform) in track_query.iter_mut() {
@@ -106,7 +114,7 @@ pub(crate) fn update_tracking_controller_system<T: Component + Default>(
 
                 let pos_diff: Vec3 = plane_intersection - transform.translation();
 
-                let mut control_output = track.controller.act(pos_diff);
+                let mut control_output = track.controller.act(pos_diff, dt);
 
                 if let Some(mass) = multibody_mass {
                     control_output *= mass.val as f32;
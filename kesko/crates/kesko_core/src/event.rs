@@ -5,11 +5,14 @@ use bevy::prelude::*;
 use bevy::utils::hashbrown::HashMap;
 
 use kesko_physics::{
+    depth_camera::{DepthCamera, DepthImage},
+    imu::{Imu, ImuReading},
     joint::{
-        prismatic::PrismaticJoint, revolute::RevoluteJoint, JointMotorEvent, JointState,
-        MotorCommand,
+        prismatic::PrismaticJoint, revolute::RevoluteJoint, spherical::SphericalJoint,
+        JointMotorEvent, JointState, KeskoAxis, MotorCommand,
     },
     multibody::{MultiBodyState, MultiBodyStates, MultibodyChild, MultibodyRoot},
+    range_sensor::RangeSensor,
     rapier_extern::rapier::prelude as rapier,
 };
 use serde::{Deserialize, Serialize};
@@ -17,17 +20,25 @@ use serde::{Deserialize, Serialize};
 #[derive(Event)]
 pub enum SimulatorRequestEvent {
     GetState,
+    /// Latest images of all depth cameras
+    GetDepthImages,
     ExitApp,
     IsAlive,
     ApplyMotorCommand {
         entity: Entity,
         command: HashMap<u64, f32>,
     },
+    /// Targets for the x, y and z axis of spherical joints
+    ApplySphericalMotorCommand {
+        entity: Entity,
+        command: HashMap<u64, Vec3>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Event)]
 pub enum SimulatorResponseEvent {
     MultibodyStates(MultiBodyStates),
+    DepthImages(Vec<DepthImage>),
     WillExitApp,
     Alive,
     Ok(String),
@@ -54,39 +65,142 @@ pub fn handle_system_events(
     }
 }
 
+/// Motor command for a revolute or prismatic joint. Joints with a motor that has no stiffness
+/// can't hold a position, for those the value is used as a velocity target instead.
+pub fn motor_command(
+    revolute_joint: Option<&RevoluteJoint>,
+    prismatic_joint: Option<&PrismaticJoint>,
+    val: rapier::Real,
+) -> MotorCommand {
+    if let Some(joint) = prismatic_joint {
+        if joint.stiffness == 0.0 {
+            MotorCommand::VelocityPrismatic {
+                velocity: val,
+                damping: None,
+            }
+        } else {
+            MotorCommand::PositionPrismatic {
+                position: val,
+                stiffness: None,
+                damping: None,
+            }
+        }
+    } else if matches!(revolute_joint, Some(joint) if joint.stiffness == 0.0) {
+        MotorCommand::VelocityRevolute {
+            velocity: val,
+            damping: None,
+        }
+    } else {
+        MotorCommand::PositionRevolute {
+            position: val,
+            stiffness: None,
+            damping: None,
+        }
+    }
+}
+
+/// Motor commands for the x, y and z axis of a spherical joint, in the same way as
+/// [`motor_command`] an axis without stiffness gets a velocity target.
+pub fn spherical_motor_commands(joint: &SphericalJoint, vals: Vec3) -> [MotorCommand; 3] {
+    let axes = [KeskoAxis::AngX, KeskoAxis::AngY, KeskoAxis::AngZ];
+    std::array::from_fn(|i| {
+        let (axis, val) = (axes[i], vals[i]);
+        match joint.motor_params(axis) {
+            Some((stiffness, _)) if stiffness == 0.0 => MotorCommand::VelocitySpherical {
+                velocity: val,
+                axis,
+            },
+            _ => MotorCommand::PositionSpherical {
+                position: val,
+                axis,
+            },
+        }
+    })
+}
+
+/// Sends motor commands for each joint, see [`motor_command`]
 pub fn handle_motor_command_requests(
     mut system_requests: EventReader<SimulatorRequestEvent>,
     mut motor_event_writer: EventWriter<JointMotorEvent>,
+    revolute_joints: Query<&RevoluteJoint>,
+    prismatic_joints: Query<&PrismaticJoint>,
 ) {
     for event in system_requests.iter() {
         if let SimulatorRequestEvent::ApplyMotorCommand { entity: _, command } = event {
             for (joint_id, val) in command.iter() {
-                motor_event_writer.send(JointMotorEvent {
-                    entity: Entity::from_bits(*joint_id),
-                    command: MotorCommand::PositionRevolute {
-                        position: *val as rapier::Real,
-                        stiffness: None,
-                        damping: None,
-                    },
-                });
+                let entity = Entity::from_bits(*joint_id);
+                let command = motor_command(
+                    revolute_joints.get(entity).ok(),
+                    prismatic_joints.get(entity).ok(),
+                    *val as rapier::Real,
+                );
+                motor_event_writer.send(JointMotorEvent { entity, command });
+            }
+        }
+    }
+}
+
+/// Sends motor commands for each axis of spherical joints, see [`spherical_motor_commands`]
+pub fn handle_spherical_motor_command_requests(
+    mut system_requests: EventReader<SimulatorRequestEvent>,
+    mut motor_event_writer: EventWriter<JointMotorEvent>,
+    spherical_joints: Query<&SphericalJoint>,
+) {
+    for event in system_requests.iter() {
+        if let SimulatorRequestEvent::ApplySphericalMotorCommand { entity: _, command } = event {
+            for (joint_id, val) in command.iter() {
+                let entity = Entity::from_bits(*joint_id);
+                let joint = match spherical_joints.get(entity) {
+                    Ok(joint) => joint,
+                    Err(e) => {
+                        error!("Spherical motor command for joint {}: {}", joint_id, e);
+                        continue;
+                    }
+                };
+
+                for command in spherical_motor_commands(joint, *val) {
+                    motor_event_writer.send(JointMotorEvent { entity, command });
+                }
             }
         }
     }
 }
 
+pub fn handle_depth_image_requests(
+    mut system_requests: EventReader<SimulatorRequestEvent>,
+    mut system_response_writer: EventWriter<SimulatorResponseEvent>,
+    cameras: Query<(Entity, &DepthCamera, Option<&Name>)>,
+) {
+    for event in system_requests.iter() {
+        if let SimulatorRequestEvent::GetDepthImages = event {
+            let images = cameras
+                .iter()
+                .map(|(entity, camera, name)| {
+                    let name = name.map_or_else(|| entity.index().to_string(), Name::to_string);
+                    camera.image(entity, name)
+                })
+                .collect();
+            system_response_writer.send(SimulatorResponseEvent::DepthImages(images));
+        }
+    }
+}
+
 pub fn handle_serializable_state_request(
     mut system_requests: EventReader<SimulatorRequestEvent>,
     mut system_response_writer: EventWriter<SimulatorResponseEvent>,
-    multibody_root_query: Query<(Entity, &MultibodyRoot, &Transform)>,
+    multibody_root_query: Query<(Entity, &MultibodyRoot, &Transform, Option<&Name>)>,
     multibody_child_query: Query<(&MultibodyChild, &Transform)>,
     revolute_joints: Query<&RevoluteJoint>,
     prismatic_joints: Query<&PrismaticJoint>,
+    spherical_joints: Query<&SphericalJoint>,
+    range_sensors: Query<&RangeSensor>,
+    imus: Query<&Imu>,
 ) {
     for event in system_requests.iter() {
         if let SimulatorRequestEvent::GetState = event {
             let states = multibody_root_query
                 .iter()
-                .map(|(e, root, transform)| {
+                .map(|(e, root, transform, root_name)| {
                     // get positions of all the child bodies
                     let child_positions: BTreeMap<String, Vec3> = root
                         .child_map
@@ -109,6 +223,8 @@ pub fn handle_serializable_state_request(
                                 Some(joint.state())
                             } else if let Ok(joint) = prismatic_joints.get(*e) {
                                 Some(joint.state())
+                            } else if let Ok(joint) = spherical_joints.get(*e) {
+                                Some(joint.state())
                             } else {
                                 None
                             };
@@ -117,6 +233,23 @@ pub fn handle_serializable_state_request(
                         })
                         .collect();
 
+                    // sensors on the root are named as the root link
+                    let root_name =
+                        root_name.map_or_else(|| e.index().to_string(), Name::to_string);
+                    let links = || std::iter::once((&root_name, &e)).chain(root.child_map.iter());
+
+                    let sensors: BTreeMap<String, Vec<f32>> = links()
+                        .filter_map(|(name, link)| {
+                            let sensor = range_sensors.get(*link).ok()?;
+                            Some((name.clone(), sensor.ranges().to_vec()))
+                        })
+                        .collect();
+                    let imu_readings: BTreeMap<String, ImuReading> = links()
+                        .filter_map(|(name, link)| {
+                            Some((name.clone(), imus.get(*link).ok()?.reading()))
+                        })
+                        .collect();
+
                     MultiBodyState {
                         name: root.name.clone(),
                         id: e.to_bits(),
@@ -126,6 +259,8 @@ pub fn handle_serializable_state_request(
                         angular_velocity: root.angvel,
                         relative_positions: Some(child_positions),
                         joint_states: Some(joint_states),
+                        range_sensors: Some(sensors),
+                        imus: Some(imu_readings),
                     }
                 })
                 .collect::<Vec<MultiBodyState>>();
@@ -136,3 +271,39 @@ pub fn handle_serializable_state_request(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_stiffness_gives_velocity_commands() {
+        let mut joint = SphericalJoint::attach_to(Entity::from_raw(0)).with_motor_params(1.0, 0.5);
+        joint.y_stiffness = 0.0;
+
+        let commands = spherical_motor_commands(&joint, Vec3::new(0.1, 0.2, 0.3));
+        assert!(matches!(
+            commands[0],
+            MotorCommand::PositionSpherical { position, axis: KeskoAxis::AngX } if position == 0.1
+        ));
+        assert!(matches!(
+            commands[1],
+            MotorCommand::VelocitySpherical { velocity, axis: KeskoAxis::AngY } if velocity == 0.2
+        ));
+        assert!(matches!(
+            commands[2],
+            MotorCommand::PositionSpherical { position, axis: KeskoAxis::AngZ } if position == 0.3
+        ));
+
+        let revolute = RevoluteJoint::attach_to(Entity::from_raw(0)).with_motor_params(0.0, 1.0);
+        assert!(matches!(
+            motor_command(Some(&revolute), None, 1.0),
+            MotorCommand::VelocityRevolute { .. }
+        ));
+        let prismatic = PrismaticJoint::attach_to(Entity::from_raw(0)).with_motor_params(1.0, 1.0);
+        assert!(matches!(
+            motor_command(None, Some(&prismatic), 1.0),
+            MotorCommand::PositionPrismatic { .. }
+        ));
+    }
+}
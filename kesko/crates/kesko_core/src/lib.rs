@@ -92,6 +92,8 @@ impl Plugin for CorePlugin {
                     event::handle_system_events,
                     event::handle_serializable_state_request,
                     event::handle_motor_command_requests,
+                    event::handle_spherical_motor_command_requests,
+                    event::handle_depth_image_requests,
                 ).in_set(HandleEventsSet),
             );
     }
@@ -131,6 +133,39 @@ impl Plugin for CoreHeadlessPlugin {
                 event::handle_system_events,
                 event::handle_serializable_state_request,
                 event::handle_motor_command_requests,
+                event::handle_spherical_motor_command_requests,
+                event::handle_depth_image_requests,
+            ),
+        );
+    }
+}
+
+/// Core plugin without rendering, windows or logging.
+/// Used when running many simulations in the same process.
+pub struct CoreMinimalPlugin;
+
+impl Plugin for CoreMinimalPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            AssetPlugin::default(),
+        ))
+        // models are spawned with meshes and materials even if they are never rendered
+        .add_asset::<Mesh>()
+        .add_asset::<StandardMaterial>()
+        // Simulator system events
+        .add_event::<event::SimulatorRequestEvent>()
+        .add_event::<event::SimulatorResponseEvent>()
+        .add_systems(
+            Last,
+            (
+                event::handle_system_events,
+                event::handle_serializable_state_request,
+                event::handle_motor_command_requests,
+                event::handle_spherical_motor_command_requests,
+                event::handle_depth_image_requests,
             ),
         );
     }
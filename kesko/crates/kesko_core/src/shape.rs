@@ -131,6 +131,14 @@ impl From<Cylinder> for Mesh {
     }
 }
 
+/// Which kind of collider to build from a mesh
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshCollider {
+    TriMesh,
+    ConvexHull,
+    ConvexDecomposition,
+}
+
 #[derive(Debug)]
 pub enum Shape {
     Sphere {
@@ -154,6 +162,20 @@ pub enum Shape {
         radius: f32,
         length: f32,
     },
+    /// Arbitrary triangle mesh used both for rendering and as collider
+    Mesh {
+        mesh: Mesh,
+        collider: MeshCollider,
+    },
+    /// Grid of heights centered on the xz-plane, the heights are given row by row where rows go
+    /// along the z-axis and columns along the x-axis
+    HeightField {
+        heights: Vec<f32>,
+        rows: usize,
+        cols: usize,
+        x_length: f32,
+        z_length: f32,
+    },
 }
 
 impl Shape {
@@ -197,11 +219,20 @@ impl Shape {
                 .into(),
             ),
             Self::Cube { size } => Some(shape::Box::new(*size, *size, *size).into()),
+            Self::Mesh { mesh, .. } => Some(mesh.clone()),
+            Self::HeightField {
+                heights,
+                rows,
+                cols,
+                x_length,
+                z_length,
+            } => height_field_mesh(heights, *rows, *cols, *x_length, *z_length),
         }
     }
 
-    pub fn into_collider_shape(&self) -> ColliderShape {
-        match self {
+    /// Collider of the shape, None if a mesh is not a triangle list with vertex positions
+    pub fn into_collider_shape(&self) -> Option<ColliderShape> {
+        let shape = match self {
             Self::Sphere {
                 radius,
                 subdivisions: _,
@@ -234,6 +265,119 @@ impl Shape {
                 y_half: (size / 2.0) as rapier::Real,
                 z_half: (size / 2.0) as rapier::Real,
             },
+            Self::Mesh { mesh, collider } => match collider {
+                MeshCollider::TriMesh => ColliderShape::trimesh(mesh),
+                MeshCollider::ConvexHull => ColliderShape::convex_hull(mesh),
+                MeshCollider::ConvexDecomposition => ColliderShape::convex_decomposition(mesh),
+            }?,
+            Self::HeightField {
+                heights,
+                rows,
+                cols,
+                x_length,
+                z_length,
+            } => ColliderShape::HeightField {
+                heights: heights.iter().map(|h| *h as rapier::Real).collect(),
+                rows: *rows,
+                cols: *cols,
+                x_length: *x_length as rapier::Real,
+                z_length: *z_length as rapier::Real,
+            },
+        };
+        Some(shape)
+    }
+}
+
+/// Mesh of a height field with the same triangles as its collider, None if the heights don't make
+/// up a grid of at least two by two
+fn height_field_mesh(
+    heights: &[f32],
+    rows: usize,
+    cols: usize,
+    x_length: f32,
+    z_length: f32,
+) -> Option<Mesh> {
+    if rows < 2 || cols < 2 || heights.len() != rows * cols {
+        return None;
+    }
+
+    let dx = x_length / (cols - 1) as f32;
+    let dz = z_length / (rows - 1) as f32;
+    let height = |row: usize, col: usize| heights[row * cols + col];
+
+    let mut positions = Vec::with_capacity(rows * cols);
+    let mut normals = Vec::with_capacity(rows * cols);
+    let mut uvs = Vec::with_capacity(rows * cols);
+    for row in 0..rows {
+        for col in 0..cols {
+            positions.push([
+                -x_length / 2.0 + col as f32 * dx,
+                height(row, col),
+                -z_length / 2.0 + row as f32 * dz,
+            ]);
+
+            // slope from the neighbours, one sided at the edges
+            let (left, right) = (col.saturating_sub(1), (col + 1).min(cols - 1));
+            let (back, front) = (row.saturating_sub(1), (row + 1).min(rows - 1));
+            let slope_x = (height(row, right) - height(row, left)) / ((right - left) as f32 * dx);
+            let slope_z = (height(front, col) - height(back, col)) / ((front - back) as f32 * dz);
+            normals.push(Vec3::new(-slope_x, 1.0, -slope_z).normalize().to_array());
+
+            uvs.push([
+                col as f32 / (cols - 1) as f32,
+                row as f32 / (rows - 1) as f32,
+            ]);
+        }
+    }
+
+    let mut indices = Vec::with_capacity(6 * (rows - 1) * (cols - 1));
+    for row in 0..rows - 1 {
+        for col in 0..cols - 1 {
+            let i = (row * cols + col) as u32;
+            let below = i + cols as u32;
+            indices.extend([i, below, i + 1]);
+            indices.extend([i + 1, below, below + 1]);
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    Some(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mesh_collider() {
+        let shape = Shape::Mesh {
+            mesh: shape::Box::new(1.0, 1.0, 1.0).into(),
+            collider: MeshCollider::ConvexHull,
+        };
+        assert!(matches!(
+            shape.into_collider_shape(),
+            Some(ColliderShape::ConvexHull { .. })
+        ));
+    }
+
+    #[test]
+    fn mesh_without_triangles_has_no_collider() {
+        let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0f32; 3], [1.0, 0.0, 0.0]]);
+        for collider in [
+            MeshCollider::TriMesh,
+            MeshCollider::ConvexHull,
+            MeshCollider::ConvexDecomposition,
+        ] {
+            let shape = Shape::Mesh {
+                mesh: mesh.clone(),
+                collider,
+            };
+            assert!(shape.into_collider_shape().is_none());
         }
     }
 }
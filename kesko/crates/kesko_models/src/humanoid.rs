@@ -80,7 +80,7 @@ impl Humanoid {
         material: Handle<StandardMaterial>,
         transform: Transform,
         meshes: &mut Assets<Mesh>,
-    ) {
+    ) -> Entity {
         let head = commands
             .spawn((
                 MeshPhysicBodyBundle::from(
@@ -120,6 +120,8 @@ impl Humanoid {
         );
 
         Self::build_legs(hip, commands, material, hip_transform, meshes);
+
+        head
     }
 
     fn build_neck(
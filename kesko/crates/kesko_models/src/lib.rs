@@ -5,11 +5,16 @@ pub mod plane;
 pub mod snake;
 pub mod sphere;
 pub mod spider;
+pub mod terrain;
 pub mod wheely;
 
+use std::path::PathBuf;
+
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use kesko_physics::{collider::CollisionGroups, multibody::SelfCollision};
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
 pub enum SpawnSet {
     Spawn,
@@ -37,9 +42,44 @@ pub enum SpawnEvent {
         model: Model,
         transform: Transform,
         color: Color,
+        collision: SpawnCollision,
+    },
+    /// Spawn a robot described by a URDF file
+    SpawnUrdf {
+        path: PathBuf,
+        transform: Transform,
+        collision: SpawnCollision,
+    },
+    /// Spawn procedurally generated terrain
+    SpawnTerrain {
+        terrain: terrain::Terrain,
+        transform: Transform,
+        color: Color,
     },
 }
 
+/// Collision settings for the root of a spawned model, `None` keeps the defaults
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct SpawnCollision {
+    /// Groups for all the bodies of the model, see [`CollisionGroups`]
+    #[serde(default)]
+    pub groups: Option<CollisionGroups>,
+    /// If the links of the model can collide with each other
+    #[serde(default)]
+    pub self_collision: Option<bool>,
+}
+
+impl SpawnCollision {
+    fn insert(&self, commands: &mut Commands, root: Entity) {
+        if let Some(groups) = self.groups {
+            commands.entity(root).insert(groups);
+        }
+        if let Some(self_collision) = self.self_collision {
+            commands.entity(root).insert(SelfCollision(self_collision));
+        }
+    }
+}
+
 /// Description on how to manually control a robot
 /// The text will be shown in the multibody ui
 #[derive(Component)]
@@ -86,45 +126,108 @@ pub fn spawn_system(
             model,
             transform,
             color,
+            collision,
         } = event
         {
             debug!("Spawning model {:?}", model);
 
             let material = materials.add(color.clone().into());
 
-            match model {
-                Model::Spider => spider::spawn(&mut commands, material, *transform, &mut meshes),
-                Model::Snake => {
-                    snake::Snake::spawn(&mut commands, material, *transform, &mut meshes)
-                }
+            let root = match model {
+                Model::Spider => Some(spider::spawn(
+                    &mut commands,
+                    material,
+                    *transform,
+                    &mut meshes,
+                )),
+                Model::Snake => Some(snake::Snake::spawn(
+                    &mut commands,
+                    material,
+                    *transform,
+                    &mut meshes,
+                )),
                 Model::Car => {
                     let wheel_material = materials.add(Color::DARK_GRAY.into());
-                    car::Car::spawn(
+                    Some(car::Car::spawn(
                         &mut commands,
                         material,
                         wheel_material,
                         *transform,
                         &mut meshes,
-                    );
-                }
-                Model::Sphere => {
-                    sphere::Sphere::spawn(&mut commands, material, *transform, &mut meshes)
+                    ))
                 }
+                Model::Sphere => Some(sphere::Sphere::spawn(
+                    &mut commands,
+                    material,
+                    *transform,
+                    &mut meshes,
+                )),
                 Model::Wheely => {
                     let wheel_material = materials.add(Color::DARK_GRAY.into());
-                    wheely::Wheely::spawn(
+                    Some(wheely::Wheely::spawn(
                         &mut commands,
                         material,
                         wheel_material,
                         *transform,
                         &mut meshes,
-                    );
+                    ))
                 }
-                Model::Humanoid => {
-                    humanoid::Humanoid::spawn(&mut commands, material, *transform, &mut meshes)
+                Model::Humanoid => Some(humanoid::Humanoid::spawn(
+                    &mut commands,
+                    material,
+                    *transform,
+                    &mut meshes,
+                )),
+                Model::Arena => {
+                    arena::spawn(&mut commands, material, &mut meshes, 10.0, 10.0, 1.0);
+                    None
                 }
-                Model::Arena => arena::spawn(&mut commands, material, &mut meshes, 10.0, 10.0, 1.0),
-                Model::Plane => plane::spawn(&mut commands, material, &mut meshes),
+                Model::Plane => {
+                    plane::spawn(&mut commands, material, &mut meshes);
+                    None
+                }
+            };
+
+            match root {
+                Some(root) => collision.insert(&mut commands, root),
+                None if collision.groups.is_some() || collision.self_collision.is_some() => {
+                    warn!("Collision settings are not supported for {:?}", model)
+                }
+                None => {}
+            }
+        } else if let SpawnEvent::SpawnUrdf {
+            path,
+            transform,
+            collision,
+        } = event
+        {
+            debug!("Spawning URDF {:?}", path);
+
+            let spawned = kesko_urdf::Robot::from_file(path).and_then(|robot| {
+                kesko_urdf::spawn(
+                    &mut commands,
+                    &robot,
+                    *transform,
+                    &mut materials,
+                    &mut meshes,
+                )
+            });
+
+            match spawned {
+                Ok(root) => collision.insert(&mut commands, root),
+                Err(e) => error!("Failed to spawn URDF: {}", e),
+            }
+        } else if let SpawnEvent::SpawnTerrain {
+            terrain,
+            transform,
+            color,
+        } = event
+        {
+            debug!("Spawning terrain {:?}", terrain.kind);
+
+            let material = materials.add((*color).into());
+            if let Err(e) = terrain.spawn(&mut commands, material, *transform, &mut meshes) {
+                error!("Failed to spawn terrain: {}", e);
             }
         }
     }
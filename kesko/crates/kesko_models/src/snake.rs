@@ -13,7 +13,7 @@ impl Snake {
         material: Handle<StandardMaterial>,
         transform: Transform,
         meshes: &mut Assets<Mesh>,
-    ) {
+    ) -> Entity {
         let radius = 0.07;
         let length = 0.3;
         let half_length = length / 2.0 + radius;
@@ -21,7 +21,7 @@ impl Snake {
 
         let mut world_transform = transform;
 
-        let mut root = commands
+        let head = commands
             .spawn((
                 MeshPhysicBodyBundle::from(
                     RigidBody::Dynamic,
@@ -36,6 +36,7 @@ impl Snake {
             ))
             .id();
 
+        let mut root = head;
         for i in 1..4 {
             let parent_anchor = Transform::from_translation((half_length + margin) * Vec3::Y);
             let child_anchor = Transform::from_translation(-(half_length + margin) * Vec3::Y);
@@ -62,5 +63,7 @@ impl Snake {
 
             root = child;
         }
+
+        head
     }
 }
@@ -16,30 +16,32 @@ impl Sphere {
         material: Handle<StandardMaterial>,
         transform: Transform,
         meshes: &mut Assets<Mesh>,
-    ) {
-        commands.spawn((
-            PbrBundle {
-                material,
-                mesh: meshes.add(
-                    shape::Icosphere {
-                        radius: 0.2,
-                        subdivisions: 5,
-                    }
-                    .try_into()
-                    .unwrap(),
-                ),
-                transform,
-                ..default()
-            },
-            RigidBody::Dynamic,
-            ColliderShape::Sphere { radius: 0.2 },
-            InteractiveBundle::<GroupDynamic>::default(),
-            Force::default(),
-            ColliderPhysicalProperties {
-                restitution: 0.7,
-                ..default()
-            },
-            GravityScale::default(),
-        ));
+    ) -> Entity {
+        commands
+            .spawn((
+                PbrBundle {
+                    material,
+                    mesh: meshes.add(
+                        shape::Icosphere {
+                            radius: 0.2,
+                            subdivisions: 5,
+                        }
+                        .try_into()
+                        .unwrap(),
+                    ),
+                    transform,
+                    ..default()
+                },
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.2 },
+                InteractiveBundle::<GroupDynamic>::default(),
+                Force::default(),
+                ColliderPhysicalProperties {
+                    restitution: 0.7,
+                    ..default()
+                },
+                GravityScale::default(),
+            ))
+            .id()
     }
 }
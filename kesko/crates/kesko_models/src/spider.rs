@@ -31,7 +31,7 @@ pub fn spawn(
     material: Handle<StandardMaterial>,
     transform: Transform,
     meshes: &mut Assets<Mesh>,
-) {
+) -> Entity {
     let body_radius = 0.2;
     let leg_length = 0.3;
     let leg_radius = 0.06;
@@ -311,4 +311,6 @@ pub fn spawn(
         Mass { val: mass_leg },
         Name::new(RIGHT_REAR_Z),
     ));
+
+    body
 }
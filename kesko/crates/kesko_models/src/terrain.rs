@@ -0,0 +1,338 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use kesko_core::{bundle::MeshPhysicBodyBundle, interaction::groups::GroupStatic, shape::Shape};
+use kesko_physics::rigid_body::RigidBody;
+use kesko_raycast::RayVisible;
+
+const NAME: &str = "terrain";
+
+/// Height of the holes between stepping stones and gaps, deep enough that a leg can't reach the bottom
+const HOLE_DEPTH: f32 = -1.0;
+
+/// Half the side of the flat area in the middle of the terrain where models are spawned
+const START_HALF_SIZE: f32 = 1.0;
+
+/// Max number of points along each side of the height field, limits the memory used for terrain
+/// requested by clients
+const MAX_POINTS_PER_SIDE: usize = 2049;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TerrainKind {
+    /// Random bumps on top of rolling hills
+    Rough,
+    /// Stairs going up in every direction from the middle
+    Stairs,
+    /// Slopes going up in every direction from the middle
+    Slope,
+    /// Square stones with holes between them
+    SteppingStones,
+    /// Strips of ground with gaps between them along the x-axis
+    Gaps,
+}
+
+/// Procedurally generated height field terrain, the same seed and parameters always give the same terrain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Terrain {
+    pub kind: TerrainKind,
+    /// From 0 for almost flat ground to 1 for the hardest terrain
+    pub difficulty: f32,
+    #[serde(default)]
+    pub seed: u64,
+    /// Length of the sides of the terrain
+    #[serde(default = "default_size")]
+    pub size: f32,
+    /// Distance between the points of the height field, has to be small enough for the features to show
+    #[serde(default = "default_resolution")]
+    pub resolution: f32,
+}
+
+fn default_size() -> f32 {
+    16.0
+}
+
+fn default_resolution() -> f32 {
+    0.05
+}
+
+impl Terrain {
+    pub fn new(kind: TerrainKind, difficulty: f32, seed: u64) -> Self {
+        Self {
+            kind,
+            difficulty,
+            seed,
+            size: default_size(),
+            resolution: default_resolution(),
+        }
+    }
+
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_resolution(mut self, resolution: f32) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    /// Checks that the terrain can be generated, the parameters come from clients
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.size.is_finite() || self.size <= 0.0 {
+            return Err(format!(
+                "Terrain size has to be positive, got {}",
+                self.size
+            ));
+        }
+        if !self.resolution.is_finite() || self.resolution <= 0.0 {
+            return Err(format!(
+                "Terrain resolution has to be positive, got {}",
+                self.resolution
+            ));
+        }
+        if !self.difficulty.is_finite() {
+            return Err(format!(
+                "Terrain difficulty has to be finite, got {}",
+                self.difficulty
+            ));
+        }
+        if (self.size / self.resolution).round() >= MAX_POINTS_PER_SIDE as f32 {
+            return Err(format!(
+                "Terrain with size {} and resolution {} has more than {} points per side",
+                self.size, self.resolution, MAX_POINTS_PER_SIDE
+            ));
+        }
+        Ok(())
+    }
+
+    /// Height field shape of the terrain, the ground in the middle is at zero height
+    pub fn shape(&self) -> Result<Shape, String> {
+        self.validate()?;
+
+        let points = ((self.size / self.resolution).round() as usize).max(1) + 1;
+        let step = self.size / (points - 1) as f32;
+        let difficulty = self.difficulty.clamp(0.0, 1.0);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let height: Box<dyn Fn(f32, f32) -> f32> = match self.kind {
+            TerrainKind::Rough => {
+                // random heights on a coarse grid interpolated between, with small bumps on top
+                let hill_height = 0.25 * difficulty;
+                let bump_height = 0.01 + 0.04 * difficulty;
+                let cells = (self.size / 2.0).ceil() as usize + 1;
+                let hills = (0..cells * cells)
+                    .map(|_| rng.gen_range(-hill_height..=hill_height))
+                    .collect::<Vec<_>>();
+                let bumps = (0..points * points)
+                    .map(|_| rng.gen_range(-bump_height..=bump_height))
+                    .collect::<Vec<_>>();
+                let size = self.size;
+                Box::new(move |x, z| {
+                    let (u, v) = ((x + size / 2.0) / 2.0, (z + size / 2.0) / 2.0);
+                    let (col, row) = ((u as usize).min(cells - 2), (v as usize).min(cells - 2));
+                    let (s, t) = (u - col as f32, v - row as f32);
+                    let hill = |r: usize, c: usize| hills[r * cells + c];
+                    let hill = (1.0 - t) * ((1.0 - s) * hill(row, col) + s * hill(row, col + 1))
+                        + t * ((1.0 - s) * hill(row + 1, col) + s * hill(row + 1, col + 1));
+
+                    let index =
+                        |p: f32| (((p + size / 2.0) / step).round() as usize).min(points - 1);
+
+                    // the hills start at the edge of the start area so there is no step up
+                    let start_distance = x.abs().max(z.abs()) - START_HALF_SIZE;
+                    start_distance.clamp(0.0, 1.0) * (hill + bumps[index(z) * points + index(x)])
+                })
+            }
+            TerrainKind::Stairs => {
+                let step_height = 0.02 + 0.16 * difficulty;
+                let step_depth = 0.5 - 0.2 * difficulty;
+                Box::new(move |x, z| {
+                    let distance = x.abs().max(z.abs()) - START_HALF_SIZE;
+                    (distance / step_depth).ceil().max(0.0) * step_height
+                })
+            }
+            TerrainKind::Slope => {
+                let slope = (difficulty * 30f32.to_radians()).tan();
+                Box::new(move |x, z| (x.abs().max(z.abs()) - START_HALF_SIZE).max(0.0) * slope)
+            }
+            TerrainKind::SteppingStones => {
+                let stone_size = 1.0 - 0.6 * difficulty;
+                let gap = 0.05 + 0.3 * difficulty;
+                let stone_height = 0.1 * difficulty;
+                let period = stone_size + gap;
+                let stones_per_side = (self.size / period).ceil() as usize;
+                let stone_heights = (0..stones_per_side * stones_per_side)
+                    .map(|_| rng.gen_range(-stone_height..=stone_height))
+                    .collect::<Vec<_>>();
+                let size = self.size;
+                Box::new(move |x, z| {
+                    if x.abs().max(z.abs()) <= START_HALF_SIZE {
+                        return 0.0;
+                    }
+                    let (u, v) = ((x + size / 2.0) / period, (z + size / 2.0) / period);
+                    if u.fract() * period > stone_size || v.fract() * period > stone_size {
+                        return HOLE_DEPTH;
+                    }
+                    let (col, row) = (u as usize, v as usize);
+                    stone_heights[row.min(stones_per_side - 1) * stones_per_side
+                        + col.min(stones_per_side - 1)]
+                })
+            }
+            TerrainKind::Gaps => {
+                let gap = 0.1 + 0.4 * difficulty;
+                let platform = 1.5 - 0.7 * difficulty;
+                let period = platform + gap;
+                Box::new(move |x, _| {
+                    let distance = x.abs() - START_HALF_SIZE;
+                    if distance > 0.0 && distance % period < gap {
+                        HOLE_DEPTH
+                    } else {
+                        0.0
+                    }
+                })
+            }
+        };
+
+        let heights = (0..points)
+            .flat_map(|row| (0..points).map(move |col| (row, col)))
+            .map(|(row, col)| {
+                height(
+                    -self.size / 2.0 + col as f32 * step,
+                    -self.size / 2.0 + row as f32 * step,
+                )
+            })
+            .collect();
+
+        Ok(Shape::HeightField {
+            heights,
+            rows: points,
+            cols: points,
+            x_length: self.size,
+            z_length: self.size,
+        })
+    }
+
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        material: Handle<StandardMaterial>,
+        transform: Transform,
+        meshes: &mut ResMut<Assets<Mesh>>,
+    ) -> Result<Entity, String> {
+        let bundle = MeshPhysicBodyBundle::try_from(
+            RigidBody::Fixed,
+            self.shape()?,
+            material,
+            transform,
+            meshes,
+        )
+        .ok_or("Terrain could not be made into a mesh and a collider")?;
+        Ok(commands
+            .spawn((
+                bundle,
+                RayVisible::<GroupStatic>::default(),
+                Name::new(NAME),
+            ))
+            .id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const KINDS: [TerrainKind; 5] = [
+        TerrainKind::Rough,
+        TerrainKind::Stairs,
+        TerrainKind::Slope,
+        TerrainKind::SteppingStones,
+        TerrainKind::Gaps,
+    ];
+
+    fn heights(terrain: &Terrain) -> (Vec<f32>, usize, usize) {
+        match terrain.shape().unwrap() {
+            Shape::HeightField {
+                heights,
+                rows,
+                cols,
+                x_length,
+                z_length,
+            } => {
+                assert_eq!((x_length, z_length), (terrain.size, terrain.size));
+                (heights, rows, cols)
+            }
+            shape => panic!("Terrain should be a height field, got {:?}", shape),
+        }
+    }
+
+    #[test]
+    fn dimensions() {
+        for kind in KINDS {
+            let terrain = Terrain::new(kind, 0.5, 0)
+                .with_size(4.0)
+                .with_resolution(0.1);
+            let (heights, rows, cols) = heights(&terrain);
+            assert_eq!((rows, cols), (41, 41), "{:?}", kind);
+            assert_eq!(heights.len(), rows * cols, "{:?}", kind);
+            assert!(heights.iter().all(|h| h.is_finite()), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn flat_start_area() {
+        for kind in KINDS {
+            let terrain = Terrain::new(kind, 1.0, 3)
+                .with_size(6.0)
+                .with_resolution(0.1);
+            let (heights, rows, cols) = heights(&terrain);
+
+            // the points within 0.9 meters of the middle
+            for row in 21..=39 {
+                for col in 21..=39 {
+                    assert_eq!(heights[row * cols + col], 0.0, "{:?}", kind);
+                }
+            }
+            assert_eq!(rows, 61);
+
+            // and the terrain is not flat further out
+            assert!(heights.iter().any(|h| *h != 0.0), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_terrain() {
+        for kind in KINDS {
+            let terrain = Terrain::new(kind, 0.7, 42).with_size(6.0);
+            assert_eq!(heights(&terrain), heights(&terrain.clone()), "{:?}", kind);
+        }
+
+        let rough = Terrain::new(TerrainKind::Rough, 0.7, 42).with_size(6.0);
+        let other_seed = Terrain {
+            seed: 43,
+            ..rough.clone()
+        };
+        assert_ne!(heights(&rough), heights(&other_seed));
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let terrain = Terrain::new(TerrainKind::Rough, 0.5, 0);
+        for invalid in [
+            terrain.clone().with_size(0.0),
+            terrain.clone().with_size(-1.0),
+            terrain.clone().with_size(f32::INFINITY),
+            terrain.clone().with_resolution(0.0),
+            terrain.clone().with_resolution(-0.1),
+            terrain.clone().with_resolution(f32::NAN),
+            terrain.clone().with_size(1000.0).with_resolution(0.01),
+            Terrain {
+                difficulty: f32::NAN,
+                ..terrain.clone()
+            },
+        ] {
+            assert!(invalid.shape().is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -100,7 +100,7 @@ impl Wheely {
         wheel_material: Handle<StandardMaterial>,
         transform: Transform,
         meshes: &mut Assets<Mesh>,
-    ) {
+    ) -> Entity {
         let hh = BODY_HEIGHT / 2.0;
         let rh = BODY_RADIUS / 2.0 + 0.2;
         let wheel_offset = WHEEL_RADIUS / 4.0;
@@ -228,6 +228,8 @@ impl Wheely {
         ));
 
         Self::build_arm(body, commands, material, transform, meshes);
+
+        body
     }
 
     fn build_arm(
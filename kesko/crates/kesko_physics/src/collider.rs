@@ -1,14 +1,23 @@
 use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
 use fnv::FnvHashMap;
+use nalgebra::DMatrix;
+use serde::{Deserialize, Serialize};
 
 use kesko_types::resource::KeskoRes;
 
 use crate::rapier_extern::rapier::prelude as rapier;
-use crate::{event::collision::GenerateCollisionEvents, mass::Mass, rigid_body::RigidBodyHandle};
+use crate::{
+    event::collision::{GenerateCollisionEvents, GenerateContactForceEvents},
+    mass::Mass,
+    multibody::MultibodyRoot,
+    rigid_body::RigidBodyHandle,
+};
 
 pub type Entity2Collider = FnvHashMap<Entity, rapier::ColliderHandle>;
+type MeshBuffers = (Vec<rapier::Point<rapier::Real>>, Vec<[u32; 3]>);
 
-#[derive(Component)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub enum ColliderShape {
     Cuboid {
         x_half: rapier::Real,
@@ -34,6 +43,130 @@ pub enum ColliderShape {
         radius: rapier::Real,
         length: rapier::Real,
     },
+    /// Triangle mesh, best suited for fixed bodies since it has no volume
+    TriMesh {
+        vertices: Vec<rapier::Point<rapier::Real>>,
+        indices: Vec<[u32; 3]>,
+    },
+    /// Smallest convex shape containing all the points
+    ConvexHull {
+        points: Vec<rapier::Point<rapier::Real>>,
+    },
+    /// Approximates a concave mesh with a set of convex parts
+    ConvexDecomposition {
+        vertices: Vec<rapier::Point<rapier::Real>>,
+        indices: Vec<[u32; 3]>,
+    },
+    /// Grid of heights centered on the xz-plane, the heights are given row by row where rows go
+    /// along the z-axis and columns along the x-axis
+    HeightField {
+        heights: Vec<rapier::Real>,
+        rows: usize,
+        cols: usize,
+        x_length: rapier::Real,
+        z_length: rapier::Real,
+    },
+}
+
+impl ColliderShape {
+    /// The rapier shape, fails if no convex hull can be computed or the heights of a height field
+    /// don't make up a grid of at least two by two
+    pub fn shared_shape(&self) -> Option<rapier::SharedShape> {
+        let shape = match self {
+            ColliderShape::Cuboid {
+                x_half,
+                y_half,
+                z_half,
+            } => rapier::SharedShape::cuboid(*x_half, *y_half, *z_half),
+            ColliderShape::Sphere { radius } => rapier::SharedShape::ball(*radius),
+            ColliderShape::CapsuleX {
+                half_length,
+                radius,
+            } => rapier::SharedShape::capsule_x(*half_length, *radius),
+            ColliderShape::CapsuleY {
+                half_length,
+                radius,
+            } => rapier::SharedShape::capsule_y(*half_length, *radius),
+            ColliderShape::CapsuleZ {
+                half_length,
+                radius,
+            } => rapier::SharedShape::capsule_z(*half_length, *radius),
+            ColliderShape::Cylinder { radius, length } => {
+                rapier::SharedShape::cylinder(length / 2.0, *radius)
+            }
+            ColliderShape::TriMesh { vertices, indices } => {
+                rapier::SharedShape::trimesh(vertices.clone(), indices.clone())
+            }
+            ColliderShape::ConvexHull { points } => rapier::SharedShape::convex_hull(points)?,
+            ColliderShape::ConvexDecomposition { vertices, indices } => {
+                rapier::SharedShape::convex_decomposition(vertices, indices)
+            }
+            ColliderShape::HeightField {
+                heights,
+                rows,
+                cols,
+                x_length,
+                z_length,
+            } => {
+                if *rows < 2 || *cols < 2 || heights.len() != rows * cols {
+                    return None;
+                }
+                rapier::SharedShape::heightfield(
+                    DMatrix::from_row_slice(*rows, *cols, heights),
+                    rapier::Vector::new(*x_length, 1.0, *z_length),
+                )
+            }
+        };
+        Some(shape)
+    }
+
+    /// Triangle mesh collider from a mesh, returns None if the mesh is not a triangle list with positions
+    pub fn trimesh(mesh: &Mesh) -> Option<Self> {
+        let (vertices, indices) = mesh_buffers(mesh)?;
+        Some(Self::TriMesh { vertices, indices })
+    }
+
+    /// Convex hull collider from the vertices of a mesh
+    pub fn convex_hull(mesh: &Mesh) -> Option<Self> {
+        let (points, _) = mesh_buffers(mesh)?;
+        Some(Self::ConvexHull { points })
+    }
+
+    /// Convex decomposition collider from a mesh
+    pub fn convex_decomposition(mesh: &Mesh) -> Option<Self> {
+        let (vertices, indices) = mesh_buffers(mesh)?;
+        Some(Self::ConvexDecomposition { vertices, indices })
+    }
+}
+
+/// Gets the vertices and triangles of a mesh, meshes without indices are treated as one triangle per three vertices
+fn mesh_buffers(mesh: &Mesh) -> Option<MeshBuffers> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return None;
+    }
+
+    let VertexAttributeValues::Float32x3(positions) = mesh.attribute(Mesh::ATTRIBUTE_POSITION)?
+    else {
+        return None;
+    };
+    let vertices = positions
+        .iter()
+        .map(|[x, y, z]| {
+            rapier::Point::new(*x as rapier::Real, *y as rapier::Real, *z as rapier::Real)
+        })
+        .collect::<Vec<_>>();
+
+    let flat_indices = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|i| *i as u32).collect(),
+        Some(Indices::U32(indices)) => indices.clone(),
+        None => (0..vertices.len() as u32).collect::<Vec<_>>(),
+    };
+    let indices = flat_indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect();
+
+    Some((vertices, indices))
 }
 
 /// Component for setting the physical material properties for a collider
@@ -57,6 +190,50 @@ impl Default for ColliderPhysicalProperties {
     }
 }
 
+/// Component to make the collider a trigger volume, it detects other colliders without any contact
+/// forces and sends [`CollisionEvent::TriggerEntered`] and [`CollisionEvent::TriggerExited`] events
+///
+/// [`CollisionEvent::TriggerEntered`]: crate::event::collision::CollisionEvent::TriggerEntered
+/// [`CollisionEvent::TriggerExited`]: crate::event::collision::CollisionEvent::TriggerExited
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Sensor;
+
+/// Component for filtering which colliders can collide with each other.
+///
+/// Two colliders can only collide if each one is a member of a group in the filter of the other.
+/// When added to a multibody root the groups are used for all links that don't have their own.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollisionGroups {
+    /// Bitmask of the groups the collider is a member of
+    pub memberships: u32,
+    /// Bitmask of the groups the collider can collide with
+    pub filter: u32,
+}
+
+impl CollisionGroups {
+    pub fn new(memberships: u32, filter: u32) -> Self {
+        Self {
+            memberships,
+            filter,
+        }
+    }
+}
+
+impl Default for CollisionGroups {
+    fn default() -> Self {
+        Self::new(u32::MAX, u32::MAX)
+    }
+}
+
+impl From<CollisionGroups> for rapier::InteractionGroups {
+    fn from(groups: CollisionGroups) -> Self {
+        rapier::InteractionGroups::new(
+            rapier::Group::from_bits_truncate(groups.memberships),
+            rapier::Group::from_bits_truncate(groups.filter),
+        )
+    }
+}
+
 #[derive(Component)]
 pub(crate) struct ColliderHandle(rapier::ColliderHandle);
 
@@ -73,34 +250,29 @@ pub(crate) fn add_colliders(
             &RigidBodyHandle,
             Option<&ColliderPhysicalProperties>,
             Option<&GenerateCollisionEvents>,
+            Option<&GenerateContactForceEvents>,
+            Option<&Sensor>,
         ),
         Without<ColliderHandle>,
     >,
 ) {
-    for (entity, collider_shape, rigid_body_handle, physical_props, gen_events) in query.iter() {
-        let mut collider_builder = match collider_shape {
-            ColliderShape::Cuboid {
-                x_half,
-                y_half,
-                z_half,
-            } => rapier::ColliderBuilder::cuboid(*x_half, *y_half, *z_half),
-            ColliderShape::Sphere { radius } => rapier::ColliderBuilder::ball(*radius),
-            ColliderShape::CapsuleX {
-                half_length: half_height,
-                radius,
-            } => rapier::ColliderBuilder::capsule_x(*half_height, *radius),
-            ColliderShape::CapsuleY {
-                half_length: half_height,
-                radius,
-            } => rapier::ColliderBuilder::capsule_y(*half_height, *radius),
-            ColliderShape::CapsuleZ {
-                half_length: half_height,
-                radius,
-            } => rapier::ColliderBuilder::capsule_z(*half_height, *radius),
-            ColliderShape::Cylinder { radius, length } => {
-                rapier::ColliderBuilder::cylinder(length / 2.0, *radius)
-            }
+    for (
+        entity,
+        collider_shape,
+        rigid_body_handle,
+        physical_props,
+        gen_events,
+        gen_force_events,
+        sensor,
+    ) in query.iter()
+    {
+        let Some(shape) = collider_shape.shared_shape() else {
+            // remove the shape so the failing computation is not repeated every frame
+            error!("Failed to compute collider shape for entity {:?}, removing it", entity);
+            commands.entity(entity).remove::<ColliderShape>();
+            continue;
         };
+        let mut collider_builder = rapier::ColliderBuilder::new(shape);
 
         if let Some(physical_props) = physical_props {
             collider_builder = collider_builder
@@ -109,10 +281,24 @@ pub(crate) fn add_colliders(
                 .restitution(physical_props.restitution);
         }
 
-        if gen_events.is_some() {
+        let mut active_events = rapier::ActiveEvents::empty();
+        if sensor.is_some() {
+            // sensors should also detect kinematic bodies, but not fixed bodies they overlap with
+            collider_builder = collider_builder.sensor(true).active_collision_types(
+                rapier::ActiveCollisionTypes::default()
+                    | rapier::ActiveCollisionTypes::KINEMATIC_FIXED
+                    | rapier::ActiveCollisionTypes::KINEMATIC_KINEMATIC,
+            );
+        }
+        if gen_events.is_some() || sensor.is_some() {
+            active_events |= rapier::ActiveEvents::COLLISION_EVENTS;
+        }
+        if let Some(gen_force_events) = gen_force_events {
+            active_events |= rapier::ActiveEvents::CONTACT_FORCE_EVENTS;
             collider_builder =
-                collider_builder.active_events(rapier::ActiveEvents::COLLISION_EVENTS);
+                collider_builder.contact_force_event_threshold(gen_force_events.threshold);
         }
+        collider_builder = collider_builder.active_events(active_events);
 
         // store entity as user data
         collider_builder = collider_builder.user_data(entity.to_bits().into());
@@ -136,14 +322,47 @@ pub(crate) fn add_colliders(
     }
 }
 
+/// System that sets the collision groups of the colliders, runs after the multibodies are added
+/// so the groups of a root can be used for its links
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_collision_groups_system(
+    mut colliders: ResMut<KeskoRes<rapier::ColliderSet>>,
+    entity_collider_map: Res<KeskoRes<Entity2Collider>>,
+    query: Query<
+        (Entity, &CollisionGroups, Option<&MultibodyRoot>),
+        Or<(Changed<CollisionGroups>, Added<MultibodyRoot>)>,
+    >,
+    link_groups: Query<(), With<CollisionGroups>>,
+) {
+    for (entity, groups, root) in query.iter() {
+        let links = root
+            .into_iter()
+            .flat_map(|root| root.child_map.values())
+            .filter(|link| !link_groups.contains(**link));
+
+        for target in std::iter::once(&entity).chain(links) {
+            if let Some(collider) = entity_collider_map
+                .get(target)
+                .and_then(|handle| colliders.get_mut(*handle))
+            {
+                collider.set_collision_groups((*groups).into());
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use crate::collider::{
-        add_colliders, ColliderHandle, ColliderPhysicalProperties, ColliderShape, Entity2Collider,
+        add_colliders, ColliderHandle, ColliderPhysicalProperties, ColliderShape, CollisionGroups,
+        Entity2Collider,
     };
+    use crate::joint::fixed::FixedJoint;
     use crate::rapier_extern::rapier::prelude as rapier;
     use crate::rigid_body::{add_rigid_bodies, Body2Entity, Entity2Body, RigidBody};
+    use crate::scene_query::SceneQuery;
+    use crate::PhysicsPlugin;
     use bevy::prelude::*;
     use kesko_types::resource::KeskoRes;
 
@@ -259,4 +478,200 @@ mod tests {
         assert_eq!(collider.friction(), physical_properties.friction);
         assert_eq!(collider.restitution(), physical_properties.restitution);
     }
+
+    #[test]
+    fn mesh_colliders() {
+        let mut app = setup_app();
+
+        let mesh = Mesh::from(shape::Box::new(1.0, 2.0, 3.0));
+        let trimesh = app
+            .world
+            .spawn((
+                RigidBody::Fixed,
+                ColliderShape::trimesh(&mesh).unwrap(),
+                Transform::default(),
+            ))
+            .id();
+        let convex_hull = app
+            .world
+            .spawn((
+                RigidBody::Dynamic,
+                ColliderShape::convex_hull(&mesh).unwrap(),
+                Transform::default(),
+            ))
+            .id();
+        let convex_decomposition = app
+            .world
+            .spawn((
+                RigidBody::Dynamic,
+                ColliderShape::convex_decomposition(&mesh).unwrap(),
+                Transform::default(),
+            ))
+            .id();
+
+        app.update();
+        app.update();
+
+        let collider_map = app
+            .world
+            .get_resource::<KeskoRes<Entity2Collider>>()
+            .unwrap();
+        let collider_set = app
+            .world
+            .get_resource::<KeskoRes<rapier::ColliderSet>>()
+            .unwrap();
+        let collider = |entity| {
+            collider_set
+                .get(*collider_map.get(&entity).unwrap())
+                .unwrap()
+        };
+
+        let trimesh = collider(trimesh).shape().as_trimesh().unwrap();
+        assert_eq!(trimesh.num_triangles(), 12);
+        assert!(collider(convex_hull)
+            .shape()
+            .as_convex_polyhedron()
+            .is_some());
+        assert!(collider(convex_decomposition)
+            .shape()
+            .as_compound()
+            .is_some());
+    }
+
+    #[test]
+    fn height_field() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        // a bump in the middle of a flat 2x2 meters grid
+        let mut heights = vec![0.0; 9];
+        heights[4] = 1.0;
+        let shape = ColliderShape::HeightField {
+            heights,
+            rows: 3,
+            cols: 3,
+            x_length: 2.0,
+            z_length: 2.0,
+        };
+        assert!(shape.shared_shape().is_some());
+        let ground = app
+            .world
+            .spawn((TransformBundle::default(), RigidBody::Fixed, shape))
+            .id();
+        app.update();
+
+        let mut state = bevy::ecs::system::SystemState::<SceneQuery>::new(&mut app.world);
+        let scene_query = state.get(&app.world);
+        let height_at = |x, z| {
+            scene_query
+                .cast_ray(Vec3::new(x, 5.0, z), Vec3::NEG_Y, 10.0, default())
+                .map(|hit| {
+                    assert_eq!(hit.entity, ground);
+                    hit.point.y
+                })
+        };
+        assert!((height_at(0.0, 0.0).unwrap() - 1.0).abs() < 1e-4);
+        assert!((height_at(0.5, 0.0).unwrap() - 0.5).abs() < 1e-4);
+        assert!((height_at(0.0, -0.5).unwrap() - 0.5).abs() < 1e-4);
+        assert!(height_at(1.5, 0.0).is_none());
+
+        let wrong_size = ColliderShape::HeightField {
+            heights: vec![0.0; 5],
+            rows: 2,
+            cols: 3,
+            x_length: 1.0,
+            z_length: 1.0,
+        };
+        assert!(wrong_size.shared_shape().is_none());
+    }
+
+    #[test]
+    fn invalid_shape_is_removed() {
+        let mut app = setup_app();
+
+        let entity = app
+            .world
+            .spawn((
+                RigidBody::Fixed,
+                ColliderShape::HeightField {
+                    heights: vec![0.0; 5],
+                    rows: 2,
+                    cols: 3,
+                    x_length: 1.0,
+                    z_length: 1.0,
+                },
+                Transform::default(),
+            ))
+            .id();
+        app.update();
+        app.update();
+
+        assert!(app.world.get::<ColliderShape>(entity).is_none());
+        assert!(app.world.get::<ColliderHandle>(entity).is_none());
+        assert!(app
+            .world
+            .resource::<KeskoRes<rapier::ColliderSet>>()
+            .is_empty());
+    }
+
+    #[test]
+    fn mesh_collider_requires_triangles() {
+        let mesh = Mesh::new(bevy::render::mesh::PrimitiveTopology::LineList);
+        assert!(ColliderShape::trimesh(&mesh).is_none());
+    }
+
+    #[test]
+    fn collision_groups() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        let shape = || ColliderShape::Sphere { radius: 0.1 };
+        let root_groups = CollisionGroups::new(0b01, 0b10);
+        let link_groups = CollisionGroups::new(0b100, 0b100);
+
+        let root = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Dynamic,
+                shape(),
+                root_groups,
+            ))
+            .id();
+        let child = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Dynamic,
+                shape(),
+                FixedJoint::attach_to(root),
+            ))
+            .id();
+        let grandchild = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Dynamic,
+                shape(),
+                FixedJoint::attach_to(child),
+                link_groups,
+            ))
+            .id();
+
+        app.update();
+
+        let collider_map = app.world.resource::<KeskoRes<Entity2Collider>>();
+        let collider_set = app.world.resource::<KeskoRes<rapier::ColliderSet>>();
+        let groups = |entity| {
+            collider_set
+                .get(*collider_map.get(&entity).unwrap())
+                .unwrap()
+                .collision_groups()
+        };
+
+        // links without groups of their own get the groups of the root
+        assert_eq!(groups(root), root_groups.into());
+        assert_eq!(groups(child), root_groups.into());
+        assert_eq!(groups(grandchild), link_groups.into());
+    }
 }
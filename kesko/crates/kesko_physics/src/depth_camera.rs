@@ -0,0 +1,256 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    scene_query::{SceneQuery, SceneQueryFilter},
+    timestep::PhysicsTime,
+};
+
+/// Segmentation value of pixels where nothing was hit
+pub const BACKGROUND: u64 = u64::MAX;
+
+/// Component for a pinhole camera that renders depth and segmentation images by casting one ray
+/// per pixel against the colliders, so it works without a GPU.
+///
+/// The camera looks along the negative z-axis of its rotation relative to the body, like the
+/// Bevy cameras. Rays start at the near plane and colliders beyond the far plane are not seen.
+#[derive(Component, Debug, Clone)]
+pub struct DepthCamera {
+    pub width: u32,
+    pub height: u32,
+    /// Vertical field of view in radians
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+    /// Position of the camera relative to the body
+    pub offset: Vec3,
+    /// Rotation of the camera relative to the body
+    pub rotation: Quat,
+    /// Images per second of simulated time, renders every frame when None
+    pub update_rate: Option<f32>,
+    depth: Vec<f32>,
+    segmentation: Vec<u64>,
+    since_update: f32,
+}
+
+impl DepthCamera {
+    pub fn new(width: u32, height: u32, fov: f32) -> Self {
+        Self {
+            width,
+            height,
+            fov,
+            near: 0.05,
+            far: 50.0,
+            offset: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            update_rate: None,
+            depth: Vec::new(),
+            segmentation: Vec::new(),
+            since_update: 0.0,
+        }
+    }
+
+    pub fn with_clipping(mut self, near: f32, far: f32) -> Self {
+        self.near = near;
+        self.far = far;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Vec3) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_update_rate(mut self, update_rate: f32) -> Self {
+        self.update_rate = Some(update_rate);
+        self
+    }
+
+    /// Distance along the view direction for each pixel, row major from the top left corner.
+    /// Pixels where nothing was hit have the far distance, empty until the first image.
+    pub fn depth(&self) -> &[f32] {
+        &self.depth
+    }
+
+    /// Bits of the entity seen in each pixel, [`BACKGROUND`] where nothing was hit
+    pub fn segmentation(&self) -> &[u64] {
+        &self.segmentation
+    }
+
+    /// Latest image in a form that can be sent outside of Kesko
+    pub fn image(&self, entity: Entity, name: String) -> DepthImage {
+        DepthImage {
+            name,
+            entity,
+            width: self.width,
+            height: self.height,
+            depth: self.depth.iter().flat_map(|d| d.to_le_bytes()).collect(),
+            segmentation: self
+                .segmentation
+                .iter()
+                .flat_map(|s| s.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    /// Direction of the ray through the center of each pixel in the camera frame, scaled so the
+    /// view direction component is one
+    fn pixel_directions(&self) -> impl Iterator<Item = Vec3> + '_ {
+        let half_height = (self.fov / 2.0).tan();
+        let half_width = half_height * self.width as f32 / self.height as f32;
+
+        (0..self.height).flat_map(move |row| {
+            let y = half_height * (1.0 - 2.0 * (row as f32 + 0.5) / self.height as f32);
+            (0..self.width).map(move |col| {
+                let x = half_width * (2.0 * (col as f32 + 0.5) / self.width as f32 - 1.0);
+                Vec3::new(x, y, -1.0)
+            })
+        })
+    }
+
+    /// If a new image should be rendered after `dt` seconds of simulated time
+    fn should_update(&mut self, dt: f32) -> bool {
+        self.since_update += dt;
+        match self.update_rate {
+            Some(rate) if rate > 0.0 && !self.depth.is_empty() => {
+                let period = 1.0 / rate;
+                if self.since_update < period {
+                    return false;
+                }
+                self.since_update %= period;
+                true
+            }
+            _ => {
+                self.since_update = 0.0;
+                true
+            }
+        }
+    }
+}
+
+/// Image of a [`DepthCamera`], the pixels are stored as little endian bytes to be sent as binary
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DepthImage {
+    /// Name of the camera entity, or its index if it has no name
+    pub name: String,
+    pub entity: Entity,
+    pub width: u32,
+    pub height: u32,
+    /// f32 for each pixel
+    #[serde(with = "serde_bytes")]
+    pub depth: Vec<u8>,
+    /// u64 for each pixel
+    #[serde(with = "serde_bytes")]
+    pub segmentation: Vec<u8>,
+}
+
+/// System that renders the images of the depth cameras after the physics step
+pub(crate) fn update_depth_cameras_system(
+    physics_time: Res<PhysicsTime>,
+    scene_query: SceneQuery,
+    mut query: Query<(Entity, &Transform, &mut DepthCamera)>,
+) {
+    for (entity, transform, mut camera) in query.iter_mut() {
+        if !camera.should_update(physics_time.delta()) {
+            continue;
+        }
+
+        // the body the camera is on is not seen
+        let filter = SceneQueryFilter {
+            exclude_sensors: true,
+            exclude_body: Some(entity),
+            ..default()
+        };
+        let origin = transform.transform_point(camera.offset);
+        let rotation = transform.rotation * camera.rotation;
+        let (near, far) = (camera.near, camera.far);
+
+        let (depth, segmentation) = camera
+            .pixel_directions()
+            .map(|direction| {
+                // the length of the direction converts between depth and distance along the ray
+                let length = direction.length();
+                let world_direction = rotation * direction;
+                scene_query
+                    .cast_ray(
+                        origin + world_direction * near,
+                        world_direction,
+                        (far - near) * length,
+                        filter,
+                    )
+                    .map_or((far, BACKGROUND), |hit| {
+                        (near + hit.distance / length, hit.entity.to_bits())
+                    })
+            })
+            .unzip();
+
+        camera.depth = depth;
+        camera.segmentation = segmentation;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::{collider::ColliderShape, rigid_body::RigidBody, PhysicsPlugin};
+
+    #[test]
+    fn render_depth_image() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::ZERO,
+            ..default()
+        });
+
+        // wall covering the left half of the image, 4 meters in front of the camera
+        let wall = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(-5.0, 0.0, -4.5)),
+                RigidBody::Fixed,
+                ColliderShape::Cuboid {
+                    x_half: 5.0,
+                    y_half: 5.0,
+                    z_half: 0.5,
+                },
+            ))
+            .id();
+        let camera_entity = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Fixed,
+                ColliderShape::Sphere { radius: 0.2 },
+                DepthCamera::new(4, 2, FRAC_PI_2).with_clipping(0.1, 10.0),
+            ))
+            .id();
+        app.update();
+
+        let camera = app.world.get::<DepthCamera>(camera_entity).unwrap();
+        assert_eq!(camera.depth().len(), 8);
+        for row in 0..2 {
+            for col in 0..4 {
+                let i = row * 4 + col;
+                if col < 2 {
+                    assert!((camera.depth()[i] - 4.0).abs() < 1e-4);
+                    assert_eq!(camera.segmentation()[i], wall.to_bits());
+                } else {
+                    assert_eq!(camera.depth()[i], 10.0);
+                    assert_eq!(camera.segmentation()[i], BACKGROUND);
+                }
+            }
+        }
+
+        let image = camera.image(camera_entity, "camera".to_owned());
+        assert_eq!(image.depth.len(), 8 * 4);
+        assert_eq!(image.segmentation.len(), 8 * 8);
+    }
+}
@@ -10,9 +10,14 @@ use kesko_types::resource::KeskoRes;
 
 use crate::rapier_extern::rapier::prelude as rapier;
 use crate::{
+    collider::ColliderShape,
+    force::ForcePoint,
+    force_field::{Drag, ForceFields},
     joint::JointInfo,
     multibody::MultibodyRoot,
+    randomization::RandomizationConfig,
     rigid_body::{Entity2Body, RigidBodyHandle},
+    scene_query::{RayHit, SceneQueryFilter},
     PhysicState,
 };
 
@@ -23,6 +28,56 @@ pub enum PhysicRequestEvent {
     TogglePhysics,
     DespawnBody(u64),
     DespawnAll,
+    SaveSnapshot,
+    RestoreSnapshot(u64),
+    /// Frees the memory of a snapshot that is not needed anymore
+    DeleteSnapshot(u64),
+    /// Casts a ray against all colliders, answered with [`PhysicResponseEvent::RaycastHit`]
+    Raycast {
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: rapier::Real,
+        filter: SceneQueryFilter,
+    },
+    /// Finds all colliders overlapping a shape, answered with [`PhysicResponseEvent::Overlaps`]
+    Overlap {
+        shape: ColliderShape,
+        transform: Transform,
+        filter: SceneQueryFilter,
+    },
+    /// Replaces the config used to randomize the physical parameters
+    SetRandomization(RandomizationConfig),
+    /// Samples new physical parameters for all bodies, joints and the world
+    Randomize,
+    /// Replaces the force from earlier requests on a body, or on the link with the given name of a
+    /// multibody, other forces on the body are kept. The force acts at the center of mass if no
+    /// point is given.
+    ApplyForce {
+        id: u64,
+        link: Option<String>,
+        force: Vec3,
+        torque: Vec3,
+        point: Option<ForcePoint>,
+        /// If the force keeps acting until it is replaced, otherwise only during the physics steps of
+        /// the next frame, which is `steps * substeps` pipeline steps
+        persistent: bool,
+    },
+    /// Applies an impulse to a body, or to the link with the given name of a multibody
+    ApplyImpulse {
+        id: u64,
+        link: Option<String>,
+        impulse: Vec3,
+        torque: Vec3,
+        point: Option<ForcePoint>,
+    },
+    /// Replaces the wind and water volumes
+    SetForceFields(ForceFields),
+    /// Sets the drag of a body, or of the link with the given name of a multibody
+    SetDrag {
+        id: u64,
+        link: Option<String>,
+        drag: Drag,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Event)]
@@ -41,6 +96,16 @@ pub enum PhysicResponseEvent {
         id: u64,
         name: String,
     },
+    SnapshotSaved(u64),
+    SnapshotRestored(u64),
+    SnapshotDeleted(u64),
+    /// Saving, restoring or deleting a snapshot failed, `id` is the requested snapshot if any
+    SnapshotFailed {
+        id: Option<u64>,
+        error: String,
+    },
+    RaycastHit(Option<RayHit>),
+    Overlaps(Vec<Entity>),
 }
 
 #[allow(clippy::type_complexity, clippy::too_many_arguments)]
@@ -126,6 +191,18 @@ pub(crate) fn handle_events(
                 });
                 response_events.send(PhysicResponseEvent::DespawnedAllBodies);
             }
+            // handled by the snapshot, scene query, randomization, force and force field systems
+            PhysicRequestEvent::SaveSnapshot
+            | PhysicRequestEvent::RestoreSnapshot(_)
+            | PhysicRequestEvent::DeleteSnapshot(_)
+            | PhysicRequestEvent::Raycast { .. }
+            | PhysicRequestEvent::Overlap { .. }
+            | PhysicRequestEvent::SetRandomization(_)
+            | PhysicRequestEvent::Randomize
+            | PhysicRequestEvent::ApplyForce { .. }
+            | PhysicRequestEvent::ApplyImpulse { .. }
+            | PhysicRequestEvent::SetForceFields(_)
+            | PhysicRequestEvent::SetDrag { .. } => {}
         }
     }
 }
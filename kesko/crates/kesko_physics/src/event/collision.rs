@@ -1,18 +1,49 @@
 use bevy::prelude::*;
+use fnv::FnvHashMap;
 use serde::{Deserialize, Serialize};
 
 use kesko_types::resource::KeskoRes;
 
+use crate::conversions::IntoBevy;
+use crate::multibody::{MultibodyChild, MultibodyRoot};
 use crate::rapier_extern::rapier;
 
 /// Component to indicate if an entity should generate collision events
 #[derive(Component)]
 pub struct GenerateCollisionEvents;
 
+/// Component to indicate if an entity should generate contact force events
+#[derive(Component, Clone, Copy, Default)]
+pub struct GenerateContactForceEvents {
+    /// Events are only sent when the total force magnitude is above the threshold
+    pub threshold: rapier::math::Real,
+    /// Include the contact points, normals and impulses in the events
+    pub contacts: bool,
+}
+
+impl GenerateContactForceEvents {
+    pub fn with_threshold(threshold: rapier::math::Real) -> Self {
+        Self {
+            threshold,
+            ..default()
+        }
+    }
+
+    pub fn with_contacts(mut self) -> Self {
+        self.contacts = true;
+        self
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Event)]
 pub enum CollisionEvent {
     CollisionStarted(CollisionData),
     CollisionStopped(CollisionData),
+    ContactForce(ContactForceEvent),
+    /// A collider entered the volume of a [`Sensor`](crate::collider::Sensor)
+    TriggerEntered(TriggerData),
+    /// A collider left the volume of a [`Sensor`](crate::collider::Sensor)
+    TriggerExited(TriggerData),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,11 +53,163 @@ pub struct CollisionData {
     pub flag: rapier::geometry::CollisionEventFlags,
 }
 
+/// A collider entering or leaving a sensor, with the names of the body if it is part of a multibody
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TriggerData {
+    pub sensor: Entity,
+    pub entity: Entity,
+    /// Root of the multibody the entity belongs to
+    pub root: Option<Entity>,
+    /// Name of the multibody, the same name as in the multibody states
+    pub root_name: Option<String>,
+    /// Name of the link, the same name as in the joint states
+    pub link_name: Option<String>,
+}
+
+impl TriggerData {
+    fn new(sensor: Entity, entity: Entity, bodies: &Query<BodyNames>) -> Self {
+        let Ok((name, root, child)) = bodies.get(entity) else {
+            return Self {
+                sensor,
+                entity,
+                root: None,
+                root_name: None,
+                link_name: None,
+            };
+        };
+
+        let root_entity = match (root, child) {
+            (Some(_), _) => Some(entity),
+            (None, Some(child)) => Some(child.root),
+            (None, None) => None,
+        };
+        let root_name = root_entity
+            .and_then(|root| bodies.get(root).ok())
+            .and_then(|(_, root, _)| root.map(|root| root.name.clone()));
+        let link_name = root_entity.map(|_| match name {
+            Some(name) => name.to_string(),
+            None => entity.index().to_string(),
+        });
+
+        Self {
+            sensor,
+            entity,
+            root: root_entity,
+            root_name,
+            link_name,
+        }
+    }
+}
+
+/// Name and multibody components used to describe the entity in a trigger event
+type BodyNames = (
+    Option<&'static Name>,
+    Option<&'static MultibodyRoot>,
+    Option<&'static MultibodyChild>,
+);
+
+/// Contact forces between two entities, averaged over the physics steps of the frame where the force
+/// was above the threshold
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContactForceEvent {
+    pub entity1: Entity,
+    pub entity2: Entity,
+    /// Sum of the contact forces applied on `entity2`, `entity1` gets the opposite force
+    pub total_force: Vec3,
+    pub total_force_magnitude: rapier::math::Real,
+    /// Direction of the largest force of a single contact manifold during the frame, pointing from
+    /// `entity1` to `entity2`
+    pub max_force_direction: Vec3,
+    pub max_force_magnitude: rapier::math::Real,
+    /// Contacts of the last step, only included if one of the entities asked for it in
+    /// [`GenerateContactForceEvents`]
+    pub contacts: Option<Vec<ContactPoint>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContactPoint {
+    /// Contact point on `entity1` in world space
+    pub point: Vec3,
+    /// Contact normal in world space, pointing from `entity1` to `entity2`
+    pub normal: Vec3,
+    /// Distance between the entities at the contact, negative if penetrating
+    pub distance: rapier::math::Real,
+    /// Impulse along the normal applied by the solver
+    pub impulse: rapier::math::Real,
+    /// Friction impulse along the two tangents
+    pub tangent_impulse: Vec2,
+}
+
+impl ContactForceEvent {
+    fn from_contact_pair(
+        dt: rapier::math::Real,
+        colliders: &rapier::geometry::ColliderSet,
+        pair: &rapier::geometry::ContactPair,
+    ) -> Option<Self> {
+        let collider1 = colliders.get(pair.collider1)?;
+        let collider2 = colliders.get(pair.collider2)?;
+
+        let total_force = pair.total_impulse() / dt;
+        let (max_impulse, max_direction) = pair.max_impulse();
+
+        let mut contacts = Vec::new();
+        for manifold in pair.manifolds.iter() {
+            // contacts are expressed in the frame of the sub-shape for compound shapes
+            let position = match manifold.subshape_pos1 {
+                Some(subshape_pos) => collider1.position() * subshape_pos,
+                None => *collider1.position(),
+            };
+            for contact in manifold.points.iter() {
+                contacts.push(ContactPoint {
+                    point: (position * contact.local_p1).coords.into_bevy(),
+                    normal: manifold.data.normal.into_bevy(),
+                    distance: contact.dist,
+                    impulse: contact.data.impulse,
+                    tangent_impulse: Vec2::new(
+                        contact.data.tangent_impulse.x as f32,
+                        contact.data.tangent_impulse.y as f32,
+                    ),
+                });
+            }
+        }
+
+        Some(Self {
+            entity1: Entity::from_bits(collider1.user_data as u64),
+            entity2: Entity::from_bits(collider2.user_data as u64),
+            total_force: total_force.into_bevy(),
+            total_force_magnitude: pair.total_impulse_magnitude() / dt,
+            max_force_direction: max_direction.into_bevy(),
+            max_force_magnitude: max_impulse / dt,
+            contacts: Some(contacts),
+        })
+    }
+
+    /// Adds the forces of a later step, the average is taken by [`Self::average`]
+    fn add_step(&mut self, step: Self) {
+        self.total_force += step.total_force;
+        self.total_force_magnitude += step.total_force_magnitude;
+        if step.max_force_magnitude > self.max_force_magnitude {
+            self.max_force_direction = step.max_force_direction;
+            self.max_force_magnitude = step.max_force_magnitude;
+        }
+        self.contacts = step.contacts;
+    }
+
+    fn average(&mut self, steps: usize) {
+        self.total_force /= steps as f32;
+        self.total_force_magnitude /= steps as rapier::math::Real;
+    }
+}
+
 // Responsible for fetching collision events from Rapier and propagate them to Bevy
 #[derive(Resource)]
 pub(crate) struct CollisionEventHandler {
     collision_send: crossbeam::channel::Sender<rapier::geometry::CollisionEvent>,
     collision_recv: crossbeam::channel::Receiver<rapier::geometry::CollisionEvent>,
+    contact_force_send: crossbeam::channel::Sender<ContactForceEvent>,
+    contact_force_recv: crossbeam::channel::Receiver<ContactForceEvent>,
+    /// Colliders inside a sensor, kept so the exit can be sent after one of the colliders is removed
+    triggers: FnvHashMap<[rapier::geometry::ColliderHandle; 2], TriggerData>,
 }
 
 impl rapier::pipeline::EventHandler for CollisionEventHandler {
@@ -43,34 +226,63 @@ impl rapier::pipeline::EventHandler for CollisionEventHandler {
         }
     }
 
+    /// builds the contact force events, called for each substep the force is above the threshold
     fn handle_contact_force_event(
         &self,
-        _dt: rapier::math::Real,
+        dt: rapier::math::Real,
         _bodies: &rapier::dynamics::RigidBodySet,
-        _colliders: &rapier::geometry::ColliderSet,
-        _contact_pair: &rapier::geometry::ContactPair,
+        colliders: &rapier::geometry::ColliderSet,
+        contact_pair: &rapier::geometry::ContactPair,
         _total_force_magnitude: rapier::math::Real,
     ) {
-        todo!("Implement when needed");
+        let Some(event) = ContactForceEvent::from_contact_pair(dt, colliders, contact_pair) else {
+            return;
+        };
+        if let Err(e) = self.contact_force_send.send(event) {
+            error!("Failed to propagate contact force event: {e}");
+        }
     }
 }
 
 impl CollisionEventHandler {
     pub(crate) fn new() -> Self {
         let (send, recv) = crossbeam::channel::unbounded();
+        let (contact_force_send, contact_force_recv) = crossbeam::channel::unbounded();
         Self {
             collision_send: send,
             collision_recv: recv,
+            contact_force_send,
+            contact_force_recv,
+            triggers: FnvHashMap::default(),
         }
     }
 
     /// Propagate collision events from Rapier to Bevys event system
     fn send_events(
-        &self,
+        &mut self,
         event_writer: &mut EventWriter<CollisionEvent>,
         colliders: &rapier::geometry::ColliderSet,
+        query: &Query<&GenerateContactForceEvents>,
+        bodies: &Query<BodyNames>,
     ) {
         while let Ok(event) = self.collision_recv.try_recv() {
+            if event.sensor() {
+                let pair = [event.collider1(), event.collider2()];
+                if event.started() {
+                    if let Some(trigger) = Self::trigger_data(&event, colliders, bodies) {
+                        self.triggers.insert(pair, trigger.clone());
+                        event_writer.send(CollisionEvent::TriggerEntered(trigger));
+                    }
+                } else if let Some(trigger) = self
+                    .triggers
+                    .remove(&pair)
+                    .or_else(|| Self::trigger_data(&event, colliders, bodies))
+                {
+                    event_writer.send(CollisionEvent::TriggerExited(trigger));
+                }
+                continue;
+            }
+
             match event {
                 rapier::geometry::CollisionEvent::Started(handle1, handle2, flag) => {
                     if let (Some(coll1), Some(coll2)) =
@@ -96,6 +308,55 @@ impl CollisionEventHandler {
                 }
             }
         }
+
+        // one event per pair and frame, the force events are sent for each substep
+        let mut pair_events: Vec<(ContactForceEvent, usize)> = Vec::new();
+        let mut pair_index = FnvHashMap::<[Entity; 2], usize>::default();
+        while let Ok(event) = self.contact_force_recv.try_recv() {
+            match pair_index.get(&[event.entity1, event.entity2]) {
+                Some(&index) => {
+                    let (pair_event, steps) = &mut pair_events[index];
+                    pair_event.add_step(event);
+                    *steps += 1;
+                }
+                None => {
+                    pair_index.insert([event.entity1, event.entity2], pair_events.len());
+                    pair_events.push((event, 1));
+                }
+            }
+        }
+
+        for (mut event, steps) in pair_events {
+            event.average(steps);
+            let with_contacts = [event.entity1, event.entity2]
+                .iter()
+                .any(|entity| matches!(query.get(*entity), Ok(gen_events) if gen_events.contacts));
+            if !with_contacts {
+                event.contacts = None;
+            }
+            event_writer.send(CollisionEvent::ContactForce(event));
+        }
+    }
+
+    /// The sensor is the first collider if both of them are sensors
+    fn trigger_data(
+        event: &rapier::geometry::CollisionEvent,
+        colliders: &rapier::geometry::ColliderSet,
+        bodies: &Query<BodyNames>,
+    ) -> Option<TriggerData> {
+        let coll1 = colliders.get(event.collider1())?;
+        let coll2 = colliders.get(event.collider2())?;
+        let (sensor, other) = if coll1.is_sensor() {
+            (coll1, coll2)
+        } else {
+            (coll2, coll1)
+        };
+
+        Some(TriggerData::new(
+            Entity::from_bits(sensor.user_data as u64),
+            Entity::from_bits(other.user_data as u64),
+            bodies,
+        ))
     }
 }
 
@@ -103,8 +364,284 @@ impl CollisionEventHandler {
 /// Should be executed after the rapier pipeline step in order to capture the latest events
 pub(crate) fn send_collision_events_system(
     colliders: Res<KeskoRes<rapier::geometry::ColliderSet>>,
-    collision_event_manager: Res<CollisionEventHandler>,
+    mut collision_event_manager: ResMut<CollisionEventHandler>,
     mut event_writer: EventWriter<CollisionEvent>,
+    query: Query<&GenerateContactForceEvents>,
+    bodies: Query<BodyNames>,
 ) {
-    collision_event_manager.send_events(&mut event_writer, &colliders);
+    collision_event_manager.send_events(&mut event_writer, &colliders, &query, &bodies);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        collider::{ColliderShape, Sensor},
+        joint::fixed::FixedJoint,
+        mass::Mass,
+        rigid_body::RigidBody,
+        PhysicsPlugin,
+    };
+
+    fn spawn_ball(app: &mut App, x: f32, gen_events: GenerateContactForceEvents) -> Entity {
+        app.world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(x, 1.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.5 },
+                gen_events,
+            ))
+            .id()
+    }
+
+    #[test]
+    fn contact_force_events() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            ..default()
+        });
+
+        app.world.spawn((
+            TransformBundle::default(),
+            RigidBody::Fixed,
+            ColliderShape::Cuboid {
+                x_half: 10.0,
+                y_half: 0.5,
+                z_half: 10.0,
+            },
+        ));
+        let ball = spawn_ball(
+            &mut app,
+            -2.0,
+            GenerateContactForceEvents::with_threshold(0.0).with_contacts(),
+        );
+        let ball_no_contacts = spawn_ball(&mut app, 2.0, GenerateContactForceEvents::default());
+
+        let mut events = Vec::new();
+        for _ in 0..60 {
+            app.update();
+            events = app
+                .world
+                .resource::<Events<CollisionEvent>>()
+                .iter_current_update_events()
+                .filter_map(|event| match event {
+                    CollisionEvent::ContactForce(event) => Some(event.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+        }
+
+        let find = |entity: Entity| {
+            events
+                .iter()
+                .find(|event| event.entity1 == entity || event.entity2 == entity)
+                .expect("No contact force event")
+        };
+
+        // resting on the ground, the ground carries the weight of the ball
+        let event = find(ball);
+        let force = if event.entity2 == ball {
+            event.total_force
+        } else {
+            -event.total_force
+        };
+        let weight = app.world.get::<Mass>(ball).unwrap().val * 9.81;
+        assert!((force - Vec3::new(0.0, weight, 0.0)).length() < 0.05 * weight);
+
+        let contacts = event.contacts.as_ref().expect("No contacts");
+        assert!(!contacts.is_empty());
+        for contact in contacts {
+            assert!(contact.impulse > 0.0);
+            assert!(contact.normal.y.abs() > 0.99);
+        }
+
+        assert!(find(ball_no_contacts).contacts.is_none());
+    }
+
+    #[test]
+    fn one_contact_force_event_per_frame() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            substeps: 4,
+            ..default()
+        });
+
+        app.world.spawn((
+            TransformBundle::default(),
+            RigidBody::Fixed,
+            ColliderShape::Cuboid {
+                x_half: 10.0,
+                y_half: 0.5,
+                z_half: 10.0,
+            },
+        ));
+        let ball = spawn_ball(&mut app, 0.0, GenerateContactForceEvents::default());
+
+        let mut events = Vec::new();
+        for _ in 0..60 {
+            app.update();
+            events = app
+                .world
+                .resource::<Events<CollisionEvent>>()
+                .iter_current_update_events()
+                .filter_map(|event| match event {
+                    CollisionEvent::ContactForce(event) => Some(event.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+        }
+
+        // the substeps are averaged into a single event that still carries the weight of the ball
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        let force = if event.entity2 == ball {
+            event.total_force
+        } else {
+            -event.total_force
+        };
+        let weight = app.world.get::<Mass>(ball).unwrap().val * 9.81;
+        assert!((force - Vec3::new(0.0, weight, 0.0)).length() < 0.05 * weight);
+    }
+
+    #[test]
+    fn trigger_events() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            ..default()
+        });
+
+        let sensor = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Fixed,
+                ColliderShape::Cuboid {
+                    x_half: 2.0,
+                    y_half: 0.5,
+                    z_half: 2.0,
+                },
+                Sensor,
+            ))
+            .id();
+
+        let root = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 2.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.1 },
+                Name::new("body"),
+            ))
+            .id();
+        let foot = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 1.5, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.1 },
+                FixedJoint::attach_to(root).with_parent_anchor(Transform::from_xyz(0.0, -0.5, 0.0)),
+                Name::new("foot"),
+            ))
+            .id();
+
+        let mut events = Vec::new();
+        for _ in 0..120 {
+            app.update();
+            events.extend(
+                app.world
+                    .resource::<Events<CollisionEvent>>()
+                    .iter_current_update_events()
+                    .cloned(),
+            );
+        }
+
+        // the sensor has no contact forces so the body falls through it
+        assert!(app.world.get::<Transform>(root).unwrap().translation.y < -0.5);
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, CollisionEvent::CollisionStarted(_))));
+
+        let entered = events
+            .iter()
+            .find_map(|event| match event {
+                CollisionEvent::TriggerEntered(data) if data.entity == foot => Some(data),
+                _ => None,
+            })
+            .expect("No trigger entered event");
+        assert_eq!(entered.sensor, sensor);
+        assert_eq!(entered.root, Some(root));
+        assert_eq!(entered.root_name, Some(format!("body-{}", root.index())));
+        assert_eq!(entered.link_name.as_deref(), Some("foot"));
+
+        for entity in [root, foot] {
+            assert!(events.iter().any(|event| matches!(
+                event,
+                CollisionEvent::TriggerExited(data) if data.entity == entity
+            )));
+        }
+    }
+
+    #[test]
+    fn trigger_exited_on_removal() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        let sensor = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Fixed,
+                ColliderShape::Cuboid {
+                    x_half: 2.0,
+                    y_half: 2.0,
+                    z_half: 2.0,
+                },
+                Sensor,
+            ))
+            .id();
+        let body = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.1 },
+                Name::new("body"),
+            ))
+            .id();
+
+        let mut events = Vec::new();
+        let mut run = |app: &mut App, updates: usize| {
+            for _ in 0..updates {
+                app.update();
+                events.extend(
+                    app.world
+                        .resource::<Events<CollisionEvent>>()
+                        .iter_current_update_events()
+                        .cloned(),
+                );
+            }
+        };
+        run(&mut app, 5);
+        app.world
+            .send_event(crate::event::PhysicRequestEvent::DespawnBody(
+                body.to_bits(),
+            ));
+        run(&mut app, 5);
+
+        // the collider is gone when the exit is reported, so the data is from when it entered
+        let exited = events
+            .iter()
+            .find_map(|event| match event {
+                CollisionEvent::TriggerExited(data) => Some(data),
+                _ => None,
+            })
+            .expect("No trigger exited event");
+        assert_eq!(exited.sensor, sensor);
+        assert_eq!(exited.entity, body);
+        assert!(app.world.get_entity(body).is_none());
+    }
 }
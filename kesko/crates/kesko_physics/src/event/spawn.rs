@@ -4,7 +4,9 @@ use bevy::prelude::*;
 
 use crate::{
     event::PhysicResponseEvent,
-    joint::{prismatic::PrismaticJoint, revolute::RevoluteJoint, JointInfo},
+    joint::{
+        prismatic::PrismaticJoint, revolute::RevoluteJoint, spherical::SphericalJoint, JointInfo,
+    },
     multibody::MultibodyRoot,
     rigid_body::RigidBody,
 };
@@ -14,6 +16,7 @@ pub(crate) fn send_spawned_events(
     bodies: Query<(Entity, Option<&Name>, Option<&MultibodyRoot>), Added<RigidBody>>,
     revolute_joints: Query<&RevoluteJoint>,
     prismatic_joints: Query<&PrismaticJoint>,
+    spherical_joints: Query<&SphericalJoint>,
 ) {
     for (entity, name, root) in bodies.iter() {
         if let Some(root) = root {
@@ -40,6 +43,19 @@ pub(crate) fn send_spawned_events(
                             stiffness: joint.stiffness,
                             max_motor_force: joint.max_motor_force,
                         })
+                    } else if let Ok(joint) = spherical_joints.get(*e) {
+                        Some(JointInfo::Spherical {
+                            name: name.clone(),
+                            x_limits: joint.x_ang_limit,
+                            y_limits: joint.y_ang_limit,
+                            z_limits: joint.z_ang_limit,
+                            damping: Vec3::new(joint.x_damping, joint.y_damping, joint.z_damping),
+                            stiffness: Vec3::new(
+                                joint.x_stiffness,
+                                joint.y_stiffness,
+                                joint.z_stiffness,
+                            ),
+                        })
                     } else {
                         None
                     };
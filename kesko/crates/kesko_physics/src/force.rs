@@ -1,47 +1,457 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use kesko_types::resource::KeskoRes;
 
 use crate::rapier_extern::rapier::prelude as rapier;
-use crate::{conversions::IntoRapier, rigid_body::RigidBodyHandle};
+use crate::{
+    conversions::{IntoBevy, IntoRapier},
+    event::PhysicRequestEvent,
+    impulse::Impulse,
+    multibody::MultibodyRoot,
+    rigid_body::RigidBodyHandle,
+};
+
+/// Point where a force or impulse acts on a body
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ForcePoint {
+    /// Point in world coordinates
+    World(Vec3),
+    /// Point relative to the body
+    Local(Vec3),
+}
+
+/// Force, or impulse, in world frame acting on a point of the body
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PointForce {
+    pub force: Vec3,
+    pub point: ForcePoint,
+}
+
+impl PointForce {
+    pub fn at_world_point(force: Vec3, point: Vec3) -> Self {
+        Self {
+            force,
+            point: ForcePoint::World(point),
+        }
+    }
+
+    pub fn at_local_point(force: Vec3, point: Vec3) -> Self {
+        Self {
+            force,
+            point: ForcePoint::Local(point),
+        }
+    }
+}
+
+/// Total force and torque around the center of mass of the body from forces at the center of
+/// mass, torques and forces at points
+pub(crate) fn total_force_and_torque(
+    body: &rapier::RigidBody,
+    force: Vec3,
+    torque: Vec3,
+    point_forces: &[PointForce],
+) -> (Vec3, Vec3) {
+    let center_of_mass = body.center_of_mass().coords.into_bevy();
+    let (translation, rotation) = body.position().into_bevy();
+
+    point_forces
+        .iter()
+        .fold((force, torque), |(force, torque), point_force| {
+            let point = match point_force.point {
+                ForcePoint::World(point) => point,
+                ForcePoint::Local(point) => translation + rotation * point,
+            };
+            (
+                force + point_force.force,
+                torque + (point - center_of_mass).cross(point_force.force),
+            )
+        })
+}
 
 /// Component to apply force to a rigid body
-#[derive(Component)]
+#[derive(Component, Debug, Clone)]
 pub struct Force {
     /// Force vector
     pub vec: Vec3,
-    /// If the forces should be reset before applying force
+    /// Torque in world frame
+    pub torque: Vec3,
+    /// Forces acting on points of the body instead of the center of mass, persistent forces at
+    /// local points follow the body as it moves
+    pub point_forces: Vec<PointForce>,
+    /// If all forces on the body should be reset before applying force, otherwise only the force
+    /// this component applied before is replaced
     pub reset_forces: bool,
+    /// If the force keeps acting until the component is changed, otherwise it only acts during
+    /// the physics steps of the next frame
+    pub persistent: bool,
+    /// Force and torque this component added to the body
+    applied: Option<(Vec3, Vec3)>,
 }
 
 impl Force {
+    /// Force that only acts during the physics steps of the next frame
+    pub fn one_shot(vec: Vec3) -> Self {
+        Self {
+            vec,
+            persistent: false,
+            ..default()
+        }
+    }
+
+    pub fn with_torque(mut self, torque: Vec3) -> Self {
+        self.torque = torque;
+        self
+    }
+
+    pub fn with_point_force(mut self, point_force: PointForce) -> Self {
+        self.point_forces.push(point_force);
+        self
+    }
+
     pub fn reset(&mut self) {
         self.vec = Vec3::ZERO;
+        self.torque = Vec3::ZERO;
+        self.point_forces.clear();
         self.reset_forces = true;
     }
+
+    /// If the force has to be converted to world frame again when the body moves
+    fn follows_body(&self) -> bool {
+        self.persistent
+            && self
+                .point_forces
+                .iter()
+                .any(|point_force| matches!(point_force.point, ForcePoint::Local(_)))
+    }
 }
 
 impl Default for Force {
     fn default() -> Self {
         Self {
             vec: Vec3::ZERO,
+            torque: Vec3::ZERO,
+            point_forces: Vec::new(),
             reset_forces: true,
+            persistent: true,
+            applied: None,
         }
     }
 }
 
-/// System to apply a force to a rigid body
-#[allow(clippy::type_complexity)]
+/// System to apply a force to a rigid body.
+///
+/// One shot forces are removed the frame after they are applied, the physics steps run in
+/// between. Persistent forces at local points are updated every frame to follow the body.
 pub(crate) fn update_force_system(
     mut rigid_bodies: ResMut<KeskoRes<rapier::RigidBodySet>>,
-    changed_force: Query<(&RigidBodyHandle, &Force), (Changed<Force>, With<RigidBodyHandle>)>,
+    mut query: Query<(&RigidBodyHandle, &mut Force)>,
 ) {
-    for (body_handle, force) in changed_force.iter() {
-        if let Some(body) = rigid_bodies.get_mut(body_handle.0) {
-            if force.reset_forces {
-                body.reset_forces(true);
+    for (body_handle, mut force) in query.iter_mut() {
+        let changed = force.is_changed();
+        if !changed && !force.follows_body() && (force.persistent || force.applied.is_none()) {
+            continue;
+        }
+        let Some(body) = rigid_bodies.get_mut(body_handle.0) else {
+            continue;
+        };
+
+        // take away what this component added before, a reset removes it together with the rest
+        let applied = force.bypass_change_detection().applied.take();
+        if changed && force.reset_forces {
+            body.reset_forces(true);
+            body.reset_torques(true);
+        } else if let Some((applied_force, applied_torque)) = applied {
+            body.add_force((-applied_force).into_rapier(), true);
+            body.add_torque((-applied_torque).into_rapier(), true);
+        }
+
+        if !changed && !force.persistent {
+            continue;
+        }
+        let (total_force, total_torque) =
+            total_force_and_torque(body, force.vec, force.torque, &force.point_forces);
+        body.add_force(total_force.into_rapier(), true);
+        body.add_torque(total_torque.into_rapier(), true);
+        force.bypass_change_detection().applied = Some((total_force, total_torque));
+    }
+}
+
+/// Rigid bodies, with the multibody root if they are one
+pub(crate) type BodyQuery<'w, 's> =
+    Query<'w, 's, Option<&'static MultibodyRoot>, With<RigidBodyHandle>>;
+
+/// The body with the id, or the link with the name if the id is of a multibody. None if there is
+/// no such rigid body, since ids from clients can be stale.
+pub(crate) fn target_entity(id: u64, link: &Option<String>, bodies: &BodyQuery) -> Option<Entity> {
+    let entity = Entity::from_bits(id);
+    let root = bodies.get(entity).ok()?;
+    let target = match link {
+        None => entity,
+        Some(link) => {
+            let root = root?;
+            if root.name == *link {
+                entity
+            } else {
+                *root.child_map.get(link)?
             }
-            body.add_force(force.vec.into_rapier(), true);
         }
+    };
+    bodies.contains(target).then_some(target)
+}
+
+/// System that handles requests to apply forces and impulses to bodies
+pub(crate) fn handle_force_events(
+    mut commands: Commands,
+    mut request_events: EventReader<PhysicRequestEvent>,
+    bodies: BodyQuery,
+    mut forces: Query<&mut Force>,
+    mut impulses: Query<&mut Impulse>,
+) {
+    for event in request_events.iter() {
+        match event {
+            PhysicRequestEvent::ApplyForce {
+                id,
+                link,
+                force,
+                torque,
+                point,
+                persistent,
+            } => {
+                let Some(entity) = target_entity(*id, link, &bodies) else {
+                    error!("Could not find body {} with link {:?}", id, link);
+                    continue;
+                };
+
+                // the new force only replaces the force from earlier requests, other forces on
+                // the body like joint friction and force fields are kept
+                let (vec, point_forces) = match point {
+                    Some(point) => (
+                        Vec3::ZERO,
+                        vec![PointForce {
+                            force: *force,
+                            point: *point,
+                        }],
+                    ),
+                    None => (*force, Vec::new()),
+                };
+
+                match forces.get_mut(entity) {
+                    Ok(mut current) => {
+                        current.vec = vec;
+                        current.torque = *torque;
+                        current.point_forces = point_forces;
+                        current.reset_forces = false;
+                        current.persistent = *persistent;
+                    }
+                    Err(_) => {
+                        commands.entity(entity).insert(Force {
+                            vec,
+                            torque: *torque,
+                            point_forces,
+                            reset_forces: false,
+                            persistent: *persistent,
+                            applied: None,
+                        });
+                    }
+                }
+            }
+            PhysicRequestEvent::ApplyImpulse {
+                id,
+                link,
+                impulse,
+                torque,
+                point,
+            } => {
+                let Some(entity) = target_entity(*id, link, &bodies) else {
+                    error!("Could not find body {} with link {:?}", id, link);
+                    continue;
+                };
+
+                let mut new_impulse = Impulse {
+                    torque: *torque,
+                    ..default()
+                };
+                match point {
+                    Some(point) => new_impulse.point_impulses.push(PointForce {
+                        force: *impulse,
+                        point: *point,
+                    }),
+                    None => new_impulse.vec = *impulse,
+                }
+
+                match impulses.get_mut(entity) {
+                    Ok(mut current) => *current = new_impulse,
+                    Err(_) => {
+                        commands.entity(entity).insert(new_impulse);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{collider::ColliderShape, rigid_body::RigidBody, PhysicsPlugin};
+
+    fn setup_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::ZERO,
+            ..default()
+        });
+        let body = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.5 },
+                Force::default(),
+            ))
+            .id();
+        app.update();
+        (app, body)
+    }
+
+    fn velocities(app: &App, body: Entity) -> (Vec3, Vec3) {
+        let handle = app.world.get::<RigidBodyHandle>(body).unwrap().0;
+        let body = &app.world.resource::<KeskoRes<rapier::RigidBodySet>>()[handle];
+        (body.linvel().into_bevy(), body.angvel().into_bevy())
+    }
+
+    #[test]
+    fn force_at_point_gives_torque() {
+        let (mut app, body) = setup_app();
+
+        app.world.get_mut::<Force>(body).unwrap().point_forces =
+            vec![PointForce::at_local_point(Vec3::Z, Vec3::X)];
+        for _ in 0..3 {
+            app.update();
+        }
+
+        // pushing the side of the sphere forward turns it around the y-axis
+        let (linvel, angvel) = velocities(&app, body);
+        assert!(linvel.z > 0.0);
+        assert!(angvel.y < 0.0);
+        assert!(angvel.x.abs() < 1e-5 && angvel.z.abs() < 1e-5);
+    }
+
+    #[test]
+    fn one_shot_force() {
+        let (mut app, body) = setup_app();
+
+        *app.world.get_mut::<Force>(body).unwrap() = Force::one_shot(Vec3::X).with_torque(Vec3::Y);
+        for _ in 0..2 {
+            app.update();
+        }
+        let (linvel, angvel) = velocities(&app, body);
+        assert!(linvel.x > 0.0);
+        assert!(angvel.y > 0.0);
+
+        // the force was only applied for one step so the velocity stays the same
+        for _ in 0..3 {
+            app.update();
+        }
+        let (new_linvel, new_angvel) = velocities(&app, body);
+        assert!(new_linvel.abs_diff_eq(linvel, 1e-5));
+        assert!(new_angvel.abs_diff_eq(angvel, 1e-5));
+    }
+
+    #[test]
+    fn local_force_follows_body() {
+        let (mut app, body) = setup_app();
+        *app.world.get_mut::<Force>(body).unwrap() =
+            Force::default().with_point_force(PointForce::at_local_point(Vec3::Z, Vec3::X));
+        app.update();
+
+        let handle = app.world.get::<RigidBodyHandle>(body).unwrap().0;
+        let torque = |app: &App| {
+            app.world.resource::<KeskoRes<rapier::RigidBodySet>>()[handle]
+                .user_torque()
+                .into_bevy()
+        };
+        assert!(torque(&app).abs_diff_eq(-Vec3::Y, 1e-5));
+
+        // turned a quarter around the y-axis the point is behind the center and gives no torque
+        let rotation = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        app.world
+            .resource_mut::<KeskoRes<rapier::RigidBodySet>>()
+            .get_mut(handle)
+            .unwrap()
+            .set_position((Vec3::ZERO, rotation).into_rapier(), true);
+        app.update();
+        assert!(torque(&app).length() < 0.05);
+    }
+
+    #[test]
+    fn apply_force_keeps_other_forces() {
+        let (mut app, body) = setup_app();
+        let handle = app.world.get::<RigidBodyHandle>(body).unwrap().0;
+        app.world
+            .resource_mut::<KeskoRes<rapier::RigidBodySet>>()
+            .get_mut(handle)
+            .unwrap()
+            .add_force(Vec3::Y.into_rapier(), true);
+
+        for force in [Vec3::X, 2.0 * Vec3::X] {
+            app.world.send_event(PhysicRequestEvent::ApplyForce {
+                id: body.to_bits(),
+                link: None,
+                force,
+                torque: Vec3::ZERO,
+                point: None,
+                persistent: true,
+            });
+            app.update();
+            app.update();
+        }
+
+        let user_force = app.world.resource::<KeskoRes<rapier::RigidBodySet>>()[handle]
+            .user_force()
+            .into_bevy();
+        assert!(user_force.abs_diff_eq(Vec3::new(2.0, 1.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn unknown_body() {
+        let (mut app, body) = setup_app();
+        let root = app.world.spawn(TransformBundle::default()).id();
+        let despawned = app.world.spawn(TransformBundle::default()).id();
+        app.world.despawn(despawned);
+
+        let requests = [
+            (despawned.to_bits(), None),
+            (Entity::from_raw(1000).to_bits(), None),
+            // not a rigid body
+            (root.to_bits(), None),
+            // not a multibody
+            (body.to_bits(), Some("link".to_owned())),
+        ];
+        for (id, link) in requests {
+            app.world.send_event(PhysicRequestEvent::ApplyForce {
+                id,
+                link: link.clone(),
+                force: Vec3::X,
+                torque: Vec3::ZERO,
+                point: None,
+                persistent: true,
+            });
+            app.world.send_event(PhysicRequestEvent::ApplyImpulse {
+                id,
+                link,
+                impulse: Vec3::X,
+                torque: Vec3::ZERO,
+                point: None,
+            });
+        }
+        app.update();
+
+        assert!(app.world.get::<Force>(root).is_none());
+        assert!(app.world.get::<Impulse>(root).is_none());
+        assert_eq!(velocities(&app, body).0, Vec3::ZERO);
     }
 }
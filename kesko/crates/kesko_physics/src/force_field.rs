@@ -0,0 +1,488 @@
+use std::{collections::HashMap, f32::consts::TAU};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use kesko_types::resource::KeskoRes;
+
+use crate::rapier_extern::rapier::prelude as rapier;
+use crate::{
+    conversions::{IntoBevy, IntoRapier},
+    event::PhysicRequestEvent,
+    force::{target_entity, BodyQuery},
+    gravity::Gravity,
+    rigid_body::RigidBodyHandle,
+    timestep::PhysicsTime,
+};
+
+/// Drag coefficients, the drag force is `-(linear * v + quadratic * |v| * v)` where `v` is the
+/// velocity relative to the air or water
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Drag {
+    pub linear: f32,
+    /// For a body with cross section area `A` and drag coefficient `C` it is `0.5 * rho * C * A`
+    /// where `rho` is the density of the air, about 1.2 kg/m^3
+    #[serde(default)]
+    pub quadratic: f32,
+    /// Torque opposing the angular velocity, per rad/s
+    #[serde(default)]
+    pub angular: f32,
+}
+
+impl Drag {
+    pub fn new(linear: f32, quadratic: f32, angular: f32) -> Self {
+        Self {
+            linear,
+            quadratic,
+            angular,
+        }
+    }
+
+    fn force(&self, velocity: Vec3) -> Vec3 {
+        -(self.linear + self.quadratic * velocity.length()) * velocity
+    }
+
+    fn torque(&self, angular_velocity: Vec3) -> Vec3 {
+        -self.angular * angular_velocity
+    }
+}
+
+/// Wind acting on the bodies with [`Drag`], it gets stronger with height and has gusts that
+/// travel along the wind direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Wind {
+    /// Mean wind velocity at zero height
+    pub velocity: Vec3,
+    /// Relative change of the mean wind speed per meter of height
+    #[serde(default)]
+    pub height_gradient: f32,
+    /// Amplitude of the gusts added on top of the mean wind
+    #[serde(default)]
+    pub gust: Vec3,
+    /// Seconds between gusts at a point, there are no gusts when zero
+    #[serde(default)]
+    pub gust_period: f32,
+    /// Distance between gusts along the wind direction, gusts hit everywhere at once when zero
+    #[serde(default)]
+    pub gust_length: f32,
+}
+
+impl Wind {
+    pub fn new(velocity: Vec3) -> Self {
+        Self {
+            velocity,
+            ..default()
+        }
+    }
+
+    pub fn with_height_gradient(mut self, height_gradient: f32) -> Self {
+        self.height_gradient = height_gradient;
+        self
+    }
+
+    pub fn with_gusts(mut self, gust: Vec3, period: f32, length: f32) -> Self {
+        self.gust = gust;
+        self.gust_period = period;
+        self.gust_length = length;
+        self
+    }
+
+    /// Wind velocity at a point at `time` seconds of simulated time
+    pub fn velocity_at(&self, point: Vec3, time: f32) -> Vec3 {
+        let mean = self.velocity * (1.0 + self.height_gradient * point.y).max(0.0);
+        if self.gust_period <= 0.0 {
+            return mean;
+        }
+
+        let distance = if self.gust_length > 0.0 {
+            point.dot(self.velocity.normalize_or_zero()) / self.gust_length
+        } else {
+            0.0
+        };
+        mean + self.gust * (TAU * (time / self.gust_period - distance)).sin()
+    }
+}
+
+/// Box filled with water, bodies in it get buoyancy from the volume of their colliders below the
+/// surface and drag from the water.
+///
+/// The submerged part of a collider is approximated from its bounding box.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WaterVolume {
+    /// Lower corner of the box
+    pub min: Vec3,
+    /// Upper corner of the box, the surface is at its height
+    pub max: Vec3,
+    #[serde(default = "default_water_density")]
+    pub density: f32,
+    /// Velocity of the water
+    #[serde(default)]
+    pub current: Vec3,
+    /// Drag for each cubic meter of submerged volume
+    #[serde(default = "default_water_drag")]
+    pub drag: Drag,
+}
+
+fn default_water_density() -> f32 {
+    1000.0
+}
+
+/// Rough drag for bodies around ten centimeters in size
+fn default_water_drag() -> Drag {
+    Drag::new(100.0, 1000.0, 10.0)
+}
+
+impl WaterVolume {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self {
+            min,
+            max,
+            density: default_water_density(),
+            current: Vec3::ZERO,
+            drag: default_water_drag(),
+        }
+    }
+
+    pub fn with_density(mut self, density: f32) -> Self {
+        self.density = density;
+        self
+    }
+
+    pub fn with_current(mut self, current: Vec3) -> Self {
+        self.current = current;
+        self
+    }
+
+    pub fn with_drag(mut self, drag: Drag) -> Self {
+        self.drag = drag;
+        self
+    }
+
+    /// Fraction of the box from `min` to `max` that is in the water and the center of that part
+    fn submerged(&self, min: Vec3, max: Vec3) -> Option<(f32, Vec3)> {
+        let low = min.max(self.min);
+        let high = max.min(self.max);
+        if high.cmplt(low).any() {
+            return None;
+        }
+
+        // flat boxes are fully in the water along their flat axes
+        let extent = max - min;
+        let overlap = high - low;
+        let fraction = (0..3)
+            .map(|axis| {
+                if extent[axis] > 0.0 {
+                    overlap[axis] / extent[axis]
+                } else {
+                    1.0
+                }
+            })
+            .product::<f32>();
+        Some((fraction, (low + high) / 2.0))
+    }
+}
+
+/// Environmental forces acting on the bodies besides gravity
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ForceFields {
+    #[serde(default)]
+    pub wind: Wind,
+    #[serde(default)]
+    pub water: Vec<WaterVolume>,
+    /// Simulated time used for the wind gusts
+    #[serde(skip)]
+    time: f32,
+}
+
+impl ForceFields {
+    pub fn with_wind(mut self, wind: Wind) -> Self {
+        self.wind = wind;
+        self
+    }
+
+    pub fn with_water(mut self, water: WaterVolume) -> Self {
+        self.water.push(water);
+        self
+    }
+}
+
+/// Forces and torques added by the force fields for the current step, removed after the step so
+/// they don't add up with the forces set through [`crate::force::Force`]
+#[derive(Resource, Default)]
+pub(crate) struct AppliedFieldForces(HashMap<rapier::RigidBodyHandle, (Vec3, Vec3)>);
+
+/// System that adds the forces from the force fields right before the physics steps of the frame,
+/// the forces are computed once and act during all of them, see [`crate::timestep::TimestepMode`]
+pub(crate) fn apply_force_fields_system(
+    physics_time: Res<PhysicsTime>,
+    gravity: Res<Gravity>,
+    mut force_fields: ResMut<ForceFields>,
+    mut applied: ResMut<AppliedFieldForces>,
+    mut rigid_bodies: ResMut<KeskoRes<rapier::RigidBodySet>>,
+    colliders: Res<KeskoRes<rapier::ColliderSet>>,
+    drag_query: Query<(&RigidBodyHandle, &Drag)>,
+) {
+    let time = force_fields.time;
+    force_fields.time += physics_time.delta();
+
+    let mut forces = HashMap::<rapier::RigidBodyHandle, (Vec3, Vec3)>::new();
+    // submerged and total volume of the colliders of each body in the water
+    let mut volumes = HashMap::<rapier::RigidBodyHandle, (f32, f32)>::new();
+
+    if !force_fields.water.is_empty() {
+        for (_, collider) in colliders.iter() {
+            if collider.is_sensor() {
+                continue;
+            }
+            let Some(handle) = collider.parent() else {
+                continue;
+            };
+            let Some(body) = rigid_bodies.get(handle) else {
+                continue;
+            };
+            if !body.is_dynamic() {
+                continue;
+            }
+
+            let aabb = collider.compute_aabb();
+            let volume = collider.shape().mass_properties(1.0).mass();
+            let center_of_mass = body.center_of_mass().coords.into_bevy();
+            let (force, torque) = forces.entry(handle).or_default();
+            let (submerged_volume, total_volume) = volumes.entry(handle).or_default();
+            *total_volume += volume;
+
+            for water in force_fields.water.iter() {
+                let Some((fraction, center)) =
+                    water.submerged(aabb.mins.coords.into_bevy(), aabb.maxs.coords.into_bevy())
+                else {
+                    continue;
+                };
+                let submerged = fraction * volume;
+                *submerged_volume += submerged;
+
+                // buoyancy acts at the center of the submerged part, which rights floating bodies
+                let buoyancy = -*gravity.get() * water.density * submerged;
+                let velocity = body.linvel().into_bevy() - water.current;
+                *force += buoyancy + submerged * water.drag.force(velocity);
+                *torque += (center - center_of_mass).cross(buoyancy)
+                    + submerged * water.drag.torque(body.angvel().into_bevy());
+            }
+        }
+    }
+
+    for (handle, drag) in drag_query.iter() {
+        let Some(body) = rigid_bodies.get(handle.0) else {
+            continue;
+        };
+        // only the part of the body above the water feels the wind
+        let in_air = volumes
+            .get(&handle.0)
+            .filter(|(_, total)| *total > 0.0)
+            .map_or(1.0, |(submerged, total)| 1.0 - (submerged / total).min(1.0));
+
+        let center_of_mass = body.center_of_mass().coords.into_bevy();
+        let velocity =
+            body.linvel().into_bevy() - force_fields.wind.velocity_at(center_of_mass, time);
+        let (force, torque) = forces.entry(handle.0).or_default();
+        *force += in_air * drag.force(velocity);
+        *torque += in_air * drag.torque(body.angvel().into_bevy());
+    }
+
+    for (handle, (force, torque)) in forces {
+        if force == Vec3::ZERO && torque == Vec3::ZERO {
+            continue;
+        }
+        if let Some(body) = rigid_bodies.get_mut(handle) {
+            body.add_force(force.into_rapier(), true);
+            body.add_torque(torque.into_rapier(), true);
+            applied.0.insert(handle, (force, torque));
+        }
+    }
+}
+
+/// System that removes the forces from the force fields after the physics step
+pub(crate) fn remove_force_fields_system(
+    mut applied: ResMut<AppliedFieldForces>,
+    mut rigid_bodies: ResMut<KeskoRes<rapier::RigidBodySet>>,
+) {
+    for (handle, (force, torque)) in applied.0.drain() {
+        if let Some(body) = rigid_bodies.get_mut(handle) {
+            body.add_force((-force).into_rapier(), false);
+            body.add_torque((-torque).into_rapier(), false);
+        }
+    }
+}
+
+/// System that handles requests to change the force fields and the drag of bodies
+pub(crate) fn handle_force_field_events(
+    mut commands: Commands,
+    mut request_events: EventReader<PhysicRequestEvent>,
+    mut force_fields: ResMut<ForceFields>,
+    bodies: BodyQuery,
+) {
+    for event in request_events.iter() {
+        match event {
+            PhysicRequestEvent::SetForceFields(new_force_fields) => {
+                // keep the time so the gusts continue where they were
+                let time = force_fields.time;
+                *force_fields = new_force_fields.clone();
+                force_fields.time = time;
+            }
+            PhysicRequestEvent::SetDrag { id, link, drag } => {
+                match target_entity(*id, link, &bodies) {
+                    Some(entity) => {
+                        commands.entity(entity).insert(*drag);
+                    }
+                    None => error!("Could not find body {} with link {:?}", id, link),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        collider::ColliderShape, rigid_body::RigidBody, timestep::TimestepMode, PhysicsPlugin,
+    };
+
+    #[test]
+    fn wind_velocity() {
+        let wind = Wind::new(Vec3::X).with_height_gradient(0.5);
+        assert_eq!(
+            wind.velocity_at(Vec3::new(3.0, 2.0, 0.0), 0.0),
+            2.0 * Vec3::X
+        );
+        assert_eq!(wind.velocity_at(Vec3::new(0.0, -4.0, 0.0), 0.0), Vec3::ZERO);
+
+        // a quarter period after the start the gust is at its strongest where the wind starts
+        let wind = Wind::new(Vec3::X).with_gusts(Vec3::X, 4.0, 10.0);
+        assert!(wind
+            .velocity_at(Vec3::ZERO, 1.0)
+            .abs_diff_eq(2.0 * Vec3::X, 1e-5));
+        assert!(wind
+            .velocity_at(Vec3::new(5.0, 0.0, 0.0), 1.0)
+            .abs_diff_eq(Vec3::ZERO, 1e-5));
+    }
+
+    #[test]
+    fn forces_are_held_during_the_frame() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::ZERO,
+            timestep_mode: TimestepMode::Fixed {
+                steps_per_update: 4,
+            },
+            ..default()
+        });
+        app.insert_resource(ForceFields::default().with_wind(Wind::new(Vec3::new(5.0, 0.0, 0.0))));
+
+        let body = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.5 },
+                Drag::new(1.0, 0.0, 0.0),
+            ))
+            .id();
+        app.update();
+
+        // the drag from the body at rest acts during all four steps, even though the body speeds
+        // up after the first one
+        let handle = app.world.get::<RigidBodyHandle>(body).unwrap().0;
+        let rigid_body = &app.world.resource::<KeskoRes<rapier::RigidBodySet>>()[handle];
+        let expected = 5.0 * 4.0 / 60.0 / rigid_body.mass();
+        assert!(
+            (rigid_body.linvel().x - expected).abs() < 1e-4,
+            "{:?}",
+            rigid_body.linvel()
+        );
+    }
+
+    #[test]
+    fn buoyancy_and_wind() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+        app.insert_resource(
+            ForceFields::default()
+                .with_wind(Wind::new(Vec3::new(5.0, 0.0, 0.0)))
+                .with_water(
+                    // the colliders have a density of one, so the water is made only a bit denser
+                    WaterVolume::new(Vec3::new(-5.0, -10.0, -5.0), Vec3::new(5.0, 0.0, 5.0))
+                        .with_density(2.0)
+                        .with_drag(Drag::default()),
+                ),
+        );
+
+        let underwater = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, -5.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.5 },
+            ))
+            .id();
+        let in_wind = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(20.0, 5.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.5 },
+                Drag::new(1.0, 0.0, 0.0),
+            ))
+            .id();
+
+        for _ in 0..5 {
+            app.update();
+        }
+
+        let velocity = |entity: Entity| {
+            let handle = app.world.get::<RigidBodyHandle>(entity).unwrap().0;
+            app.world.resource::<KeskoRes<rapier::RigidBodySet>>()[handle]
+                .linvel()
+                .into_bevy()
+        };
+
+        // the sphere is lighter than the water it displaces
+        assert!(velocity(underwater).y > 0.0);
+        let wind_velocity = velocity(in_wind);
+        assert!(wind_velocity.x > 0.0);
+        assert!(wind_velocity.y < 0.0);
+
+        // the forces are removed after the step
+        assert!(app.world.resource::<AppliedFieldForces>().0.is_empty());
+    }
+
+    #[test]
+    fn set_drag() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+        let body = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.5 },
+            ))
+            .id();
+        let despawned = app.world.spawn(TransformBundle::default()).id();
+        app.world.despawn(despawned);
+        app.update();
+
+        let drag = Drag::new(1.0, 0.5, 0.1);
+        for id in [body.to_bits(), despawned.to_bits()] {
+            app.world.send_event(PhysicRequestEvent::SetDrag {
+                id,
+                link: None,
+                drag,
+            });
+        }
+        app.update();
+
+        assert_eq!(app.world.get::<Drag>(body), Some(&drag));
+    }
+}
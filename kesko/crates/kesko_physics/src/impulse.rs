@@ -3,21 +3,131 @@ use bevy::prelude::*;
 use kesko_types::resource::KeskoRes;
 
 use crate::conversions::IntoRapier;
+use crate::force::{total_force_and_torque, PointForce};
 use crate::rapier_extern::rapier::prelude as rapier;
 use crate::rigid_body::RigidBodyHandle;
 
-#[derive(Component, Default)]
+/// Component to apply an impulse to a rigid body, the impulse is applied once each time the
+/// component is changed
+#[derive(Component, Debug, Clone, Default)]
 pub struct Impulse {
     pub vec: Vec3,
+    /// Angular impulse in world frame
+    pub torque: Vec3,
+    /// Impulses acting on points of the body instead of the center of mass
+    pub point_impulses: Vec<PointForce>,
+}
+
+/// Impulses on multibody links waiting for the next physics step.
+///
+/// The velocity of a link is computed from the joint velocities of the multibody, so changing the
+/// velocity of the rigid body directly has no effect. Instead the impulse is applied as a force
+/// that only acts during the first pipeline step of the next frame.
+#[derive(Resource, Default)]
+pub(crate) struct MultibodyImpulses(Vec<(rapier::RigidBodyHandle, Vec3, Vec3)>);
+
+impl MultibodyImpulses {
+    /// Adds the impulses as forces acting during a pipeline step of length `dt`
+    pub(crate) fn add_forces(&self, dt: f32, rigid_bodies: &mut rapier::RigidBodySet) {
+        for (handle, impulse, torque_impulse) in self.0.iter() {
+            if let Some(body) = rigid_bodies.get_mut(*handle) {
+                body.add_force((*impulse / dt).into_rapier(), true);
+                body.add_torque((*torque_impulse / dt).into_rapier(), true);
+            }
+        }
+    }
+
+    /// Removes the forces added by [`Self::add_forces`] after the step, the impulses are then done
+    pub(crate) fn remove_forces(&mut self, dt: f32, rigid_bodies: &mut rapier::RigidBodySet) {
+        for (handle, impulse, torque_impulse) in self.0.drain(..) {
+            if let Some(body) = rigid_bodies.get_mut(handle) {
+                body.add_force((-impulse / dt).into_rapier(), false);
+                body.add_torque((-torque_impulse / dt).into_rapier(), false);
+            }
+        }
+    }
 }
 
 pub(crate) fn update_impulse(
     mut rigid_bodies: ResMut<KeskoRes<rapier::RigidBodySet>>,
+    multibody_joints: Res<KeskoRes<rapier::MultibodyJointSet>>,
+    mut multibody_impulses: ResMut<MultibodyImpulses>,
     query: Query<(&RigidBodyHandle, &Impulse), Changed<Impulse>>,
 ) {
     for (body_handle, impulse) in query.iter() {
         if let Some(body) = rigid_bodies.get_mut(body_handle.0) {
-            body.apply_impulse(impulse.vec.into_rapier(), true);
+            let (linear, angular) =
+                total_force_and_torque(body, impulse.vec, impulse.torque, &impulse.point_impulses);
+            if multibody_joints.rigid_body_link(body_handle.0).is_some() {
+                multibody_impulses.0.push((body_handle.0, linear, angular));
+            } else {
+                body.apply_impulse(linear.into_rapier(), true);
+                body.apply_torque_impulse(angular.into_rapier(), true);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        collider::ColliderShape,
+        event::PhysicRequestEvent,
+        joint::{revolute::RevoluteJoint, KeskoAxis},
+        rigid_body::RigidBody,
+        PhysicsPlugin,
+    };
+
+    #[test]
+    fn impulse_on_multibody_link() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::ZERO,
+            ..default()
+        });
+
+        let root = app
+            .world
+            .spawn((TransformBundle::default(), RigidBody::Fixed))
+            .id();
+        let link = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, -1.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.1 },
+                Name::new("link"),
+                RevoluteJoint::attach_to(root)
+                    .with_parent_anchor(Transform::from_xyz(0.0, -1.0, 0.0))
+                    .with_axis(KeskoAxis::X),
+            ))
+            .id();
+        app.update();
+
+        app.world.send_event(PhysicRequestEvent::ApplyImpulse {
+            id: root.to_bits(),
+            link: Some("link".to_owned()),
+            impulse: Vec3::Z,
+            torque: Vec3::ZERO,
+            point: None,
+        });
+        for _ in 0..5 {
+            app.update();
         }
+
+        // pushing the link forward turns the joint
+        let position = app.world.get::<Transform>(link).unwrap().translation;
+        assert!(position.z > 0.0);
+        let joint = app.world.get::<RevoluteJoint>(link).unwrap();
+        assert!(joint.angular_velocity().abs() > 0.0);
+
+        // the impulse only acts once
+        assert!(app.world.resource::<MultibodyImpulses>().0.is_empty());
+        let handle = app.world.get::<RigidBodyHandle>(link).unwrap().0;
+        let user_force =
+            app.world.resource::<KeskoRes<rapier::RigidBodySet>>()[handle].user_force();
+        assert!(user_force.norm() < 1e-3);
     }
 }
@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+use rand::Rng;
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+
+use kesko_types::resource::KeskoRes;
+
+use crate::rapier_extern::rapier::prelude as rapier;
+use crate::{
+    conversions::IntoBevy, gravity::Gravity, rigid_body::RigidBodyHandle, timestep::PhysicsTime,
+};
+
+/// Noise added to each axis of a measurement
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NoiseModel {
+    /// Standard deviation of the white noise added to every measurement
+    pub noise: f32,
+    /// Constant offset of the measurement, changes over time with the drift
+    pub bias: Vec3,
+    /// Standard deviation of the bias random walk after one second
+    pub drift: f32,
+}
+
+impl NoiseModel {
+    pub fn new(noise: f32, bias: Vec3, drift: f32) -> Self {
+        Self { noise, bias, drift }
+    }
+
+    /// Drifts the bias `dt` seconds and returns the measurement with bias and noise added
+    fn apply(&mut self, value: Vec3, dt: f32, rng: &mut impl Rng) -> Vec3 {
+        self.bias += gaussian(rng) * self.drift * dt.sqrt();
+        value + self.bias + gaussian(rng) * self.noise
+    }
+}
+
+/// Measurement of an [`Imu`], the acceleration and angular velocity are in the frame of the body
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ImuReading {
+    /// Acceleration minus gravity, a body at rest measures the acceleration needed to hold it up
+    pub linear_acceleration: Vec3,
+    pub angular_velocity: Vec3,
+    /// Orientation of the body in the world
+    pub orientation: Quat,
+}
+
+impl Default for ImuReading {
+    fn default() -> Self {
+        Self {
+            linear_acceleration: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+            orientation: Quat::IDENTITY,
+        }
+    }
+}
+
+impl ImuReading {
+    /// Acceleration, angular velocity and orientation as one array, used for observations
+    pub fn to_array(&self) -> [f32; 10] {
+        let [ax, ay, az] = self.linear_acceleration.to_array();
+        let [wx, wy, wz] = self.angular_velocity.to_array();
+        let [x, y, z, w] = self.orientation.to_array();
+        [ax, ay, az, wx, wy, wz, x, y, z, w]
+    }
+}
+
+/// Component that simulates an inertial measurement unit on the body it is added to.
+///
+/// The measurements are updated after every physics step, the acceleration is computed from the
+/// change in velocity over the simulated time of the frame.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Imu {
+    pub accelerometer: NoiseModel,
+    pub gyroscope: NoiseModel,
+    /// Standard deviation in radians of the rotation error added to the orientation
+    pub orientation_noise: f32,
+    reading: ImuReading,
+    prev_velocity: Option<Vec3>,
+}
+
+impl Imu {
+    pub fn with_accelerometer(mut self, accelerometer: NoiseModel) -> Self {
+        self.accelerometer = accelerometer;
+        self
+    }
+
+    pub fn with_gyroscope(mut self, gyroscope: NoiseModel) -> Self {
+        self.gyroscope = gyroscope;
+        self
+    }
+
+    pub fn with_orientation_noise(mut self, orientation_noise: f32) -> Self {
+        self.orientation_noise = orientation_noise;
+        self
+    }
+
+    /// Latest measurement
+    pub fn reading(&self) -> ImuReading {
+        self.reading
+    }
+}
+
+/// Vector with each element drawn from a standard normal distribution
+fn gaussian(rng: &mut impl Rng) -> Vec3 {
+    Vec3::new(
+        rng.sample(StandardNormal),
+        rng.sample(StandardNormal),
+        rng.sample(StandardNormal),
+    )
+}
+
+/// System that updates the measurements of the imus after the physics step
+pub(crate) fn update_imu_system(
+    physics_time: Res<PhysicsTime>,
+    gravity: Res<Gravity>,
+    rigid_bodies: Res<KeskoRes<rapier::RigidBodySet>>,
+    mut query: Query<(&RigidBodyHandle, &mut Imu)>,
+) {
+    let dt = physics_time.delta();
+    if dt <= 0.0 {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+    for (handle, mut imu) in query.iter_mut() {
+        let Some(body) = rigid_bodies.get(handle.0) else {
+            continue;
+        };
+
+        let (_, rotation) = body.position().into_bevy();
+        let velocity = body.linvel().into_bevy();
+        let acceleration = imu
+            .prev_velocity
+            .map_or(Vec3::ZERO, |prev| (velocity - prev) / dt);
+        imu.prev_velocity = Some(velocity);
+
+        // measured in the frame of the body
+        let inverse = rotation.inverse();
+        let specific_force = inverse * (acceleration - *gravity.get());
+        let body_angvel = inverse * body.angvel().into_bevy();
+
+        let linear_acceleration = imu.accelerometer.apply(specific_force, dt, &mut rng);
+        let angular_velocity = imu.gyroscope.apply(body_angvel, dt, &mut rng);
+        let orientation_error = Quat::from_scaled_axis(gaussian(&mut rng) * imu.orientation_noise);
+
+        imu.reading = ImuReading {
+            linear_acceleration,
+            angular_velocity,
+            orientation: (rotation * orientation_error).normalize(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::{collider::ColliderShape, rigid_body::RigidBody, PhysicsPlugin};
+
+    fn reading(app: &App, entity: Entity) -> ImuReading {
+        app.world.get::<Imu>(entity).unwrap().reading()
+    }
+
+    #[test]
+    fn accelerometer_measures_gravity() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        let rotation = Quat::from_rotation_z(FRAC_PI_2);
+        let fixed = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_rotation(rotation)),
+                RigidBody::Fixed,
+                Imu::default(),
+            ))
+            .id();
+        let falling = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 10.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.5 },
+                Imu::default(),
+            ))
+            .id();
+
+        for _ in 0..3 {
+            app.update();
+        }
+
+        // the fixed body is rotated so its x-axis points up
+        let fixed_reading = reading(&app, fixed);
+        assert!(fixed_reading
+            .linear_acceleration
+            .abs_diff_eq(Vec3::new(9.81, 0.0, 0.0), 1e-3));
+        assert!(fixed_reading.orientation.abs_diff_eq(rotation, 1e-5));
+
+        // a body in free fall doesn't measure any acceleration
+        let falling_reading = reading(&app, falling);
+        assert!(falling_reading
+            .linear_acceleration
+            .abs_diff_eq(Vec3::ZERO, 1e-3));
+    }
+
+    #[test]
+    fn bias_and_drift() {
+        let mut rng = rand::thread_rng();
+
+        let mut model = NoiseModel::new(0.0, Vec3::ONE, 0.0);
+        assert_eq!(
+            model.apply(Vec3::X, 0.1, &mut rng),
+            Vec3::new(2.0, 1.0, 1.0)
+        );
+
+        let mut model = NoiseModel::new(0.0, Vec3::ZERO, 1.0);
+        model.apply(Vec3::ZERO, 0.1, &mut rng);
+        assert_ne!(model.bias, Vec3::ZERO);
+    }
+}
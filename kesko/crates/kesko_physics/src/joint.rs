@@ -1,3 +1,4 @@
+pub mod actuator;
 pub mod fixed;
 pub mod prismatic;
 pub mod revolute;
@@ -10,14 +11,25 @@ use serde::{Deserialize, Serialize};
 use kesko_types::resource::KeskoRes;
 
 use crate::conversions::IntoBevy;
+use crate::gravity::Gravity;
 use crate::rapier_extern::rapier::prelude as rapier;
 use crate::rigid_body::{Entity2Body, RigidBodyHandle};
+use crate::timestep::PhysicsTime;
 
+use self::actuator::Actuator;
 use self::prismatic::PrismaticJoint;
 use self::revolute::RevoluteJoint;
+use self::spherical::SphericalJoint;
 
 pub type Entity2JointHandle = FnvHashMap<Entity, rapier::MultibodyJointHandle>;
 
+/// How close to a limit a joint has to be to count as being at the limit
+const LIMIT_TOLERANCE: rapier::Real = 1e-3;
+
+/// The angular motors of impulse joints in Rapier compare `sin(angle / 2)` with `sin(target)`,
+/// scaling the target makes them end up at the same angle as the motors of multibody joints
+const IMPULSE_MOTOR_ANGLE_SCALE: rapier::Real = 0.5;
+
 /// trait for converting an axis into a unit vector
 pub(crate) trait AxisIntoVec {
     fn into_unitvec(self) -> rapier::UnitVector<rapier::Real>;
@@ -63,7 +75,6 @@ pub enum KeskoAxis {
 }
 
 /// used to send joint info outside Kesko
-/// TODO: This is a bit redundant when spherical joints is not available
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "lowercase", tag = "type")]
 pub enum JointInfo {
@@ -83,6 +94,15 @@ pub enum JointInfo {
         stiffness: rapier::Real,
         max_motor_force: rapier::Real,
     },
+    /// Limits, damping and stiffness are given for the x, y and z axis
+    Spherical {
+        name: String,
+        x_limits: Option<Vec2>,
+        y_limits: Option<Vec2>,
+        z_limits: Option<Vec2>,
+        damping: Vec3,
+        stiffness: Vec3,
+    },
 }
 
 /// used to send joint state outside Kesko
@@ -93,12 +113,189 @@ pub enum JointState {
         axis: KeskoAxis,
         angle: rapier::Real,
         angular_velocity: rapier::Real,
+        motor_torque: rapier::Real,
+        reaction_force: Vec3,
+        at_lower_limit: bool,
+        at_upper_limit: bool,
     },
     Prismatic {
         axis: KeskoAxis,
         position: rapier::Real,
         velocity: rapier::Real,
+        motor_force: rapier::Real,
+        reaction_force: Vec3,
+        at_lower_limit: bool,
+        at_upper_limit: bool,
     },
+    /// Angles and angular velocities around the x, y and z axis, `orientation` is the same
+    /// rotation as the angles
+    Spherical {
+        angles: Vec3,
+        orientation: Quat,
+        angular_velocities: Vec3,
+    },
+}
+
+impl JointState {
+    /// Position of the joint along each of its free axes
+    pub fn positions(&self) -> Vec<rapier::Real> {
+        match self {
+            JointState::Revolute { angle, .. } => vec![*angle],
+            JointState::Prismatic { position, .. } => vec![*position],
+            JointState::Spherical { angles, .. } => angles.to_array().to_vec(),
+        }
+    }
+
+    /// Velocity of the joint along each of its free axes
+    pub fn velocities(&self) -> Vec<rapier::Real> {
+        match self {
+            JointState::Revolute {
+                angular_velocity, ..
+            } => vec![*angular_velocity],
+            JointState::Prismatic { velocity, .. } => vec![*velocity],
+            JointState::Spherical {
+                angular_velocities, ..
+            } => angular_velocities.to_array().to_vec(),
+        }
+    }
+}
+
+/// Forces in a joint and if it is at one of its limits, updated after the physics steps of each frame.
+///
+/// Rapier does not give us the impulses of multibody joints, so the motor output is calculated from
+/// the spring-damper law of the motor and the reaction force from the change in momentum of the bodies
+/// below the joint together with the contact forces, both during the last pipeline step of the frame.
+/// Only normal contact forces are taken into account for the reaction force.
+/// For impulse joints the impulses from the solver are used directly.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JointForces {
+    /// Torque for revolute joints and force for prismatic joints, limited by the max motor force
+    pub motor: rapier::Real,
+    /// Force the joint applies on the child and the bodies below it, in world frame
+    pub reaction: Vec3,
+    pub at_lower_limit: bool,
+    pub at_upper_limit: bool,
+}
+
+impl JointForces {
+    /// Updates the forces from the joint link in the multibody, `position` and `velocity` are
+    /// the current position and velocity of the joint along its free axis and `dt` is the timestep
+    /// of the last pipeline step
+    #[allow(clippy::too_many_arguments)]
+    fn update(
+        &mut self,
+        multibody: &rapier::Multibody,
+        link_id: usize,
+        axis: rapier::JointAxis,
+        position: rapier::Real,
+        velocity: rapier::Real,
+        rigid_bodies: &rapier::RigidBodySet,
+        last_step_velocities: &LastStepVelocities,
+        contact_forces: &FnvHashMap<rapier::RigidBodyHandle, Vec3>,
+        gravity: Vec3,
+        dt: rapier::Real,
+    ) {
+        let Some(link) = multibody.link(link_id) else {
+            return;
+        };
+
+        self.motor = link.joint.data.motor(axis).map_or(0.0, |motor| {
+            let force = motor.stiffness * (motor.target_pos - position)
+                + motor.damping * (motor.target_vel - velocity);
+            force.clamp(-motor.max_force, motor.max_force)
+        });
+        self.update_limits(&link.joint.data, axis, position);
+
+        // sum up everything acting on the child and the bodies below it, the joint has to make up for the rest
+        let mut momentum_change = Vec3::ZERO;
+        let mut external_force = Vec3::ZERO;
+        for handle in multibody
+            .links()
+            .filter(|other| is_in_subtree(multibody, other.link_id(), link_id))
+            .map(|other| other.rigid_body_handle())
+        {
+            if let Some(body) = rigid_bodies.get(handle) {
+                // bodies added during the frame have no velocity from before the step
+                let Some(prev_velocity) = last_step_velocities.0.get(&handle) else {
+                    return;
+                };
+                let mass = body.mass() as f32;
+                momentum_change += mass * (body.linvel().into_bevy() - *prev_velocity);
+                external_force += mass * body.gravity_scale() as f32 * gravity;
+            }
+            if let Some(force) = contact_forces.get(&handle) {
+                external_force += *force;
+            }
+        }
+
+        self.reaction = momentum_change / dt - external_force;
+    }
+
+    /// Updates the forces from the impulses of an impulse joint, `dt` is the timestep of the solver
+    fn update_impulse(
+        &mut self,
+        joint: &rapier::ImpulseJoint,
+        axis: rapier::JointAxis,
+        position: rapier::Real,
+        rigid_bodies: &rapier::RigidBodySet,
+        dt: rapier::Real,
+    ) {
+        // the impulses are the ones applied on the parent, expressed in the joint frame on the parent
+        self.motor = joint
+            .data
+            .motor(axis)
+            .map_or(0.0, |motor| -motor.impulse / dt);
+        self.update_limits(&joint.data, axis, position);
+
+        if let Some(parent) = rigid_bodies.get(joint.body1) {
+            let frame = parent.position() * joint.data.local_frame1;
+            self.reaction = -(frame.rotation * joint.impulses.xyz() / dt).into_bevy();
+        }
+    }
+
+    fn update_limits(
+        &mut self,
+        joint: &rapier::GenericJoint,
+        axis: rapier::JointAxis,
+        position: rapier::Real,
+    ) {
+        (self.at_lower_limit, self.at_upper_limit) = match joint.limits(axis) {
+            Some(limits) => (
+                position <= limits.min + LIMIT_TOLERANCE,
+                position >= limits.max - LIMIT_TOLERANCE,
+            ),
+            None => (false, false),
+        };
+    }
+}
+
+/// Linear velocities of the dynamic bodies before the last pipeline step of the frame, so the change
+/// in momentum can be compared with the contact forces of that step
+#[derive(Resource, Default)]
+pub(crate) struct LastStepVelocities(FnvHashMap<rapier::RigidBodyHandle, Vec3>);
+
+impl LastStepVelocities {
+    pub(crate) fn record(&mut self, rigid_bodies: &rapier::RigidBodySet) {
+        self.0.clear();
+        self.0.extend(
+            rigid_bodies
+                .iter()
+                .filter(|(_, body)| body.is_dynamic())
+                .map(|(handle, body)| (handle, body.linvel().into_bevy())),
+        );
+    }
+}
+
+/// If the link is the root link or one of its descendants
+fn is_in_subtree(multibody: &rapier::Multibody, link_id: usize, root_id: usize) -> bool {
+    let mut current = Some(link_id);
+    while let Some(id) = current {
+        if id == root_id {
+            return true;
+        }
+        current = multibody.link(id).and_then(|link| link.parent_id());
+    }
+    false
 }
 
 /// Enum to indicate joint type
@@ -115,6 +312,27 @@ pub enum JointType {
 #[derive(Component, Debug)]
 pub struct MultibodyJointHandle(pub(crate) rapier::MultibodyJointHandle);
 
+/// Same as [`MultibodyJointHandle`] but for impulse joints
+#[derive(Component, Debug)]
+pub struct ImpulseJointHandle(pub(crate) rapier::ImpulseJointHandle);
+
+/// Component to add the joint of the entity as an impulse joint instead of a multibody joint.
+///
+/// Every body in a multibody can only have one parent, impulse joints don't have that restriction
+/// so they can be used to close kinematic loops. They are solved iteratively and can drift a bit apart.
+/// By default the joint connects the parent of the joint with the body of the entity, set `child`
+/// to spawn the joint on an entity of its own, e.g. when the body already has a joint.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct ImpulseJoint {
+    pub child: Option<Entity>,
+}
+
+impl ImpulseJoint {
+    pub fn with_child(child: Entity) -> Self {
+        Self { child: Some(child) }
+    }
+}
+
 /// System to add multibody joints between bodies
 #[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub(crate) fn add_multibody_joints(
@@ -124,19 +342,35 @@ pub(crate) fn add_multibody_joints(
     entity_body_map: Res<KeskoRes<Entity2Body>>,
     revolute_joints: Query<
         (Entity, &revolute::RevoluteJoint),
-        (With<RigidBodyHandle>, Without<MultibodyJointHandle>),
+        (
+            With<RigidBodyHandle>,
+            Without<MultibodyJointHandle>,
+            Without<ImpulseJoint>,
+        ),
     >,
     prismatic_joints: Query<
         (Entity, &prismatic::PrismaticJoint),
-        (With<RigidBodyHandle>, Without<MultibodyJointHandle>),
+        (
+            With<RigidBodyHandle>,
+            Without<MultibodyJointHandle>,
+            Without<ImpulseJoint>,
+        ),
     >,
     spherical_joints: Query<
         (Entity, &spherical::SphericalJoint),
-        (With<RigidBodyHandle>, Without<MultibodyJointHandle>),
+        (
+            With<RigidBodyHandle>,
+            Without<MultibodyJointHandle>,
+            Without<ImpulseJoint>,
+        ),
     >,
     fixed_joints: Query<
         (Entity, &fixed::FixedJoint),
-        (With<RigidBodyHandle>, Without<MultibodyJointHandle>),
+        (
+            With<RigidBodyHandle>,
+            Without<MultibodyJointHandle>,
+            Without<ImpulseJoint>,
+        ),
     >,
 ) {
     for (entity, joint) in revolute_joints.iter() {
@@ -201,36 +435,256 @@ pub(crate) fn add_multibody_joints(
     }
 }
 
-/// System that updates the joints positions and velocities
+/// System to add impulse joints between bodies, see [`ImpulseJoint`]
 #[allow(clippy::type_complexity)]
-pub(crate) fn update_joint_pos_system(
-    physics_params: Res<KeskoRes<rapier::IntegrationParameters>>,
-    multibody_joint_set: Res<KeskoRes<rapier::MultibodyJointSet>>,
-    mut revolute_joints: Query<
-        (&mut RevoluteJoint, &MultibodyJointHandle),
-        (With<MultibodyJointHandle>, With<RevoluteJoint>),
+pub(crate) fn add_impulse_joints(
+    mut commands: Commands,
+    mut impulse_joint_set: ResMut<KeskoRes<rapier::ImpulseJointSet>>,
+    entity_body_map: Res<KeskoRes<Entity2Body>>,
+    revolute_joints: Query<
+        (Entity, &revolute::RevoluteJoint, &ImpulseJoint),
+        Without<ImpulseJointHandle>,
     >,
-    mut prismatic_joints: Query<
-        (&mut PrismaticJoint, &MultibodyJointHandle),
-        (With<MultibodyJointHandle>, With<PrismaticJoint>),
+    prismatic_joints: Query<
+        (Entity, &prismatic::PrismaticJoint, &ImpulseJoint),
+        Without<ImpulseJointHandle>,
+    >,
+    spherical_joints: Query<
+        (Entity, &spherical::SphericalJoint, &ImpulseJoint),
+        Without<ImpulseJointHandle>,
     >,
+    fixed_joints: Query<(Entity, &fixed::FixedJoint, &ImpulseJoint), Without<ImpulseJointHandle>>,
+) {
+    let joints = revolute_joints
+        .iter()
+        .map(|(entity, joint, impulse)| {
+            (
+                entity,
+                joint.parent,
+                impulse,
+                rapier::GenericJoint::from(*joint),
+            )
+        })
+        .chain(prismatic_joints.iter().map(|(entity, joint, impulse)| {
+            (
+                entity,
+                joint.parent,
+                impulse,
+                rapier::GenericJoint::from(*joint),
+            )
+        }))
+        .chain(spherical_joints.iter().map(|(entity, joint, impulse)| {
+            (
+                entity,
+                joint.parent,
+                impulse,
+                rapier::GenericJoint::from(*joint),
+            )
+        }))
+        .chain(fixed_joints.iter().map(|(entity, joint, impulse)| {
+            (
+                entity,
+                joint.parent,
+                impulse,
+                rapier::GenericJoint::from(*joint),
+            )
+        }));
+
+    for (entity, parent, impulse, joint) in joints {
+        let child = impulse.child.unwrap_or(entity);
+        // the bodies might not have been added yet if the joint is on an entity of its own
+        let (Some(parent_body), Some(child_body)) =
+            (entity_body_map.get(&parent), entity_body_map.get(&child))
+        else {
+            continue;
+        };
+
+        let joint_handle = impulse_joint_set.insert(*parent_body, *child_body, joint, true);
+        commands
+            .entity(entity)
+            .insert(ImpulseJointHandle(joint_handle));
+    }
+}
+
+/// Handle of either a multibody or an impulse joint
+type JointHandles = AnyOf<(&'static MultibodyJointHandle, &'static ImpulseJointHandle)>;
+
+/// Transformation of the child body in the frame of the parent body for either kind of joint
+fn body_to_parent(
+    handles: (Option<&MultibodyJointHandle>, Option<&ImpulseJointHandle>),
+    multibody_joint_set: &rapier::MultibodyJointSet,
+    impulse_joint_set: &rapier::ImpulseJointSet,
+    rigid_bodies: &rapier::RigidBodySet,
+) -> Option<rapier::Isometry<rapier::Real>> {
+    match handles {
+        (Some(handle), _) => {
+            let (mb, link_index) = multibody_joint_set.get(handle.0)?;
+            Some(mb.link(link_index)?.joint().body_to_parent())
+        }
+        (None, Some(handle)) => {
+            let joint = impulse_joint_set.get(handle.0)?;
+            let parent = rigid_bodies.get(joint.body1)?;
+            let child = rigid_bodies.get(joint.body2)?;
+            Some(parent.position().inv_mul(child.position()))
+        }
+        (None, None) => None,
+    }
+}
+
+/// System that updates the joints positions and velocities
+pub(crate) fn update_joint_pos_system(
+    physics_time: Res<PhysicsTime>,
+    multibody_joint_set: Res<KeskoRes<rapier::MultibodyJointSet>>,
+    impulse_joint_set: Res<KeskoRes<rapier::ImpulseJointSet>>,
+    rigid_bodies: Res<KeskoRes<rapier::RigidBodySet>>,
+    mut revolute_joints: Query<(&mut RevoluteJoint, JointHandles)>,
+    mut prismatic_joints: Query<(&mut PrismaticJoint, JointHandles)>,
+    mut spherical_joints: Query<(&mut SphericalJoint, JointHandles)>,
 ) {
-    for (mut joint, handle) in revolute_joints.iter_mut() {
-        if let Some((mb, link_index)) = multibody_joint_set.get(handle.0) {
-            if let Some(link) = mb.link(link_index) {
-                // we have the joint transformation in the parent frame
-                let (_, rot) = link.joint().body_to_parent().into_bevy();
-                joint.update_rotation_angvel(rot, physics_params.dt);
+    // the velocities are calculated over all the steps of the frame, nothing moved if no steps were taken
+    let dt = physics_time.delta() as rapier::Real;
+    if dt == 0.0 {
+        return;
+    }
+
+    for (mut joint, handles) in revolute_joints.iter_mut() {
+        // we have the joint transformation in the parent frame
+        if let Some(transform) = body_to_parent(
+            handles,
+            &multibody_joint_set,
+            &impulse_joint_set,
+            &rigid_bodies,
+        ) {
+            let (_, rot) = transform.into_bevy();
+            joint.update_rotation_angvel(rot, dt);
+        }
+    }
+    for (mut joint, handles) in prismatic_joints.iter_mut() {
+        if let Some(transform) = body_to_parent(
+            handles,
+            &multibody_joint_set,
+            &impulse_joint_set,
+            &rigid_bodies,
+        ) {
+            let (translation, rotation) = transform.into_bevy();
+            joint.update_position_vel(translation, rotation, dt);
+        }
+    }
+    for (mut joint, handles) in spherical_joints.iter_mut() {
+        if let Some(transform) = body_to_parent(
+            handles,
+            &multibody_joint_set,
+            &impulse_joint_set,
+            &rigid_bodies,
+        ) {
+            let (_, rot) = transform.into_bevy();
+            joint.update_rotation_angvel(rot, dt);
+        }
+    }
+}
+
+/// System that updates the forces of the joints, see [`JointForces`]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_joint_forces_system(
+    physics_time: Res<PhysicsTime>,
+    gravity: Res<Gravity>,
+    integration_parameters: Res<KeskoRes<rapier::IntegrationParameters>>,
+    rigid_bodies: Res<KeskoRes<rapier::RigidBodySet>>,
+    last_step_velocities: Res<LastStepVelocities>,
+    colliders: Res<KeskoRes<rapier::ColliderSet>>,
+    narrow_phase: Res<KeskoRes<rapier::NarrowPhase>>,
+    multibody_joint_set: Res<KeskoRes<rapier::MultibodyJointSet>>,
+    impulse_joint_set: Res<KeskoRes<rapier::ImpulseJointSet>>,
+    mut revolute_joints: Query<(&mut RevoluteJoint, JointHandles)>,
+    mut prismatic_joints: Query<(&mut PrismaticJoint, JointHandles)>,
+) {
+    // nothing moved if no steps were taken, otherwise all forces are from the last pipeline step
+    if physics_time.delta() == 0.0 {
+        return;
+    }
+    let dt = integration_parameters.dt;
+
+    // contact forces from the last step, the normal points from the first to the second collider
+    let mut contact_forces = FnvHashMap::<rapier::RigidBodyHandle, Vec3>::default();
+    for pair in narrow_phase.contact_pairs() {
+        if !pair.has_any_active_contact {
+            continue;
+        }
+        let force: Vec3 = (pair.total_impulse() / dt).into_bevy();
+        let parents = [pair.collider1, pair.collider2]
+            .map(|handle| colliders.get(handle).and_then(|collider| collider.parent()));
+        if let Some(body) = parents[0] {
+            *contact_forces.entry(body).or_default() -= force;
+        }
+        if let Some(body) = parents[1] {
+            *contact_forces.entry(body).or_default() += force;
+        }
+    }
+
+    for (mut joint, handles) in revolute_joints.iter_mut() {
+        let (angle, angvel) = (joint.rotation(), joint.angular_velocity());
+        match handles {
+            (Some(handle), _) => {
+                if let Some((mb, link_id)) = multibody_joint_set.get(handle.0) {
+                    joint.forces.update(
+                        mb,
+                        link_id,
+                        rapier::JointAxis::AngX,
+                        angle,
+                        angvel,
+                        &rigid_bodies,
+                        &last_step_velocities,
+                        &contact_forces,
+                        *gravity.get(),
+                        dt,
+                    );
+                }
             }
+            (None, Some(handle)) => {
+                if let Some(impulse_joint) = impulse_joint_set.get(handle.0) {
+                    joint.forces.update_impulse(
+                        impulse_joint,
+                        rapier::JointAxis::AngX,
+                        angle,
+                        &rigid_bodies,
+                        dt,
+                    );
+                }
+            }
+            (None, None) => {}
         }
     }
-    for (mut joint, handle) in prismatic_joints.iter_mut() {
-        if let Some((mb, link_index)) = multibody_joint_set.get(handle.0) {
-            if let Some(link) = mb.link(link_index) {
-                // we have the joint transformation in the parent frame
-                let (translation, _) = link.joint().body_to_parent().into_bevy();
-                joint.update_position_vel(translation, physics_params.dt);
+    for (mut joint, handles) in prismatic_joints.iter_mut() {
+        let (position, velocity) = (joint.position(), joint.velocity());
+        match handles {
+            (Some(handle), _) => {
+                if let Some((mb, link_id)) = multibody_joint_set.get(handle.0) {
+                    joint.forces.update(
+                        mb,
+                        link_id,
+                        rapier::JointAxis::X,
+                        position,
+                        velocity,
+                        &rigid_bodies,
+                        &last_step_velocities,
+                        &contact_forces,
+                        *gravity.get(),
+                        dt,
+                    );
+                }
+            }
+            (None, Some(handle)) => {
+                if let Some(impulse_joint) = impulse_joint_set.get(handle.0) {
+                    joint.forces.update_impulse(
+                        impulse_joint,
+                        rapier::JointAxis::X,
+                        position,
+                        &rigid_bodies,
+                        dt,
+                    );
+                }
             }
+            (None, None) => {}
         }
     }
 }
@@ -242,7 +696,7 @@ pub struct JointMotorEvent {
     pub command: MotorCommand,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum MotorCommand {
     PositionRevolute {
         position: rapier::Real,
@@ -281,235 +735,255 @@ pub enum MotorCommand {
     },
 }
 
+/// Joint data of either kind of joint and the scale of its angular motor targets
+fn joint_data<'a>(
+    handles: (Option<&MultibodyJointHandle>, Option<&ImpulseJointHandle>),
+    joint_set: &'a mut rapier::MultibodyJointSet,
+    impulse_joint_set: &'a mut rapier::ImpulseJointSet,
+    rigid_bodies: &mut rapier::RigidBodySet,
+) -> Option<(&'a mut rapier::GenericJoint, rapier::Real)> {
+    match handles {
+        (Some(handle), _) => joint_set
+            .get_mut(handle.0)
+            .and_then(|(mb, id)| mb.link_mut(id))
+            .map(|joint_link| (&mut joint_link.joint.data, 1.0)),
+        (None, Some(handle)) => impulse_joint_set.get_mut(handle.0).map(|impulse_joint| {
+            // the bodies would not react to the new motor targets while sleeping
+            for body in [impulse_joint.body1, impulse_joint.body2] {
+                if let Some(body) = rigid_bodies.get_mut(body) {
+                    body.wake_up(true);
+                }
+            }
+            (&mut impulse_joint.data, IMPULSE_MOTOR_ANGLE_SCALE)
+        }),
+        (None, None) => None,
+    }
+}
+
 #[allow(clippy::type_complexity)]
 pub(crate) fn update_joint_motors_system(
     mut joint_event: EventReader<JointMotorEvent>,
     mut joint_set: ResMut<KeskoRes<rapier::MultibodyJointSet>>,
-    mut query: Query<
-        (
-            Option<&RevoluteJoint>,
-            Option<&PrismaticJoint>,
-            &MultibodyJointHandle,
-        ),
-        With<MultibodyJointHandle>,
-    >,
+    mut impulse_joint_set: ResMut<KeskoRes<rapier::ImpulseJointSet>>,
+    mut rigid_bodies: ResMut<KeskoRes<rapier::RigidBodySet>>,
+    mut actuators: Query<(Entity, &mut Actuator)>,
+    query: Query<(
+        Option<&RevoluteJoint>,
+        Option<&PrismaticJoint>,
+        JointHandles,
+    )>,
 ) {
+    // commands to joints with an actuator reach the motor once the latency of the actuator has passed
+    let mut commands = Vec::new();
     for event in joint_event.iter() {
-        match query.get_mut(event.entity) {
+        match actuators.get_mut(event.entity) {
+            Ok((_, mut actuator)) => actuator.send(event.command),
+            Err(_) => commands.push((event.entity, event.command)),
+        }
+    }
+    for (entity, mut actuator) in actuators.iter_mut() {
+        commands.extend(
+            actuator
+                .released()
+                .into_iter()
+                .map(|command| (entity, command)),
+        );
+    }
+
+    for (entity, command) in commands {
+        match query.get(entity) {
             Err(e) => error!("{:?}", e),
-            Ok((revolute_joint, prismatic_joint, joint_handle)) => match joint_set
-                .get_mut(joint_handle.0)
-            {
-                None => {
-                    error!("Could not get joint from joint set")
-                }
-                Some((mb, id)) => match mb.link_mut(id) {
-                    None => {
-                        error!("Could not get multi joint link from multibody");
-                    }
-                    Some(joint_link) => match event.command {
-                        MotorCommand::PositionRevolute {
-                            position,
-                            stiffness,
-                            damping,
-                        } => match joint_link.joint.data.as_revolute_mut() {
-                            Some(rev_joint) => {
-                                let motor = rev_joint.motor().expect("Joint should have a motor");
-
-                                let stiffness = match stiffness {
-                                    Some(stiffness) => stiffness,
-                                    None => motor.stiffness,
-                                };
-
-                                let damping = match damping {
-                                    Some(damping) => damping,
-                                    None => motor.damping,
-                                };
-
-                                rev_joint.set_motor_position(position, stiffness, damping);
-                            }
-                            None => {
-                                info!("Joint was not a revolute joint for revolute joint event");
-                            }
-                        },
-                        MotorCommand::VelocityRevolute { velocity, damping } => {
-                            match joint_link.joint.data.as_revolute_mut() {
-                                Some(rev_joint) => {
-                                    let motor =
-                                        rev_joint.motor().expect("Joint should have a motor");
-
-                                    let damping = match damping {
-                                        Some(damping) => damping,
-                                        None => motor.damping,
-                                    };
-
-                                    rev_joint.set_motor_velocity(velocity, damping);
-                                }
-                                None => {
-                                    info!(
-                                        "Joint was not a revolute joint for revolute joint event"
-                                    );
-                                }
-                            }
-                        }
-                        MotorCommand::PositionSpherical { axis, position } => {
-                            match joint_link.joint.data.as_spherical_mut() {
-                                Some(spherical_joint) => {
-                                    let motor = spherical_joint
-                                        .motor(axis.into())
-                                        .expect("Joint should have a motor");
-                                    spherical_joint.set_motor_position(
-                                        axis.into(),
-                                        position,
-                                        motor.stiffness,
-                                        motor.damping,
-                                    );
-                                }
-                                None => {
-                                    info!(
-                                        "Joint was not a spherical joint for spherical joint event"
-                                    );
-                                }
-                            }
-                        }
-                        MotorCommand::VelocitySpherical { axis, velocity } => {
-                            match joint_link.joint.data.as_spherical_mut() {
-                                Some(spherical_joint) => {
-                                    let motor = spherical_joint
-                                        .motor(axis.into())
-                                        .expect("Joint should have a motor");
-                                    spherical_joint.set_motor_velocity(
-                                        axis.into(),
-                                        velocity,
-                                        motor.damping,
-                                    );
-                                }
-                                None => {
-                                    info!(
-                                        "Joint was not a spherical joint for spherical joint event"
-                                    );
-                                }
-                            }
-                        }
-                        MotorCommand::PositionPrismatic {
-                            position,
-                            stiffness,
-                            damping,
-                        } => match joint_link.joint.data.as_prismatic_mut() {
-                            Some(prismatic_joint) => {
-                                let motor =
-                                    prismatic_joint.motor().expect("Joint should have a motor");
-
-                                let stiffness = match stiffness {
-                                    Some(stiffness) => stiffness,
-                                    None => motor.stiffness,
-                                };
-
-                                let damping = match damping {
-                                    Some(damping) => damping,
-                                    None => motor.damping,
-                                };
-
-                                prismatic_joint.set_motor_position(position, stiffness, damping);
-                            }
-                            None => {
-                                info!("Joint was not a prismatic joint for prismatic joint event");
-                            }
-                        },
-                        MotorCommand::VelocityPrismatic { velocity, damping } => {
-                            match joint_link.joint.data.as_prismatic_mut() {
-                                Some(prismatic_joint) => {
-                                    let motor =
-                                        prismatic_joint.motor().expect("Joint should have a motor");
-                                    let damping = match damping {
-                                        Some(damping) => damping,
-                                        None => motor.damping,
-                                    };
-                                    prismatic_joint.set_motor_velocity(velocity, damping);
-                                }
-                                None => {
-                                    info!(
-                                        "Joint was not a prismatic joint for prismatic joint event"
-                                    );
-                                }
-                            }
+            Ok((revolute_joint, prismatic_joint, handles)) => {
+                match joint_data(
+                    handles,
+                    &mut joint_set,
+                    &mut impulse_joint_set,
+                    &mut rigid_bodies,
+                ) {
+                    Some((data, angle_scale)) => {
+                        apply_motor_command(
+                            data,
+                            &command,
+                            angle_scale,
+                            revolute_joint,
+                            prismatic_joint,
+                        );
+                        if let Ok((_, mut actuator)) = actuators.get_mut(entity) {
+                            actuator.commanded(&command, data);
                         }
-                        MotorCommand::SetStiffness { val } => {
-                            if let Some(joint) = joint_link.joint.data.as_revolute_mut() {
-                                let motor = joint.motor().expect("Joint should have a motor");
-                                joint.set_motor(
-                                    motor.target_pos,
-                                    motor.target_vel,
-                                    val,
-                                    motor.damping,
-                                );
-                            } else if let Some(joint) = joint_link.joint.data.as_prismatic_mut() {
-                                let motor = joint.motor().expect("Joint should have a motor");
-                                joint.set_motor(
-                                    motor.target_pos,
-                                    motor.target_vel,
-                                    val,
-                                    motor.damping,
-                                );
-                            }
-                        }
-                        MotorCommand::SetDamping { val } => {
-                            if let Some(joint) = joint_link.joint.data.as_revolute_mut() {
-                                let motor = joint.motor().expect("Joint should have a motor");
-                                joint.set_motor(
-                                    motor.target_pos,
-                                    motor.target_vel,
-                                    motor.stiffness,
-                                    val,
-                                );
-                            } else if let Some(joint) = joint_link.joint.data.as_prismatic_mut() {
-                                let motor = joint.motor().expect("Joint should have a motor");
-                                joint.set_motor(
-                                    motor.target_pos,
-                                    motor.target_vel,
-                                    motor.stiffness,
-                                    val,
-                                );
-                            }
-                        }
-                        MotorCommand::HoldPosition { stiffness } => {
-                            if let Some(prismatic_joint) = prismatic_joint {
-                                let joint =
-                                    joint_link.joint.data.as_prismatic_mut().expect(
-                                        "Prismatic component should have a prismatic joint",
-                                    );
-                                let motor = joint.motor().expect("Joint should have a motor");
-
-                                let stiffness = match stiffness {
-                                    Some(stiffness) => stiffness,
-                                    None => motor.stiffness,
-                                };
-                                joint.set_motor_position(
-                                    prismatic_joint.position(),
-                                    stiffness,
-                                    0.0,
-                                );
-                            } else if let Some(revolute_joint) = revolute_joint {
-                                let joint =
-                                    joint_link.joint.data.as_revolute_mut().expect(
-                                        "Prismatic component should have a prismatic joint",
-                                    );
-                                let motor = joint.motor().expect("Joint should have a motor");
-
-                                let stiffness = match stiffness {
-                                    Some(stiffness) => stiffness,
-                                    None => motor.stiffness,
-                                };
-                                joint.set_motor_position(revolute_joint.rotation(), stiffness, 0.0);
-                            };
-                        }
-                    },
-                },
-            },
+                    }
+                    None => error!("Could not get joint from joint set"),
+                }
+            }
         }
     }
 }
 
+/// Updates the motor of the joint, angular position targets are multiplied by `angle_scale`
+fn apply_motor_command(
+    data: &mut rapier::GenericJoint,
+    command: &MotorCommand,
+    angle_scale: rapier::Real,
+    revolute_joint: Option<&RevoluteJoint>,
+    prismatic_joint: Option<&PrismaticJoint>,
+) {
+    match *command {
+        MotorCommand::PositionRevolute {
+            position,
+            stiffness,
+            damping,
+        } => match data.as_revolute_mut() {
+            Some(rev_joint) => {
+                let motor = rev_joint.motor().expect("Joint should have a motor");
+
+                let stiffness = match stiffness {
+                    Some(stiffness) => stiffness,
+                    None => motor.stiffness,
+                };
+
+                let damping = match damping {
+                    Some(damping) => damping,
+                    None => motor.damping,
+                };
+
+                rev_joint.set_motor_position(angle_scale * position, stiffness, damping);
+            }
+            None => {
+                info!("Joint was not a revolute joint for revolute joint event");
+            }
+        },
+        MotorCommand::VelocityRevolute { velocity, damping } => match data.as_revolute_mut() {
+            Some(rev_joint) => {
+                let motor = rev_joint.motor().expect("Joint should have a motor");
+
+                let damping = match damping {
+                    Some(damping) => damping,
+                    None => motor.damping,
+                };
+
+                rev_joint.set_motor_velocity(velocity, damping);
+            }
+            None => {
+                info!("Joint was not a revolute joint for revolute joint event");
+            }
+        },
+        MotorCommand::PositionSpherical { axis, position } => match data.as_spherical_mut() {
+            Some(spherical_joint) => {
+                let (stiffness, damping) = spherical_motor_params(spherical_joint, axis);
+                spherical_joint.set_motor_position(
+                    axis.into(),
+                    angle_scale * position,
+                    stiffness,
+                    damping,
+                );
+            }
+            None => {
+                info!("Joint was not a spherical joint for spherical joint event");
+            }
+        },
+        MotorCommand::VelocitySpherical { axis, velocity } => match data.as_spherical_mut() {
+            Some(spherical_joint) => {
+                let (_, damping) = spherical_motor_params(spherical_joint, axis);
+                spherical_joint.set_motor_velocity(axis.into(), velocity, damping);
+            }
+            None => {
+                info!("Joint was not a spherical joint for spherical joint event");
+            }
+        },
+        MotorCommand::PositionPrismatic {
+            position,
+            stiffness,
+            damping,
+        } => match data.as_prismatic_mut() {
+            Some(prismatic_joint) => {
+                let motor = prismatic_joint.motor().expect("Joint should have a motor");
+
+                let stiffness = match stiffness {
+                    Some(stiffness) => stiffness,
+                    None => motor.stiffness,
+                };
+
+                let damping = match damping {
+                    Some(damping) => damping,
+                    None => motor.damping,
+                };
+
+                prismatic_joint.set_motor_position(position, stiffness, damping);
+            }
+            None => {
+                info!("Joint was not a prismatic joint for prismatic joint event");
+            }
+        },
+        MotorCommand::VelocityPrismatic { velocity, damping } => match data.as_prismatic_mut() {
+            Some(prismatic_joint) => {
+                let motor = prismatic_joint.motor().expect("Joint should have a motor");
+                let damping = match damping {
+                    Some(damping) => damping,
+                    None => motor.damping,
+                };
+                prismatic_joint.set_motor_velocity(velocity, damping);
+            }
+            None => {
+                info!("Joint was not a prismatic joint for prismatic joint event");
+            }
+        },
+        MotorCommand::SetStiffness { val } => {
+            if let Some(joint) = data.as_revolute_mut() {
+                let motor = joint.motor().expect("Joint should have a motor");
+                joint.set_motor(motor.target_pos, motor.target_vel, val, motor.damping);
+            } else if let Some(joint) = data.as_prismatic_mut() {
+                let motor = joint.motor().expect("Joint should have a motor");
+                joint.set_motor(motor.target_pos, motor.target_vel, val, motor.damping);
+            }
+        }
+        MotorCommand::SetDamping { val } => {
+            if let Some(joint) = data.as_revolute_mut() {
+                let motor = joint.motor().expect("Joint should have a motor");
+                joint.set_motor(motor.target_pos, motor.target_vel, motor.stiffness, val);
+            } else if let Some(joint) = data.as_prismatic_mut() {
+                let motor = joint.motor().expect("Joint should have a motor");
+                joint.set_motor(motor.target_pos, motor.target_vel, motor.stiffness, val);
+            }
+        }
+        MotorCommand::HoldPosition { stiffness } => {
+            if let Some(prismatic_joint) = prismatic_joint {
+                let joint = data
+                    .as_prismatic_mut()
+                    .expect("Prismatic component should have a prismatic joint");
+                let motor = joint.motor().expect("Joint should have a motor");
+
+                let stiffness = match stiffness {
+                    Some(stiffness) => stiffness,
+                    None => motor.stiffness,
+                };
+                joint.set_motor_position(prismatic_joint.position(), stiffness, 0.0);
+            } else if let Some(revolute_joint) = revolute_joint {
+                let joint = data
+                    .as_revolute_mut()
+                    .expect("Prismatic component should have a prismatic joint");
+                let motor = joint.motor().expect("Joint should have a motor");
+
+                let stiffness = match stiffness {
+                    Some(stiffness) => stiffness,
+                    None => motor.stiffness,
+                };
+                joint.set_motor_position(angle_scale * revolute_joint.rotation(), stiffness, 0.0);
+            };
+        }
+    }
+}
+
+/// Stiffness and damping of the motor for an axis of a spherical joint, the motors of the axes
+/// are only created when they have any stiffness or damping so a missing motor has neither
+fn spherical_motor_params(
+    joint: &rapier::SphericalJoint,
+    axis: KeskoAxis,
+) -> (rapier::Real, rapier::Real) {
+    joint
+        .motor(axis.into())
+        .map_or((0.0, 0.0), |motor| (motor.stiffness, motor.damping))
+}
+
 #[cfg(test)]
 mod tests {
     use bevy::ecs::event::Events;
@@ -519,6 +993,7 @@ mod tests {
     use super::*;
     use crate::conversions::IntoRapier;
     use crate::joint::fixed::FixedJoint;
+    use crate::{collider::ColliderShape, mass::Mass, rigid_body::RigidBody, PhysicsPlugin};
 
     #[test]
     fn test_add_one_joint() {
@@ -642,6 +1117,8 @@ mod tests {
         app.world.insert_resource(events);
 
         // add system and run
+        app.init_resource::<KeskoRes<rapier::ImpulseJointSet>>();
+        app.init_resource::<KeskoRes<rapier::RigidBodySet>>();
         app.add_systems(Update, update_joint_motors_system);
         app.update();
 
@@ -711,6 +1188,8 @@ mod tests {
         app.insert_resource(events);
 
         // add system and run
+        app.init_resource::<KeskoRes<rapier::ImpulseJointSet>>();
+        app.init_resource::<KeskoRes<rapier::RigidBodySet>>();
         app.add_systems(Update, update_joint_motors_system);
         app.update();
 
@@ -788,6 +1267,8 @@ mod tests {
         app.insert_resource(events);
 
         // Run stage
+        app.init_resource::<KeskoRes<rapier::ImpulseJointSet>>();
+        app.init_resource::<KeskoRes<rapier::RigidBodySet>>();
         app.add_systems(Update, update_joint_motors_system);
         app.update();
 
@@ -863,6 +1344,8 @@ mod tests {
         app.insert_resource(events);
 
         // add system and run
+        app.init_resource::<KeskoRes<rapier::ImpulseJointSet>>();
+        app.init_resource::<KeskoRes<rapier::RigidBodySet>>();
         app.add_systems(Update, update_joint_motors_system);
         app.update();
 
@@ -908,4 +1391,276 @@ mod tests {
             expected_stiffness
         );
     }
+
+    #[test]
+    fn joint_forces() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            ..default()
+        });
+
+        let root = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 2.0, 0.0)),
+                RigidBody::Fixed,
+            ))
+            .id();
+        let child = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 1.0, 0.0)),
+                RigidBody::Dynamic,
+                Mass { val: 2.0 },
+                RevoluteJoint::attach_to(root)
+                    .with_parent_anchor(Transform::from_xyz(0.0, -1.0, 0.0))
+                    .with_axis(KeskoAxis::X)
+                    .with_limits(Vec2::new(0.0, 0.5))
+                    .with_motor_params(10.0, 1.0),
+            ))
+            .id();
+
+        for _ in 0..60 {
+            app.update();
+        }
+
+        // hanging still at the lower limit, the joint carries all the weight
+        let forces = *app.world.get::<RevoluteJoint>(child).unwrap().forces();
+        assert!(
+            (forces.reaction - Vec3::new(0.0, 2.0 * 9.81, 0.0)).length() < 0.1,
+            "{:?}",
+            forces.reaction
+        );
+        assert!(forces.motor.abs() < 0.1);
+        assert!(forces.at_lower_limit);
+        assert!(!forces.at_upper_limit);
+
+        // a position target above the upper limit makes the motor push against it
+        app.world.send_event(JointMotorEvent {
+            entity: child,
+            command: MotorCommand::PositionRevolute {
+                position: 1.0,
+                stiffness: None,
+                damping: None,
+            },
+        });
+        for _ in 0..120 {
+            app.update();
+        }
+
+        let forces = *app.world.get::<RevoluteJoint>(child).unwrap().forces();
+        assert!(forces.motor > 0.0);
+        assert!(!forces.at_lower_limit);
+        assert!(forces.at_upper_limit);
+    }
+
+    #[test]
+    fn joint_forces_with_substeps() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            substeps: 4,
+            ..default()
+        });
+
+        let root = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 2.0, 0.0)),
+                RigidBody::Fixed,
+            ))
+            .id();
+        let child = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 1.0, 0.0)),
+                RigidBody::Dynamic,
+                Mass { val: 2.0 },
+                RevoluteJoint::attach_to(root)
+                    .with_parent_anchor(Transform::from_xyz(0.0, -1.0, 0.0))
+                    .with_axis(KeskoAxis::X)
+                    .with_limits(Vec2::new(0.0, 0.5)),
+            ))
+            .id();
+
+        for _ in 0..60 {
+            app.update();
+        }
+
+        // the momentum change and the gravity are both taken over the last substep
+        let forces = *app.world.get::<RevoluteJoint>(child).unwrap().forces();
+        assert!(
+            (forces.reaction - Vec3::new(0.0, 2.0 * 9.81, 0.0)).length() < 0.1,
+            "{:?}",
+            forces.reaction
+        );
+    }
+
+    #[test]
+    fn impulse_joint() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            ..default()
+        });
+
+        let root = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 2.0, 0.0)),
+                RigidBody::Fixed,
+            ))
+            .id();
+        let child = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 0.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.2 },
+                RevoluteJoint::attach_to(root)
+                    .with_parent_anchor(Transform::from_xyz(0.0, -1.0, 0.0))
+                    .with_child_anchor(Transform::from_xyz(0.0, 1.0, 0.0))
+                    .with_axis(KeskoAxis::X)
+                    .with_motor_params(1e5, 5e3),
+                ImpulseJoint::default(),
+            ))
+            .id();
+
+        for _ in 0..60 {
+            app.update();
+        }
+
+        assert!(app.world.get::<MultibodyJointHandle>(child).is_none());
+        assert_eq!(
+            app.world
+                .resource::<KeskoRes<rapier::ImpulseJointSet>>()
+                .len(),
+            1
+        );
+
+        // hanging still, the joint carries all the weight
+        let weight = app.world.get::<Mass>(child).unwrap().val * 9.81;
+        let forces = *app.world.get::<RevoluteJoint>(child).unwrap().forces();
+        assert!(
+            (forces.reaction - Vec3::new(0.0, weight, 0.0)).length() < 0.01 * weight,
+            "{:?}",
+            forces.reaction
+        );
+
+        // motors are controlled the same way as for multibody joints
+        app.world.send_event(JointMotorEvent {
+            entity: child,
+            command: MotorCommand::PositionRevolute {
+                position: 0.5,
+                stiffness: None,
+                damping: None,
+            },
+        });
+        for _ in 0..300 {
+            app.update();
+        }
+
+        // the motor is a spring so the body hangs a bit below the target
+        let joint = app.world.get::<RevoluteJoint>(child).unwrap();
+        assert!(
+            joint.rotation() > 0.2 && joint.rotation() < 0.5,
+            "{}",
+            joint.rotation()
+        );
+        // the motor holds the body up against gravity
+        let torque = weight * joint.rotation().sin();
+        assert!(
+            (joint.forces().motor - torque).abs() < 0.05 * torque,
+            "{}",
+            joint.forces().motor
+        );
+    }
+
+    #[test]
+    fn closed_loop() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            ..default()
+        });
+
+        // two links hanging from a fixed bar, connected by a third link at the bottom
+        let bar = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 3.0, 0.0)),
+                RigidBody::Fixed,
+            ))
+            .id();
+        let spawn_link = |app: &mut App, x: f32, damping: f32| {
+            app.world
+                .spawn((
+                    TransformBundle::from_transform(Transform::from_xyz(x, 2.0, 0.0)),
+                    RigidBody::Dynamic,
+                    ColliderShape::Sphere { radius: 0.1 },
+                    RevoluteJoint::attach_to(bar)
+                        .with_parent_anchor(Transform::from_xyz(x, 0.0, 0.0))
+                        .with_child_anchor(Transform::from_xyz(0.0, 1.0, 0.0))
+                        .with_axis(KeskoAxis::Z)
+                        .with_motor_params(0.0, damping),
+                ))
+                .id()
+        };
+        let left = spawn_link(&mut app, -1.0, 10.0);
+        let right = spawn_link(&mut app, 1.0, 0.0);
+        let bottom = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 1.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.1 },
+                RevoluteJoint::attach_to(left)
+                    .with_parent_anchor(Transform::from_xyz(0.0, -1.0, 0.0))
+                    .with_child_anchor(Transform::from_xyz(-1.0, 0.0, 0.0))
+                    .with_axis(KeskoAxis::Z),
+            ))
+            .id();
+        // the bottom link already has a joint, close the loop with a joint on its own entity
+        let closing = app
+            .world
+            .spawn((
+                RevoluteJoint::attach_to(right)
+                    .with_parent_anchor(Transform::from_xyz(0.0, -1.0, 0.0))
+                    .with_child_anchor(Transform::from_xyz(1.0, 0.0, 0.0))
+                    .with_axis(KeskoAxis::Z),
+                ImpulseJoint::with_child(bottom),
+            ))
+            .id();
+
+        // drive the mechanism to the side with the left link
+        app.update();
+        app.world.send_event(JointMotorEvent {
+            entity: left,
+            command: MotorCommand::VelocityRevolute {
+                velocity: 1.0,
+                damping: None,
+            },
+        });
+        for _ in 0..30 {
+            app.update();
+        }
+
+        assert!(app.world.get::<ImpulseJointHandle>(closing).is_some());
+
+        // the closing joint should hold the anchors of the right and bottom links together
+        let right = app.world.get::<Transform>(right).unwrap();
+        let bottom = app.world.get::<Transform>(bottom).unwrap();
+        let right_anchor = right.transform_point(Vec3::new(0.0, -1.0, 0.0));
+        let bottom_anchor = bottom.transform_point(Vec3::new(1.0, 0.0, 0.0));
+        assert!(
+            right_anchor.distance(bottom_anchor) < 0.05,
+            "{} {}",
+            right_anchor,
+            bottom_anchor
+        );
+        // and the bottom link should have swung sideways while staying level
+        assert!(bottom.translation.x.abs() > 0.2, "{}", bottom.translation);
+        assert!(bottom.rotation.angle_between(Quat::IDENTITY) < 0.05);
+    }
 }
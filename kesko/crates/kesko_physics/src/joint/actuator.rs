@@ -0,0 +1,584 @@
+use std::collections::VecDeque;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use kesko_types::resource::KeskoRes;
+
+use super::{
+    joint_data, prismatic::PrismaticJoint, revolute::RevoluteJoint, JointHandles, MotorCommand,
+};
+use crate::rapier_extern::rapier::prelude as rapier;
+use crate::{
+    conversions::{IntoBevy, IntoRapier},
+    rigid_body::Entity2Body,
+    timestep::PhysicsTime,
+};
+
+/// Joint speed where the friction reaches its full value, the friction is scaled down below it
+/// so joints at rest don't jitter back and forth
+const FRICTION_SPEED: rapier::Real = 1e-2;
+
+/// Torque speed curve of a DC motor, the torque it can give falls linearly from the stall torque
+/// at standstill to zero at the no load speed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DcMotor {
+    pub stall_torque: rapier::Real,
+    pub no_load_speed: rapier::Real,
+}
+
+impl DcMotor {
+    pub fn new(stall_torque: rapier::Real, no_load_speed: rapier::Real) -> Self {
+        Self {
+            stall_torque,
+            no_load_speed,
+        }
+    }
+
+    /// Max torque, or force for prismatic joints, the motor can give at `speed`
+    pub fn max_torque(&self, speed: rapier::Real) -> rapier::Real {
+        if self.no_load_speed <= 0.0 {
+            return self.stall_torque;
+        }
+        (self.stall_torque * (1.0 - speed.abs() / self.no_load_speed)).clamp(0.0, self.stall_torque)
+    }
+}
+
+/// Component that models the actuator driving a revolute or prismatic joint, without it the motor
+/// of the joint acts like an ideal servo.
+///
+/// Each part of the model is optional. Motor commands are delayed by the latency before they
+/// reach the motor, position targets move towards the command with at most the max velocity and
+/// the motor gives no force while the position error is within the backlash. The torque speed
+/// curve replaces the max motor force of the joint. The model is updated once each frame, so the
+/// motor targets and max force are held during all the physics steps of a frame. The friction
+/// follows the velocity of the joint and is updated before each pipeline step.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Actuator {
+    pub motor: Option<DcMotor>,
+    /// Max speed of the joint, in radians or meters per second
+    pub max_velocity: Option<rapier::Real>,
+    /// Seconds of simulated time before a command reaches the motor
+    pub latency: f32,
+    /// Total play of the gears, in radians or meters
+    pub backlash: rapier::Real,
+    /// Coulomb friction torque, or force for prismatic joints, opposing the motion of the joint
+    pub friction: rapier::Real,
+    /// Commands that have not reached the motor yet and when they do
+    pending: VecDeque<(f32, MotorCommand)>,
+    time: f32,
+    /// Position the joint is commanded to in the units of the motor
+    position_command: Option<rapier::Real>,
+    /// Position target moving towards the command
+    position_target: Option<rapier::Real>,
+    /// Friction to apply during the next physics steps
+    friction_load: Option<FrictionLoad>,
+}
+
+/// Friction torque, or force for prismatic joints, on the child body of a joint, the parent gets
+/// the opposite
+#[derive(Debug, Clone, Copy)]
+struct FrictionLoad {
+    child: rapier::RigidBodyHandle,
+    parent: rapier::RigidBodyHandle,
+    /// Joint axis in the frame of the child body
+    local_axis: Vec3,
+    friction: rapier::Real,
+    /// Inertia of the child body around the joint axis, or its mass for prismatic joints
+    inertia: rapier::Real,
+    is_torque: bool,
+}
+
+impl FrictionLoad {
+    /// Force and torque on each body during a pipeline step of length `dt` from the current velocity
+    /// of the joint, None if a body is gone. The friction is limited to what stops the child body
+    /// within the step so it can't reverse the joint.
+    fn forces(
+        &self,
+        rigid_bodies: &rapier::RigidBodySet,
+        dt: rapier::Real,
+    ) -> Option<[(rapier::RigidBodyHandle, Vec3, Vec3); 2]> {
+        let child = rigid_bodies.get(self.child)?;
+        let parent = rigid_bodies.get(self.parent)?;
+
+        let axis = (*child.rotation() * self.local_axis.into_rapier()).into_bevy();
+        let velocity = if self.is_torque {
+            (child.angvel() - parent.angvel()).into_bevy().dot(axis)
+        } else {
+            (child.linvel() - parent.velocity_at_point(child.center_of_mass()))
+                .into_bevy()
+                .dot(axis)
+        };
+        let friction = (self.friction * (velocity / FRICTION_SPEED).abs().min(1.0))
+            .min(self.inertia * velocity.abs() / dt);
+        let load = -friction * velocity.signum() * axis;
+
+        let (force, torque) = if self.is_torque {
+            (Vec3::ZERO, load)
+        } else {
+            (load, Vec3::ZERO)
+        };
+        Some([(self.child, force, torque), (self.parent, -force, -torque)])
+    }
+}
+
+impl Actuator {
+    pub fn with_motor(mut self, motor: DcMotor) -> Self {
+        self.motor = Some(motor);
+        self
+    }
+
+    pub fn with_max_velocity(mut self, max_velocity: rapier::Real) -> Self {
+        self.max_velocity = Some(max_velocity);
+        self
+    }
+
+    pub fn with_latency(mut self, latency: f32) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_backlash(mut self, backlash: rapier::Real) -> Self {
+        self.backlash = backlash;
+        self
+    }
+
+    pub fn with_friction(mut self, friction: rapier::Real) -> Self {
+        self.friction = friction;
+        self
+    }
+
+    /// Queues a command until the latency has passed
+    pub(crate) fn send(&mut self, command: MotorCommand) {
+        self.pending.push_back((self.time + self.latency, command));
+    }
+
+    /// Commands that have reached the motor
+    pub(crate) fn released(&mut self) -> Vec<MotorCommand> {
+        let mut released = Vec::new();
+        while let Some((time, command)) = self.pending.front() {
+            if *time > self.time {
+                break;
+            }
+            released.push(*command);
+            self.pending.pop_front();
+        }
+        released
+    }
+
+    /// Keeps track of the commanded position after a command has been applied to the motor
+    pub(crate) fn commanded(&mut self, command: &MotorCommand, data: &rapier::GenericJoint) {
+        match command {
+            MotorCommand::PositionRevolute { .. }
+            | MotorCommand::PositionPrismatic { .. }
+            | MotorCommand::HoldPosition { .. } => {
+                self.position_command = motor_axis(data)
+                    .and_then(|axis| data.motor(axis))
+                    .map(|motor| motor.target_pos);
+            }
+            MotorCommand::VelocityRevolute { .. } | MotorCommand::VelocityPrismatic { .. } => {
+                self.position_command = None;
+                self.position_target = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Updates the motor targets and max force from the position and velocity of the joint,
+    /// positions and speeds are in the units of the motor which are scaled with `angle_scale`
+    fn update_motor(
+        &mut self,
+        data: &mut rapier::GenericJoint,
+        position: rapier::Real,
+        velocity: rapier::Real,
+        angle_scale: rapier::Real,
+        dt: rapier::Real,
+    ) {
+        let Some(axis) = motor_axis(data) else {
+            return;
+        };
+        let Some(motor) = data.motor(axis).copied() else {
+            return;
+        };
+
+        let mut target_pos = motor.target_pos;
+        if let Some(command) = self.position_command {
+            let target = match self.max_velocity {
+                Some(max_velocity) => {
+                    let max_step = max_velocity * angle_scale * dt;
+                    let target = self.position_target.unwrap_or(position);
+                    target + (command - target).clamp(-max_step, max_step)
+                }
+                None => command,
+            };
+            self.position_target = Some(target);
+
+            // the gears only engage once the error is larger than the play
+            let error = target - position;
+            let play = self.backlash * angle_scale / 2.0;
+            target_pos = if error.abs() <= play {
+                position
+            } else {
+                target - play * error.signum()
+            };
+        }
+
+        let target_vel = self.max_velocity.map_or(motor.target_vel, |max_velocity| {
+            motor.target_vel.clamp(-max_velocity, max_velocity)
+        });
+        data.set_motor(axis, target_pos, target_vel, motor.stiffness, motor.damping);
+
+        if let Some(dc_motor) = self.motor {
+            data.set_motor_max_force(axis, dc_motor.max_torque(velocity / angle_scale));
+        }
+    }
+}
+
+/// The free axis of revolute and prismatic joints
+fn motor_axis(data: &rapier::GenericJoint) -> Option<rapier::JointAxis> {
+    if data.as_revolute().is_some() {
+        Some(rapier::JointAxis::AngX)
+    } else if data.as_prismatic().is_some() {
+        Some(rapier::JointAxis::LinX)
+    } else {
+        None
+    }
+}
+
+/// System that applies the actuator models to the motors after the motor commands are handled
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_actuators_system(
+    physics_time: Res<PhysicsTime>,
+    mut joint_set: ResMut<KeskoRes<rapier::MultibodyJointSet>>,
+    mut impulse_joint_set: ResMut<KeskoRes<rapier::ImpulseJointSet>>,
+    mut rigid_bodies: ResMut<KeskoRes<rapier::RigidBodySet>>,
+    entity_2_body: Res<KeskoRes<Entity2Body>>,
+    mut query: Query<(
+        Entity,
+        &mut Actuator,
+        Option<&RevoluteJoint>,
+        Option<&PrismaticJoint>,
+        JointHandles,
+    )>,
+) {
+    let dt = physics_time.delta();
+
+    for (entity, mut actuator, revolute_joint, prismatic_joint, handles) in query.iter_mut() {
+        actuator.time += dt;
+
+        let (parent, position, velocity, is_revolute) = match (revolute_joint, prismatic_joint) {
+            (Some(joint), _) => (
+                joint.parent,
+                joint.rotation(),
+                joint.angular_velocity(),
+                true,
+            ),
+            (None, Some(joint)) => (joint.parent, joint.position(), joint.velocity(), false),
+            (None, None) => continue,
+        };
+
+        let Some((data, angle_scale)) = joint_data(
+            handles,
+            &mut joint_set,
+            &mut impulse_joint_set,
+            &mut rigid_bodies,
+        ) else {
+            continue;
+        };
+        let angle_scale = if is_revolute { angle_scale } else { 1.0 };
+        actuator.update_motor(
+            data,
+            angle_scale * position,
+            angle_scale * velocity,
+            angle_scale,
+            dt,
+        );
+        let local_axis = data.local_axis2();
+        let local_anchor = data.local_anchor2();
+
+        actuator.friction_load = None;
+        if actuator.friction == 0.0 {
+            continue;
+        }
+        let (Some(child), Some(parent)) = (entity_2_body.get(&entity), entity_2_body.get(&parent))
+        else {
+            continue;
+        };
+        let Some(child_body) = rigid_bodies.get(*child) else {
+            continue;
+        };
+
+        // inertia of the child around the joint axis, or its mass for prismatic joints
+        let mass_properties = child_body.mass_properties().local_mprops;
+        let inertia = if is_revolute {
+            let axis = local_axis.into_bevy();
+            let offset = (mass_properties.local_com - local_anchor).into_bevy();
+            let distance_squared = offset.length_squared() - offset.dot(axis).powi(2);
+            let inertia_matrix = mass_properties.reconstruct_inertia_matrix();
+            let rotational = (inertia_matrix * local_axis.into_inner())
+                .into_bevy()
+                .dot(axis);
+            rotational + mass_properties.mass() * distance_squared.max(0.0)
+        } else {
+            mass_properties.mass()
+        };
+
+        actuator.friction_load = Some(FrictionLoad {
+            child: *child,
+            parent: *parent,
+            local_axis: local_axis.into_bevy(),
+            friction: actuator.friction,
+            inertia,
+            is_torque: is_revolute,
+        });
+    }
+}
+
+/// Friction forces added for the current pipeline step, removed after the step so they don't add
+/// up with the forces set through [`crate::force::Force`]
+#[derive(Resource, Default)]
+pub(crate) struct AppliedJointFriction(Vec<(rapier::RigidBodyHandle, Vec3, Vec3)>);
+
+/// The friction of the actuators, applied as forces on the bodies since the motor is already used
+#[derive(SystemParam)]
+pub(crate) struct ActuatorFriction<'w, 's> {
+    actuators: Query<'w, 's, &'static Actuator>,
+    applied: ResMut<'w, AppliedJointFriction>,
+}
+
+impl ActuatorFriction<'_, '_> {
+    /// Adds the friction right before a pipeline step, from the joint velocities at that step so
+    /// the friction can't push a joint past standstill during the frame
+    pub(crate) fn apply(&mut self, rigid_bodies: &mut rapier::RigidBodySet, dt: rapier::Real) {
+        for friction_load in self
+            .actuators
+            .iter()
+            .filter_map(|actuator| actuator.friction_load)
+        {
+            let Some(forces) = friction_load.forces(rigid_bodies, dt) else {
+                continue;
+            };
+            for (handle, force, torque) in forces {
+                if let Some(body) = rigid_bodies.get_mut(handle) {
+                    body.add_force(force.into_rapier(), true);
+                    body.add_torque(torque.into_rapier(), true);
+                    self.applied.0.push((handle, force, torque));
+                }
+            }
+        }
+    }
+
+    /// Removes the friction after the pipeline step
+    pub(crate) fn remove(&mut self, rigid_bodies: &mut rapier::RigidBodySet) {
+        for (handle, force, torque) in self.applied.0.drain(..) {
+            if let Some(body) = rigid_bodies.get_mut(handle) {
+                body.add_force((-force).into_rapier(), false);
+                body.add_torque((-torque).into_rapier(), false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        collider::ColliderShape,
+        event::PhysicRequestEvent,
+        joint::{JointMotorEvent, KeskoAxis, MultibodyJointHandle},
+        rigid_body::RigidBody,
+        timestep::TimestepMode,
+        PhysicsPlugin,
+    };
+
+    #[test]
+    fn dc_motor_torque_speed_curve() {
+        let motor = DcMotor::new(2.0, 10.0);
+        assert_eq!(motor.max_torque(0.0), 2.0);
+        assert_eq!(motor.max_torque(-5.0), 1.0);
+        assert_eq!(motor.max_torque(20.0), 0.0);
+    }
+
+    #[test]
+    fn latency_and_max_velocity() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::ZERO,
+            ..default()
+        });
+
+        let parent = app
+            .world
+            .spawn((TransformBundle::default(), RigidBody::Fixed))
+            .id();
+        let child = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, -1.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.1 },
+                RevoluteJoint::attach_to(parent)
+                    .with_parent_anchor(Transform::from_xyz(0.0, -1.0, 0.0))
+                    .with_axis(KeskoAxis::X)
+                    .with_motor_params(100.0, 10.0),
+                Actuator::default()
+                    .with_latency(0.04)
+                    .with_max_velocity(1.0),
+            ))
+            .id();
+        app.update();
+
+        app.world.send_event(JointMotorEvent {
+            entity: child,
+            command: MotorCommand::PositionRevolute {
+                position: 1.0,
+                stiffness: None,
+                damping: None,
+            },
+        });
+
+        let target = |app: &App| {
+            let handle = app.world.get::<MultibodyJointHandle>(child).unwrap().0;
+            let joint_set = app.world.resource::<KeskoRes<rapier::MultibodyJointSet>>();
+            let (multibody, link_id) = joint_set.get(handle).unwrap();
+            let data = &multibody.link(link_id).unwrap().joint.data;
+            data.motor(rapier::JointAxis::AngX).unwrap().target_pos
+        };
+
+        // the command reaches the motor on the fourth frame after it was sent
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(target(&app), 0.0);
+        app.update();
+
+        // the target moves towards the command with the max velocity
+        let first = target(&app);
+        assert!(first > 0.0 && first <= 1.0 / 60.0 + 1e-4);
+        app.update();
+        assert!(target(&app) > first);
+    }
+
+    #[test]
+    fn friction_only_acts_during_the_step() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::ZERO,
+            ..default()
+        });
+
+        let parent = app
+            .world
+            .spawn((TransformBundle::default(), RigidBody::Fixed))
+            .id();
+        let child = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, -1.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.1 },
+                RevoluteJoint::attach_to(parent)
+                    .with_parent_anchor(Transform::from_xyz(0.0, -1.0, 0.0))
+                    .with_axis(KeskoAxis::X)
+                    .with_motor_params(0.0, 10.0),
+                Actuator::default().with_friction(0.5),
+            ))
+            .id();
+        app.update();
+
+        app.world.send_event(JointMotorEvent {
+            entity: child,
+            command: MotorCommand::VelocityRevolute {
+                velocity: 1.0,
+                damping: None,
+            },
+        });
+        for _ in 0..10 {
+            app.update();
+        }
+
+        // the friction opposes the motion of the joint
+        let velocity = app
+            .world
+            .get::<RevoluteJoint>(child)
+            .unwrap()
+            .angular_velocity();
+        let friction_load = app
+            .world
+            .get::<Actuator>(child)
+            .unwrap()
+            .friction_load
+            .unwrap();
+        // a short step so the friction is not limited to what stops the joint
+        let [(_, _, child_torque), (_, _, parent_torque)] = friction_load
+            .forces(app.world.resource::<KeskoRes<rapier::RigidBodySet>>(), 1e-3)
+            .unwrap();
+        assert!(velocity > 0.0);
+        assert!(child_torque.abs_diff_eq(Vec3::new(-0.5, 0.0, 0.0), 1e-4));
+        assert_eq!(parent_torque, -child_torque);
+
+        // and is removed from the bodies after the step, so resetting the forces can't break it
+        assert!(app.world.resource::<AppliedJointFriction>().0.is_empty());
+        let handle = app
+            .world
+            .get::<crate::rigid_body::RigidBodyHandle>(child)
+            .unwrap()
+            .0;
+        let user_torque = app.world.resource::<KeskoRes<rapier::RigidBodySet>>()[handle]
+            .user_torque()
+            .into_bevy();
+        assert_eq!(user_torque, Vec3::ZERO);
+    }
+
+    #[test]
+    fn friction_stops_the_joint_within_the_frame() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::ZERO,
+            timestep_mode: TimestepMode::Fixed {
+                steps_per_update: 20,
+            },
+            ..default()
+        });
+
+        let parent = app
+            .world
+            .spawn((TransformBundle::default(), RigidBody::Fixed))
+            .id();
+        let child = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, -1.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.1 },
+                RevoluteJoint::attach_to(parent)
+                    .with_parent_anchor(Transform::from_xyz(0.0, -1.0, 0.0))
+                    .with_axis(KeskoAxis::X),
+                Actuator::default().with_friction(1.0),
+            ))
+            .id();
+        app.update();
+
+        app.world.send_event(PhysicRequestEvent::ApplyImpulse {
+            id: child.to_bits(),
+            link: None,
+            impulse: 0.01 * Vec3::Z,
+            torque: Vec3::ZERO,
+            point: None,
+        });
+        for _ in 0..3 {
+            app.update();
+        }
+        let angle = app.world.get::<RevoluteJoint>(child).unwrap().rotation();
+        assert!(angle.abs() > 0.0);
+
+        // the friction acts during many steps each frame, it stops the joint instead of pushing it
+        // back the other way
+        for _ in 0..3 {
+            app.update();
+            let joint = app.world.get::<RevoluteJoint>(child).unwrap();
+            assert!(joint.angular_velocity().abs() < 0.05);
+            assert!(joint.rotation() * angle > 0.0);
+        }
+    }
+}
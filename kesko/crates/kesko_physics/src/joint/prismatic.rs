@@ -2,7 +2,7 @@ use crate::conversions::IntoRapier;
 use crate::rapier_extern::rapier::prelude as rapier;
 use bevy::prelude::*;
 
-use super::{AxisIntoVec, JointState, KeskoAxis};
+use super::{AxisIntoVec, JointForces, JointState, KeskoAxis};
 
 #[derive(Component, Clone, Copy)]
 pub struct PrismaticJoint {
@@ -17,6 +17,7 @@ pub struct PrismaticJoint {
 
     position: rapier::Real,
     velocity: rapier::Real,
+    pub(crate) forces: JointForces,
 }
 
 impl PrismaticJoint {
@@ -33,6 +34,7 @@ impl PrismaticJoint {
 
             position: 0.0,
             velocity: 0.0,
+            forces: JointForces::default(),
         }
     }
 
@@ -67,8 +69,13 @@ impl PrismaticJoint {
         self
     }
 
-    pub fn update_position_vel(&mut self, translation: Vec3, dt: rapier::Real) {
+    /// Updates the position and velocity from the transformation of the child in the parent frame,
+    /// the position is the offset of the child anchor from the parent anchor along the axis
+    pub fn update_position_vel(&mut self, translation: Vec3, rotation: Quat, dt: rapier::Real) {
         let prev_pos = self.position;
+        let translation = self.parent_anchor.rotation.inverse()
+            * (translation + rotation * self.child_anchor.translation
+                - self.parent_anchor.translation);
 
         match self.axis {
             KeskoAxis::X => self.position = translation.x as rapier::Real,
@@ -87,11 +94,23 @@ impl PrismaticJoint {
         self.position
     }
 
+    pub fn velocity(&self) -> rapier::Real {
+        self.velocity
+    }
+
+    pub fn forces(&self) -> &JointForces {
+        &self.forces
+    }
+
     pub fn state(&self) -> JointState {
         JointState::Prismatic {
             axis: self.axis,
             position: self.position,
             velocity: self.velocity,
+            motor_force: self.forces.motor,
+            reaction_force: self.forces.reaction,
+            at_lower_limit: self.forces.at_lower_limit,
+            at_upper_limit: self.forces.at_upper_limit,
         }
     }
 }
@@ -112,11 +131,12 @@ impl From<PrismaticJoint> for rapier::GenericJoint {
             builder = builder.limits([limits.x as rapier::Real, limits.y as rapier::Real]);
         }
 
+        // the anchors are the joint frames in the parent and child body, the axis is in those frames
         let mut generic: rapier::GenericJoint = builder.into();
         generic.local_frame1.rotation =
             joint.parent_anchor.rotation.into_rapier() * generic.local_frame1.rotation;
-        generic.local_frame1.rotation =
-            joint.child_anchor.rotation.into_rapier() * generic.local_frame1.rotation;
+        generic.local_frame2.rotation =
+            joint.child_anchor.rotation.into_rapier() * generic.local_frame2.rotation;
         generic
     }
 }
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use super::{AxisIntoVec, JointState, KeskoAxis};
+use super::{AxisIntoVec, JointForces, JointState, KeskoAxis};
 use crate::conversions::IntoRapier;
 use crate::rapier_extern::rapier::prelude as rapier;
 
@@ -17,6 +17,7 @@ pub struct RevoluteJoint {
 
     rotation: rapier::Real,
     angvel: rapier::Real,
+    pub(crate) forces: JointForces,
 }
 
 impl RevoluteJoint {
@@ -32,6 +33,7 @@ impl RevoluteJoint {
             max_motor_force: rapier::Real::MAX,
             rotation: 0.0,
             angvel: 0.0,
+            forces: JointForces::default(),
         }
     }
 
@@ -68,10 +70,9 @@ impl RevoluteJoint {
 
     pub fn update_rotation_angvel(&mut self, rot: Quat, dt: rapier::Real) {
         let prev_rot = self.rotation;
-        // convert to local orientation by multiplying by the inverse of anchor's rotation
-        let rotation =
-            (self.parent_anchor.rotation.inverse() * self.child_anchor.rotation.inverse() * rot)
-                .to_euler(EulerRot::XYZ);
+        // rotation of the child anchor relative to the parent anchor
+        let rotation = (self.parent_anchor.rotation.inverse() * rot * self.child_anchor.rotation)
+            .to_euler(EulerRot::XYZ);
         match self.axis {
             KeskoAxis::X => self.rotation = rotation.0 as rapier::Real,
             KeskoAxis::NegX => self.rotation = -rotation.0 as rapier::Real,
@@ -89,11 +90,23 @@ impl RevoluteJoint {
         self.rotation
     }
 
+    pub fn angular_velocity(&self) -> rapier::Real {
+        self.angvel
+    }
+
+    pub fn forces(&self) -> &JointForces {
+        &self.forces
+    }
+
     pub fn state(&self) -> JointState {
         JointState::Revolute {
             axis: self.axis,
             angle: self.rotation,
             angular_velocity: self.angvel,
+            motor_torque: self.forces.motor,
+            reaction_force: self.forces.reaction,
+            at_lower_limit: self.forces.at_lower_limit,
+            at_upper_limit: self.forces.at_upper_limit,
         }
     }
 }
@@ -114,11 +127,12 @@ impl From<RevoluteJoint> for rapier::GenericJoint {
             builder = builder.limits([limits.x as rapier::Real, limits.y as rapier::Real]);
         }
 
+        // the anchors are the joint frames in the parent and child body, the axis is in those frames
         let mut generic: rapier::GenericJoint = builder.into();
         generic.local_frame1.rotation =
             joint.parent_anchor.rotation.into_rapier() * generic.local_frame1.rotation;
-        generic.local_frame1.rotation =
-            joint.child_anchor.rotation.into_rapier() * generic.local_frame1.rotation;
+        generic.local_frame2.rotation =
+            joint.child_anchor.rotation.into_rapier() * generic.local_frame2.rotation;
         generic
     }
 }
@@ -128,8 +142,8 @@ mod tests {
     use super::RevoluteJoint;
     use crate::rapier_extern::rapier::dynamics::JointAxis;
     use crate::rapier_extern::rapier::prelude::GenericJoint;
-    use crate::{joint::KeskoAxis, IntoRapier};
-    use bevy::prelude::{Entity, Transform, Vec2, Vec3};
+    use crate::{joint::KeskoAxis, IntoBevy, IntoRapier};
+    use bevy::prelude::{Entity, Quat, Transform, Vec2, Vec3};
 
     #[test]
     fn only_translation() {
@@ -154,6 +168,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn rotated_anchors() {
+        let parent_rotation = Quat::from_rotation_z(1.0);
+        let child_rotation = Quat::from_rotation_y(0.5);
+        let mut joint = RevoluteJoint::attach_to(Entity::from_raw(0))
+            .with_parent_anchor(Transform::from_rotation(parent_rotation))
+            .with_child_anchor(Transform::from_rotation(child_rotation))
+            .with_axis(KeskoAxis::X);
+
+        // the axis is expressed in the anchor frames
+        let generic: GenericJoint = joint.into();
+        let axis1: Vec3 = generic.local_axis1().into_bevy();
+        let axis2: Vec3 = generic.local_axis2().into_bevy();
+        assert!(axis1.abs_diff_eq(parent_rotation * Vec3::X, 1e-6));
+        assert!(axis2.abs_diff_eq(child_rotation * Vec3::X, 1e-6));
+
+        // the angle is the rotation of the child anchor relative to the parent anchor
+        let rot = parent_rotation * Quat::from_rotation_x(0.3) * child_rotation.inverse();
+        joint.update_rotation_angvel(rot, 0.1);
+        assert!((joint.rotation() - 0.3).abs() < 1e-5);
+    }
+
     #[test]
     fn with_limits() {
         let limit_min = -1.0;
@@ -1,6 +1,9 @@
+use std::f32::consts::PI;
+
 use crate::rapier_extern::rapier::prelude as rapier;
 use bevy::prelude::*;
 
+use super::{JointState, KeskoAxis};
 use crate::conversions::IntoRapier;
 
 #[derive(Component, Clone, Copy)]
@@ -17,6 +20,10 @@ pub struct SphericalJoint {
     pub y_damping: rapier::Real,
     pub z_stiffness: rapier::Real,
     pub z_damping: rapier::Real,
+
+    rotation: Vec3,
+    orientation: Quat,
+    angvel: Vec3,
 }
 
 impl SphericalJoint {
@@ -34,6 +41,9 @@ impl SphericalJoint {
             y_damping: 0.0,
             z_stiffness: 0.0,
             z_damping: 0.0,
+            rotation: Vec3::ZERO,
+            orientation: Quat::IDENTITY,
+            angvel: Vec3::ZERO,
         }
     }
 
@@ -61,6 +71,60 @@ impl SphericalJoint {
         self.z_ang_limit = Some(limits);
         self
     }
+
+    /// Sets the same motor parameters for all three axes
+    pub fn with_motor_params(mut self, stiffness: rapier::Real, damping: rapier::Real) -> Self {
+        self.x_stiffness = stiffness;
+        self.y_stiffness = stiffness;
+        self.z_stiffness = stiffness;
+        self.x_damping = damping;
+        self.y_damping = damping;
+        self.z_damping = damping;
+        self
+    }
+
+    /// Stiffness and damping of the motor for an angular axis
+    pub fn motor_params(&self, axis: KeskoAxis) -> Option<(rapier::Real, rapier::Real)> {
+        match axis {
+            KeskoAxis::AngX => Some((self.x_stiffness, self.x_damping)),
+            KeskoAxis::AngY => Some((self.y_stiffness, self.y_damping)),
+            KeskoAxis::AngZ => Some((self.z_stiffness, self.z_damping)),
+            _ => None,
+        }
+    }
+
+    /// Updates the angles and angular velocities from the rotation of the child in the parent frame.
+    /// The angles are the XYZ euler angles of the child anchor relative to the parent anchor.
+    pub fn update_rotation_angvel(&mut self, rot: Quat, dt: rapier::Real) {
+        let orientation = self.parent_anchor.rotation.inverse() * rot * self.child_anchor.rotation;
+        let (x, y, z) = orientation.to_euler(EulerRot::XYZ);
+        self.rotation = Vec3::new(x, y, z);
+
+        // the velocity comes from the change in orientation since the euler angles jump when
+        // they wrap around and their rates are not an angular velocity
+        let (axis, angle) = (orientation * self.orientation.inverse()).to_axis_angle();
+        let angle = if angle > PI { angle - 2.0 * PI } else { angle };
+        self.angvel = axis * angle / dt;
+        self.orientation = orientation;
+    }
+
+    /// Angles around the x, y and z axis
+    pub fn rotation(&self) -> Vec3 {
+        self.rotation
+    }
+
+    /// Angular velocity of the child anchor relative to the parent anchor, in the parent anchor frame
+    pub fn angular_velocity(&self) -> Vec3 {
+        self.angvel
+    }
+
+    pub fn state(&self) -> JointState {
+        JointState::Spherical {
+            angles: self.rotation,
+            orientation: self.orientation,
+            angular_velocities: self.angvel,
+        }
+    }
 }
 
 impl From<SphericalJoint> for rapier::GenericJoint {
@@ -134,11 +198,12 @@ impl From<rapier::GenericJoint> for SphericalJoint {
 #[cfg(test)]
 mod tests {
     use super::SphericalJoint;
+    use crate::joint::KeskoAxis;
     use crate::rapier_extern::rapier::dynamics::JointAxis;
     use crate::rapier_extern::rapier::prelude::GenericJoint;
     use crate::IntoRapier;
     use bevy::math::Vec2;
-    use bevy::prelude::{Entity, Transform, Vec3};
+    use bevy::prelude::{Entity, EulerRot, Quat, Transform, Vec3};
 
     #[test]
     fn only_translation() {
@@ -207,4 +272,50 @@ mod tests {
         assert!(generic.limits(JointAxis::Y).is_none());
         assert!(generic.limits(JointAxis::Z).is_none());
     }
+
+    #[test]
+    fn rotation_and_angvel() {
+        let mut joint = SphericalJoint::attach_to(Entity::from_raw(0));
+
+        let expected = Vec3::new(0.1, -0.2, 0.3);
+        joint.update_rotation_angvel(
+            Quat::from_euler(EulerRot::XYZ, expected.x, expected.y, expected.z),
+            0.5,
+        );
+
+        assert!((joint.rotation() - expected).length() < 1e-5);
+
+        let rotation = Quat::from_euler(EulerRot::XYZ, expected.x, expected.y, expected.z);
+        assert!((joint.angular_velocity() - 2.0 * rotation.to_scaled_axis()).length() < 1e-5);
+    }
+
+    #[test]
+    fn angvel_when_angles_wrap_around() {
+        let mut joint = SphericalJoint::attach_to(Entity::from_raw(0));
+
+        // the x angle goes from 3.1 to -3.08 when passing pi
+        joint.update_rotation_angvel(Quat::from_rotation_x(3.1), 0.1);
+        joint.update_rotation_angvel(Quat::from_rotation_x(3.2), 0.1);
+        assert!(joint.rotation().x < 0.0);
+        assert!((joint.angular_velocity() - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-3);
+
+        // and back again
+        joint.update_rotation_angvel(Quat::from_rotation_x(3.1), 0.1);
+        assert!((joint.angular_velocity() - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn motor_params() {
+        let joint = SphericalJoint::attach_to(Entity::from_raw(0)).with_motor_params(2.0, 0.5);
+
+        assert_eq!(joint.motor_params(KeskoAxis::AngY), Some((2.0, 0.5)));
+        assert_eq!(joint.motor_params(KeskoAxis::X), None);
+
+        let generic: GenericJoint = joint.into();
+        for axis in [JointAxis::AngX, JointAxis::AngY, JointAxis::AngZ] {
+            let motor = generic.motor(axis).expect("Joint should have a motor");
+            assert_eq!(motor.stiffness, 2.0);
+            assert_eq!(motor.damping, 0.5);
+        }
+    }
 }
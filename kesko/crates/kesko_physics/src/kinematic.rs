@@ -0,0 +1,396 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::rapier_extern::rapier::prelude as rapier;
+use crate::{
+    conversions::{IntoBevy, IntoRapier},
+    rigid_body::RigidBodyHandle,
+};
+
+/// Pose of a kinematic path at a given time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    /// Time in seconds from the start of the path
+    pub time: f32,
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, translation: Vec3) -> Self {
+        Self {
+            time,
+            translation,
+            rotation: Quat::IDENTITY,
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+}
+
+/// Path for a kinematic body to follow.
+///
+/// All paths are relative to the pose the body had when it started to follow the path,
+/// translations are added to the start position and rotations are applied on top of the start rotation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KinematicPath {
+    /// Interpolates between keyframes sorted by time, starts over from the first keyframe when looping
+    Keyframes {
+        keyframes: Vec<Keyframe>,
+        looping: bool,
+    },
+    /// Constant velocity, e.g. pushers
+    Linear { velocity: Vec3 },
+    /// Moves back and forth along `amplitude` with a sine wave, e.g. moving platforms
+    Oscillate {
+        amplitude: Vec3,
+        frequency: f32,
+        phase: f32,
+    },
+    /// Constant rotation around an axis, e.g. rollers and spinning obstacles
+    Rotate { axis: Vec3, angular_velocity: f32 },
+    /// Rotates back and forth around an axis with a sine wave, e.g. swinging obstacles
+    Swing {
+        axis: Vec3,
+        amplitude: f32,
+        frequency: f32,
+        phase: f32,
+    },
+}
+
+impl KinematicPath {
+    /// Translation and rotation relative to the start pose at time `t`
+    pub fn pose(&self, t: f32) -> (Vec3, Quat) {
+        match self {
+            KinematicPath::Keyframes { keyframes, looping } => interpolate(keyframes, *looping, t),
+            KinematicPath::Linear { velocity } => (*velocity * t, Quat::IDENTITY),
+            KinematicPath::Oscillate {
+                amplitude,
+                frequency,
+                phase,
+            } => (
+                *amplitude * (TAU * frequency * t + phase).sin(),
+                Quat::IDENTITY,
+            ),
+            KinematicPath::Rotate {
+                axis,
+                angular_velocity,
+            } => (
+                Vec3::ZERO,
+                Quat::from_axis_angle(axis.normalize_or_zero(), angular_velocity * t),
+            ),
+            KinematicPath::Swing {
+                axis,
+                amplitude,
+                frequency,
+                phase,
+            } => (
+                Vec3::ZERO,
+                Quat::from_axis_angle(
+                    axis.normalize_or_zero(),
+                    amplitude * (TAU * frequency * t + phase).sin(),
+                ),
+            ),
+        }
+    }
+}
+
+fn interpolate(keyframes: &[Keyframe], looping: bool, t: f32) -> (Vec3, Quat) {
+    let (Some(first), Some(last)) = (keyframes.first(), keyframes.last()) else {
+        return (Vec3::ZERO, Quat::IDENTITY);
+    };
+
+    let t = if looping && last.time > 0.0 {
+        t.rem_euclid(last.time)
+    } else {
+        t
+    };
+
+    match keyframes.iter().position(|keyframe| keyframe.time > t) {
+        // before the first keyframe
+        Some(0) => (first.translation, first.rotation),
+        Some(i) => {
+            let (from, to) = (&keyframes[i - 1], &keyframes[i]);
+            let s = (t - from.time) / (to.time - from.time);
+            (
+                from.translation.lerp(to.translation, s),
+                from.rotation.slerp(to.rotation, s),
+            )
+        }
+        // after the last keyframe
+        None => (last.translation, last.rotation),
+    }
+}
+
+/// Component that drives a kinematic rigid body along a path.
+///
+/// The body is moved along the path before each pipeline step, also when several steps are taken
+/// each frame. Position based bodies are moved to the pose of the path at the end of the step.
+/// Velocity based bodies get the velocity needed to reach that pose, this gives smoother contacts.
+/// Other body types are not affected.
+#[derive(Component, Debug, Clone)]
+pub struct KinematicDriver {
+    pub path: KinematicPath,
+    /// Time the body has followed the path
+    pub time: f32,
+    /// Pose of the body when it started to follow the path
+    origin: Option<(Vec3, Quat)>,
+}
+
+impl KinematicDriver {
+    pub fn new(path: KinematicPath) -> Self {
+        Self {
+            path,
+            time: 0.0,
+            origin: None,
+        }
+    }
+
+    /// Starts the path over from the current pose of the body
+    pub fn restart(&mut self) {
+        self.time = 0.0;
+        self.origin = None;
+    }
+}
+
+pub(crate) type KinematicDrivers<'w, 's> =
+    Query<'w, 's, (&'static RigidBodyHandle, &'static mut KinematicDriver)>;
+
+/// Moves the kinematic bodies along their paths, called before each pipeline step with its time step
+pub(crate) fn drive_kinematic_bodies(
+    dt: f32,
+    rigid_bodies: &mut rapier::RigidBodySet,
+    drivers: &mut KinematicDrivers,
+) {
+    if dt == 0.0 {
+        return;
+    }
+
+    for (handle, mut driver) in drivers.iter_mut() {
+        if let Some(body) = rigid_bodies.get_mut(handle.0) {
+            let (translation, rotation) = body.position().into_bevy();
+            let (origin_translation, origin_rotation) =
+                *driver.origin.get_or_insert((translation, rotation));
+
+            driver.time += dt;
+            let (offset, path_rotation) = driver.path.pose(driver.time);
+            let target_translation = origin_translation + offset;
+            let target_rotation = path_rotation * origin_rotation;
+
+            match body.body_type() {
+                rapier::RigidBodyType::KinematicPositionBased => {
+                    body.set_next_kinematic_position(
+                        (target_translation, target_rotation).into_rapier(),
+                    );
+                }
+                rapier::RigidBodyType::KinematicVelocityBased => {
+                    // take the shortest way to the target rotation
+                    let mut diff = target_rotation * rotation.inverse();
+                    if diff.w < 0.0 {
+                        diff = -diff;
+                    }
+                    let (axis, angle) = diff.to_axis_angle();
+
+                    body.set_linvel(
+                        ((target_translation - translation) / dt).into_rapier(),
+                        true,
+                    );
+                    body.set_angvel((axis * angle / dt).into_rapier(), true);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f32::consts::FRAC_PI_2;
+
+    use kesko_types::resource::KeskoRes;
+
+    use super::*;
+    use crate::{
+        collider::ColliderShape, rigid_body::RigidBody, timestep::TimestepMode, PhysicsPlugin,
+    };
+
+    fn keyframe_path(looping: bool) -> KinematicPath {
+        KinematicPath::Keyframes {
+            keyframes: vec![
+                Keyframe::new(0.0, Vec3::ZERO),
+                Keyframe::new(1.0, Vec3::X).with_rotation(Quat::from_rotation_y(FRAC_PI_2)),
+                Keyframe::new(2.0, Vec3::ZERO),
+            ],
+            looping,
+        }
+    }
+
+    #[test]
+    fn keyframes() {
+        let (translation, rotation) = keyframe_path(true).pose(0.5);
+        assert!(translation.abs_diff_eq(Vec3::X * 0.5, 1e-6));
+        assert!(rotation.abs_diff_eq(Quat::from_rotation_y(FRAC_PI_2 / 2.0), 1e-6));
+
+        // starts over when looping, stays at the last keyframe otherwise
+        let (translation, _) = keyframe_path(true).pose(2.5);
+        assert!(translation.abs_diff_eq(Vec3::X * 0.5, 1e-6));
+        let (translation, _) = keyframe_path(false).pose(2.5);
+        assert_eq!(translation, Vec3::ZERO);
+    }
+
+    #[test]
+    fn drive_kinematic_bodies() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        let path = KinematicPath::Oscillate {
+            amplitude: Vec3::new(0.0, 0.0, 1.0),
+            frequency: 0.5,
+            phase: 0.0,
+        };
+        let start = Vec3::new(0.0, 1.0, 0.0);
+
+        let position_based = app
+            .world
+            .spawn((
+                RigidBody::KinematicPositionBased,
+                ColliderShape::Cuboid {
+                    x_half: 0.5,
+                    y_half: 0.1,
+                    z_half: 0.5,
+                },
+                TransformBundle::from(Transform::from_translation(start)),
+                KinematicDriver::new(path.clone()),
+            ))
+            .id();
+        let velocity_based = app
+            .world
+            .spawn((
+                RigidBody::KinematicVelocityBased,
+                ColliderShape::Cuboid {
+                    x_half: 0.5,
+                    y_half: 0.1,
+                    z_half: 0.5,
+                },
+                TransformBundle::from(Transform::from_translation(start + Vec3::X * 5.0)),
+                KinematicDriver::new(KinematicPath::Rotate {
+                    axis: Vec3::Y,
+                    angular_velocity: 1.0,
+                }),
+            ))
+            .id();
+
+        for _ in 0..30 {
+            app.update();
+        }
+
+        // both have followed their path for half a second
+        let time = app
+            .world
+            .get::<KinematicDriver>(position_based)
+            .unwrap()
+            .time;
+        assert!((time - 0.5).abs() < 1e-4);
+
+        let (expected, _) = path.pose(time);
+        let transform = app.world.get::<Transform>(position_based).unwrap();
+        assert!(
+            transform.translation.abs_diff_eq(start + expected, 1e-4),
+            "{:?}",
+            transform.translation
+        );
+
+        // kinematic bodies are not affected by gravity
+        let transform = app.world.get::<Transform>(velocity_based).unwrap();
+        assert!(transform
+            .translation
+            .abs_diff_eq(start + Vec3::X * 5.0, 1e-4));
+        let (axis, angle) = transform.rotation.to_axis_angle();
+        assert!(axis.abs_diff_eq(Vec3::Y, 1e-4));
+        assert!((angle - 0.5).abs() < 1e-3, "{angle}");
+    }
+
+    #[test]
+    fn several_steps_each_frame() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            timestep_mode: TimestepMode::Fixed {
+                steps_per_update: 4,
+            },
+            substeps: 2,
+            ..default()
+        });
+
+        let velocity = Vec3::X * 0.5;
+        let body = app
+            .world
+            .spawn((
+                RigidBody::KinematicPositionBased,
+                ColliderShape::Sphere { radius: 0.1 },
+                TransformBundle::default(),
+                KinematicDriver::new(KinematicPath::Linear { velocity }),
+            ))
+            .id();
+
+        for _ in 0..10 {
+            app.update();
+        }
+
+        // the body moves in every step instead of making the move of the whole frame in the first
+        // step, so it still has the velocity of the path after the last step
+        let handle = app.world.get::<RigidBodyHandle>(body).unwrap().0;
+        let rigid_body = &app.world.resource::<KeskoRes<rapier::RigidBodySet>>()[handle];
+        assert!(rigid_body.linvel().into_bevy().abs_diff_eq(velocity, 1e-4));
+
+        let time = app.world.get::<KinematicDriver>(body).unwrap().time;
+        assert!((time - 10.0 * 4.0 / 60.0).abs() < 1e-4);
+        let transform = app.world.get::<Transform>(body).unwrap();
+        assert!(transform.translation.abs_diff_eq(velocity * time, 1e-4));
+    }
+
+    #[test]
+    fn carry_dynamic_body() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        // platform moving sideways with a box on top
+        app.world.spawn((
+            RigidBody::KinematicVelocityBased,
+            ColliderShape::Cuboid {
+                x_half: 5.0,
+                y_half: 0.1,
+                z_half: 1.0,
+            },
+            TransformBundle::default(),
+            KinematicDriver::new(KinematicPath::Linear {
+                velocity: Vec3::X * 0.5,
+            }),
+        ));
+        let body = app
+            .world
+            .spawn((
+                RigidBody::Dynamic,
+                ColliderShape::Cuboid {
+                    x_half: 0.2,
+                    y_half: 0.2,
+                    z_half: 0.2,
+                },
+                TransformBundle::from(Transform::from_xyz(0.0, 0.3, 0.0)),
+            ))
+            .id();
+
+        for _ in 0..120 {
+            app.update();
+        }
+
+        // friction drags the box along with the platform
+        let transform = app.world.get::<Transform>(body).unwrap();
+        assert!(transform.translation.x > 0.8, "{:?}", transform.translation);
+        assert!(transform.translation.y > 0.2);
+    }
+}
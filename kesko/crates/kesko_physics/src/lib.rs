@@ -1,15 +1,25 @@
 pub mod collider;
 mod conversions;
+pub mod depth_camera;
 pub mod event;
 pub mod force;
+pub mod force_field;
 pub mod gravity;
 pub mod impulse;
+pub mod imu;
 pub mod joint;
+pub mod kinematic;
 pub mod mass;
 pub mod multibody;
+pub mod randomization;
+pub mod range_sensor;
 pub mod rapier_extern;
 pub mod rigid_body;
+pub mod scene_query;
+pub mod snapshot;
+pub mod timestep;
 
+use bevy::ecs::system::SystemParam;
 use bevy::math::Vec3;
 use bevy::prelude::*;
 
@@ -18,6 +28,7 @@ use kesko_types::resource::KeskoRes;
 use self::rapier_extern::rapier::prelude as rapier;
 use conversions::{IntoBevy, IntoRapier};
 use gravity::Gravity;
+use timestep::{PhysicsTime, TimestepMode};
 
 /// State to control the physics system
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
@@ -41,13 +52,21 @@ pub enum PhysicSets {
 pub struct PhysicsPlugin {
     pub gravity: Vec3,
     pub initial_state: PhysicState,
+    /// Simulated time for each physics step
+    pub timestep: f32,
+    /// Number of pipeline steps each physics step is split into
+    pub substeps: usize,
+    /// Max number of physics steps for one frame when running in real time
+    pub max_steps_per_frame: usize,
+    pub timestep_mode: TimestepMode,
 }
 
 impl PhysicsPlugin {
+    /// Physics running in real time, used when running with a window
     pub fn gravity() -> Self {
         Self {
-            gravity: Vec3::new(0.0, -9.81, 0.0),
-            initial_state: PhysicState::Running,
+            timestep_mode: TimestepMode::RealTime,
+            ..default()
         }
     }
 }
@@ -57,12 +76,34 @@ impl Default for PhysicsPlugin {
         Self {
             gravity: Vec3::new(0.0, -9.81, 0.0),
             initial_state: PhysicState::Running,
+            timestep: 1.0 / 60.0,
+            substeps: 1,
+            max_steps_per_frame: 4,
+            timestep_mode: TimestepMode::Fixed {
+                steps_per_update: 1,
+            },
         }
     }
 }
 
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
+        let physics_time = PhysicsTime::new(
+            self.timestep,
+            self.substeps,
+            self.max_steps_per_frame,
+            self.timestep_mode,
+        );
+
+        let integration_parameters = rapier::IntegrationParameters {
+            // sets the parameters that controls the simulation
+            // setting this above 0.8 can cause instabilities,
+            // if needed f64 feature should be used
+            erp: 0.75,
+            dt: physics_time.substep_dt() as rapier::Real,
+            ..default()
+        };
+
         app.init_resource::<KeskoRes<rigid_body::Entity2Body>>()
             .init_resource::<KeskoRes<rigid_body::Body2Entity>>()
             .init_resource::<KeskoRes<joint::Entity2JointHandle>>()
@@ -70,30 +111,46 @@ impl Plugin for PhysicsPlugin {
             .init_resource::<KeskoRes<rapier::PhysicsPipeline>>() // Runs the complete simulation
             .init_resource::<KeskoRes<rapier::RigidBodySet>>() // Holds all the rigid bodies
             .init_resource::<KeskoRes<rapier::ColliderSet>>() // Holds all the colliders
-            .insert_resource(KeskoRes(rapier::IntegrationParameters {
-                // sets the parameters that controls the simulation
-                // setting this above 0.8 can cause instabilities,
-                // if needed f64 feature should be used
-                erp: 0.75,
-                ..default()
-            }))
+            .insert_resource(KeskoRes(integration_parameters))
+            .insert_resource(physics_time)
             .init_resource::<KeskoRes<rapier::IslandManager>>() // Keeps track of which dynamic rigid bodies that are moving and which are not
             .init_resource::<KeskoRes<rapier::BroadPhase>>() // Detects pairs of colliders that are potentially in contact
             .init_resource::<KeskoRes<rapier::NarrowPhase>>() // Calculates contact points of colliders and generate collision events
             .init_resource::<KeskoRes<rapier::ImpulseJointSet>>()
             .init_resource::<KeskoRes<rapier::MultibodyJointSet>>()
             .init_resource::<KeskoRes<rapier::CCDSolver>>()
+            .init_resource::<KeskoRes<rapier::QueryPipeline>>() // Answers ray casts and other scene queries
+            .add_systems(Update, scene_query::handle_scene_query_events)
+            .add_systems(Update, force::handle_force_events)
             // collision event related
             .insert_resource(event::collision::CollisionEventHandler::new())
             .add_event::<event::collision::CollisionEvent>()
             // gravity
             .insert_resource(Gravity::new(self.gravity))
+            // wind, water and drag
+            .init_resource::<force_field::ForceFields>()
+            .init_resource::<force_field::AppliedFieldForces>()
+            .init_resource::<joint::actuator::AppliedJointFriction>()
+            .init_resource::<impulse::MultibodyImpulses>()
+            .init_resource::<joint::LastStepVelocities>()
+            .add_systems(Update, force_field::handle_force_field_events)
             // state for controlling the physics
             .add_state::<PhysicState>()
             // Physics events
             .add_event::<event::PhysicRequestEvent>()
             .add_event::<event::PhysicResponseEvent>()
             .add_systems(Update, event::handle_events)
+            .init_resource::<snapshot::Snapshots>()
+            .add_systems(Update, snapshot::handle_snapshot_events)
+            // domain randomization
+            .insert_resource(randomization::Randomization::new(
+                self.gravity,
+                integration_parameters,
+            ))
+            .add_systems(
+                Update,
+                randomization::handle_randomization_events.after(snapshot::handle_snapshot_events),
+            )
             .add_event::<joint::JointMotorEvent>()
             // configure how the physics sets are run
             .configure_sets(
@@ -122,35 +179,67 @@ impl Plugin for PhysicsPlugin {
             )
             .add_systems(
                 PreUpdate,
-                (joint::add_multibody_joints, apply_deferred)
+                (
+                    joint::add_multibody_joints,
+                    joint::add_impulse_joints,
+                    apply_deferred,
+                )
                     .in_set(PhysicSets::AddJoints)
                     .chain(),
             )
             .add_systems(
                 PreUpdate,
-                (multibody::add_multibodies, apply_deferred)
+                (
+                    multibody::add_multibodies,
+                    apply_deferred,
+                    multibody::update_self_collision_system,
+                    collider::update_collision_groups_system,
+                )
                     .chain()
                     .in_set(PhysicSets::AddMultibodies),
             )
             .add_systems(
                 PreUpdate,
-                (physics_pipeline_step, apply_deferred)
+                randomization::randomize_spawned_system
+                    .after(PhysicSets::AddMultibodies)
+                    .before(PhysicSets::PipelineStep),
+            )
+            .add_systems(
+                PreUpdate,
+                (
+                    timestep::update_physics_time,
+                    force_field::apply_force_fields_system.run_if(in_state(PhysicState::Running)),
+                    physics_pipeline_step.run_if(in_state(PhysicState::Running)),
+                    force_field::remove_force_fields_system,
+                    apply_deferred,
+                )
                     .chain()
-                    .in_set(PhysicSets::PipelineStep)
-                    .run_if(in_state(PhysicState::Running)),
+                    .in_set(PhysicSets::PipelineStep),
             )
             .add_systems(
                 PreUpdate,
                 (
                     (
                         update_bevy_world,
+                        scene_query::update_query_pipeline_system,
+                        range_sensor::update_range_sensors_system
+                            .after(update_bevy_world)
+                            .after(scene_query::update_query_pipeline_system),
                         multibody::update_multibody_vel_angvel,
+                        imu::update_imu_system,
+                        depth_camera::update_depth_cameras_system
+                            .after(update_bevy_world)
+                            .after(scene_query::update_query_pipeline_system),
                         impulse::update_impulse,
                         force::update_force_system,
                         gravity::update_gravity_scale_system,
                         mass::update_multibody_mass_system,
                         joint::update_joint_motors_system,
+                        joint::actuator::update_actuators_system
+                            .after(joint::update_joint_motors_system)
+                            .after(joint::update_joint_pos_system),
                         joint::update_joint_pos_system,
+                        joint::update_joint_forces_system.after(joint::update_joint_pos_system),
                         event::collision::send_collision_events_system,
                         event::spawn::send_spawned_events,
                     ),
@@ -166,7 +255,8 @@ impl Plugin for PhysicsPlugin {
 fn physics_pipeline_step(
     mut pipeline: ResMut<KeskoRes<rapier::PhysicsPipeline>>,
     gravity: Res<Gravity>,
-    integration_parameters: Res<KeskoRes<rapier::IntegrationParameters>>,
+    physics_time: Res<PhysicsTime>,
+    mut integration_parameters: ResMut<KeskoRes<rapier::IntegrationParameters>>,
     mut island_manager: ResMut<KeskoRes<rapier::IslandManager>>,
     mut broad_phase: ResMut<KeskoRes<rapier::BroadPhase>>,
     mut narrow_phase: ResMut<KeskoRes<rapier::NarrowPhase>>,
@@ -176,24 +266,67 @@ fn physics_pipeline_step(
     mut multibody_joints: ResMut<KeskoRes<rapier::MultibodyJointSet>>,
     mut ccd_solver: ResMut<KeskoRes<rapier::CCDSolver>>,
     collision_event_handler: Res<event::collision::CollisionEventHandler>,
+    mut step_loads: StepLoads,
 ) {
     let gravity = gravity.get().into_rapier();
+    let dt = physics_time.substep_dt();
+    integration_parameters.dt = dt as rapier::Real;
+
+    let steps = physics_time.steps() * physics_time.substeps;
+    for step in 0..steps {
+        step_loads.before_step(step, steps, dt, &mut rigid_bodies);
+        pipeline.0.step(
+            &gravity,
+            &integration_parameters,
+            &mut island_manager.0,
+            &mut broad_phase,
+            &mut narrow_phase,
+            &mut rigid_bodies,
+            &mut colliders,
+            &mut impulse_joints,
+            &mut multibody_joints,
+            &mut ccd_solver,
+            None,
+            &(),
+            &*collision_event_handler,
+        );
+        step_loads.after_step(step, dt, &mut rigid_bodies);
+    }
+}
+
+/// Everything that has to be applied to or read from the bodies around each pipeline step
+#[derive(SystemParam)]
+struct StepLoads<'w, 's> {
+    kinematic_drivers: kinematic::KinematicDrivers<'w, 's>,
+    multibody_impulses: ResMut<'w, impulse::MultibodyImpulses>,
+    actuator_friction: joint::actuator::ActuatorFriction<'w, 's>,
+    last_step_velocities: ResMut<'w, joint::LastStepVelocities>,
+}
 
-    pipeline.0.step(
-        &gravity,
-        &integration_parameters,
-        &mut island_manager.0,
-        &mut broad_phase,
-        &mut narrow_phase,
-        &mut rigid_bodies,
-        &mut colliders,
-        &mut impulse_joints,
-        &mut multibody_joints,
-        &mut ccd_solver,
-        None,
-        &(),
-        &*collision_event_handler,
-    );
+impl StepLoads<'_, '_> {
+    fn before_step(
+        &mut self,
+        step: usize,
+        steps: usize,
+        dt: f32,
+        rigid_bodies: &mut rapier::RigidBodySet,
+    ) {
+        if step == 0 {
+            self.multibody_impulses.add_forces(dt, rigid_bodies);
+        }
+        if step + 1 == steps {
+            self.last_step_velocities.record(rigid_bodies);
+        }
+        kinematic::drive_kinematic_bodies(dt, rigid_bodies, &mut self.kinematic_drivers);
+        self.actuator_friction.apply(rigid_bodies, dt);
+    }
+
+    fn after_step(&mut self, step: usize, dt: f32, rigid_bodies: &mut rapier::RigidBodySet) {
+        self.actuator_friction.remove(rigid_bodies);
+        if step == 0 {
+            self.multibody_impulses.remove_forces(dt, rigid_bodies);
+        }
+    }
 }
 
 fn update_bevy_world(
@@ -2,6 +2,7 @@ use bevy::prelude::*;
 
 use kesko_types::resource::KeskoRes;
 
+use crate::conversions::IntoRapier;
 use crate::rapier_extern::rapier::prelude as rapier;
 use crate::rigid_body::RigidBodyHandle;
 
@@ -11,6 +12,32 @@ pub struct Mass {
     pub val: rapier::Real,
 }
 
+/// Component for setting the mass, center of mass and inertia of a rigid body when it is added,
+/// used instead of [`Mass`] if both are given.
+///
+/// The colliders of the body still add their own mass, give them a density of zero for the body
+/// to have exactly these properties.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct MassProperties {
+    pub mass: rapier::Real,
+    /// Center of mass in the frame of the body
+    pub center_of_mass: Vec3,
+    /// Inertia tensor around the center of mass, expressed in the frame of the body
+    pub inertia: Mat3,
+}
+
+impl From<MassProperties> for rapier::MassProperties {
+    fn from(props: MassProperties) -> Self {
+        let inertia =
+            nalgebra::Matrix3::from_fn(|row, col| props.inertia.col(col)[row] as rapier::Real);
+        rapier::MassProperties::with_inertia_matrix(
+            props.center_of_mass.into_rapier(),
+            props.mass,
+            inertia,
+        )
+    }
+}
+
 /// Component that stores the mass of the multibody that a rigid body is part of
 /// Will be same as Mass of the body is not part of a multibody
 #[derive(Component)]
@@ -8,6 +8,7 @@ use kesko_types::resource::KeskoRes;
 use crate::rapier_extern::rapier::prelude as rapier;
 use crate::{
     conversions::IntoBevy,
+    imu::ImuReading,
     joint::JointState,
     rigid_body::{Body2Entity, Entity2Body, RigidBodyHandle},
 };
@@ -26,6 +27,50 @@ pub struct MultiBodyState {
     pub angular_velocity: Vec3,
     pub relative_positions: Option<BTreeMap<String, Vec3>>,
     pub joint_states: Option<BTreeMap<String, Option<JointState>>>,
+    /// Latest measurements of the range sensors, by the name of the link they are on
+    #[serde(default)]
+    pub range_sensors: Option<BTreeMap<String, Vec<f32>>>,
+    /// Latest measurements of the imus, by the name of the link they are on
+    #[serde(default)]
+    pub imus: Option<BTreeMap<String, ImuReading>>,
+}
+
+/// Helpers for using the state in reinforcement learning
+impl MultiBodyState {
+    /// Position, orientation, velocity and angular velocity of the root followed by the positions
+    /// and then the velocities of the joints. Joints are ordered by name, spherical joints
+    /// have one value for each axis.
+    pub fn observation(&self) -> Vec<f32> {
+        let joint_states = self
+            .joint_states
+            .iter()
+            .flat_map(|states| states.values().flatten())
+            .collect::<Vec<_>>();
+
+        let mut obs = Vec::with_capacity(13 + 2 * joint_states.len());
+        obs.extend(self.position.to_array());
+        obs.extend(self.orientation.to_array());
+        obs.extend(self.velocity.to_array());
+        obs.extend(self.angular_velocity.to_array());
+        obs.extend(joint_states.iter().flat_map(|state| state.positions()));
+        obs.extend(joint_states.iter().flat_map(|state| state.velocities()));
+        obs
+    }
+
+    /// Velocity of the root along a direction, typically used as reward for moving forward
+    pub fn progress(&self, direction: Vec3) -> f32 {
+        self.velocity.dot(direction.normalize_or_zero())
+    }
+
+    /// Angle in radians between the local y-axis of the root and the world y-axis
+    pub fn tilt(&self) -> f32 {
+        (self.orientation * Vec3::Y).angle_between(Vec3::Y)
+    }
+
+    /// If the root is below `min_height` or tilted more than `max_tilt` radians
+    pub fn has_fallen(&self, min_height: f32, max_tilt: f32) -> bool {
+        self.position.y < min_height || self.tilt() > max_tilt
+    }
 }
 
 /// Component to indicate that the entity is a multibody root entity
@@ -52,6 +97,10 @@ pub struct MultibodyChild {
     pub child_map: BTreeMap<String, Entity>,
 }
 
+/// Component to turn contacts between the links of a multibody on or off, added to the root
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SelfCollision(pub bool);
+
 /// System that adds components related to multibodies
 ///
 /// Todo: Look how to improve this, now it feels a bit complicated and not clear.
@@ -138,6 +187,33 @@ pub(crate) fn add_multibodies(
     }
 }
 
+/// System that enables or disables contacts between the links of multibodies
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_self_collision_system(
+    mut multibody_joints: ResMut<KeskoRes<rapier::MultibodyJointSet>>,
+    entity2body: Res<KeskoRes<Entity2Body>>,
+    query: Query<
+        (Entity, &SelfCollision),
+        (
+            With<MultibodyRoot>,
+            Or<(Changed<SelfCollision>, Added<MultibodyRoot>)>,
+        ),
+    >,
+) {
+    for (entity, self_collision) in query.iter() {
+        let Some(link) = entity2body
+            .get(&entity)
+            .and_then(|handle| multibody_joints.rigid_body_link(*handle))
+            .copied()
+        else {
+            continue;
+        };
+        if let Some(multibody) = multibody_joints.get_multibody_mut(link.multibody) {
+            multibody.set_self_contacts_enabled(self_collision.0);
+        }
+    }
+}
+
 pub(crate) fn update_multibody_vel_angvel(
     rigid_bodies: ResMut<KeskoRes<rapier::RigidBodySet>>,
     entity2body: Res<KeskoRes<Entity2Body>>,
@@ -156,8 +232,84 @@ pub(crate) fn update_multibody_vel_angvel(
 #[cfg(test)]
 mod tests {
 
+    use std::f32::consts::FRAC_PI_2;
+
     use super::*;
-    use crate::{joint, rigid_body, PhysicsPlugin};
+    use crate::{joint, joint::KeskoAxis, rigid_body, PhysicsPlugin};
+
+    fn state() -> MultiBodyState {
+        MultiBodyState {
+            name: "body".to_owned(),
+            id: 0,
+            position: Vec3::new(1.0, 0.5, 0.0),
+            orientation: Quat::IDENTITY,
+            velocity: Vec3::new(2.0, 0.0, 1.0),
+            angular_velocity: Vec3::ZERO,
+            relative_positions: None,
+            range_sensors: None,
+            imus: None,
+            joint_states: Some(BTreeMap::from([
+                (
+                    "b".to_owned(),
+                    Some(JointState::Prismatic {
+                        axis: KeskoAxis::X,
+                        position: 0.2,
+                        velocity: 0.3,
+                        motor_force: 0.0,
+                        reaction_force: Vec3::ZERO,
+                        at_lower_limit: false,
+                        at_upper_limit: false,
+                    }),
+                ),
+                ("fixed".to_owned(), None),
+                (
+                    "c".to_owned(),
+                    Some(JointState::Spherical {
+                        angles: Vec3::new(0.1, 0.2, 0.3),
+                        orientation: Quat::IDENTITY,
+                        angular_velocities: Vec3::new(-0.1, -0.2, -0.3),
+                    }),
+                ),
+                (
+                    "a".to_owned(),
+                    Some(JointState::Revolute {
+                        axis: KeskoAxis::Y,
+                        angle: 0.5,
+                        angular_velocity: -1.0,
+                        motor_torque: 0.0,
+                        reaction_force: Vec3::ZERO,
+                        at_lower_limit: false,
+                        at_upper_limit: false,
+                    }),
+                ),
+            ])),
+        }
+    }
+
+    #[test]
+    fn observation() {
+        let obs = state().observation();
+
+        assert_eq!(obs.len(), 13 + 10);
+        assert_eq!(obs[..3], [1.0, 0.5, 0.0]);
+        assert_eq!(obs[3..7], [0.0, 0.0, 0.0, 1.0]);
+        // joints ordered by name, positions before velocities
+        assert_eq!(obs[13..18], [0.5, 0.2, 0.1, 0.2, 0.3]);
+        assert_eq!(obs[18..], [-1.0, 0.3, -0.1, -0.2, -0.3]);
+    }
+
+    #[test]
+    fn progress_and_fall() {
+        let mut state = state();
+        assert_eq!(state.progress(Vec3::X), 2.0);
+        assert_eq!(state.progress(Vec3::NEG_Z * 3.0), -1.0);
+
+        assert!(!state.has_fallen(0.2, 1.0));
+        assert!(state.has_fallen(0.6, 1.0));
+
+        state.orientation = Quat::from_rotation_x(FRAC_PI_2);
+        assert!(state.has_fallen(0.2, 1.0));
+    }
 
     #[test]
     fn test_name() {
@@ -188,4 +340,41 @@ mod tests {
         let root_comp = app.world.get::<MultibodyRoot>(root_entity).unwrap();
         assert_eq!(*root_comp.name, expected_name)
     }
+
+    #[test]
+    fn self_collision() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        let root_entity = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                rigid_body::RigidBody::Dynamic,
+                SelfCollision(false),
+            ))
+            .id();
+        app.world.spawn((
+            TransformBundle::default(),
+            rigid_body::RigidBody::Dynamic,
+            joint::fixed::FixedJoint::attach_to(root_entity),
+        ));
+
+        app.update();
+
+        let self_contacts_enabled = |app: &App| {
+            let handle = app.world.get::<RigidBodyHandle>(root_entity).unwrap().0;
+            let multibody_joints = app.world.resource::<KeskoRes<rapier::MultibodyJointSet>>();
+            let link = multibody_joints.rigid_body_link(handle).unwrap();
+            multibody_joints
+                .get_multibody(link.multibody)
+                .unwrap()
+                .self_contacts_enabled()
+        };
+        assert!(!self_contacts_enabled(&app));
+
+        app.world.get_mut::<SelfCollision>(root_entity).unwrap().0 = true;
+        app.update();
+        assert!(self_contacts_enabled(&app));
+    }
 }
@@ -0,0 +1,641 @@
+use std::collections::BTreeMap;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+
+use kesko_types::resource::KeskoRes;
+
+use crate::rapier_extern::rapier::prelude as rapier;
+use crate::{
+    collider::{ColliderPhysicalProperties, Entity2Collider},
+    event::PhysicRequestEvent,
+    gravity::Gravity,
+    joint::{
+        prismatic::PrismaticJoint, revolute::RevoluteJoint, ImpulseJointHandle,
+        MultibodyJointHandle,
+    },
+    mass::{Mass, MultibodyMass},
+    rigid_body::RigidBodyHandle,
+};
+
+/// Distribution a parameter is sampled from, the relative ones are applied to the value the
+/// parameter had when it was spawned
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    /// Uniform between low and high
+    Uniform { low: f32, high: f32 },
+    /// Gaussian with the mean and standard deviation
+    Normal { mean: f32, std: f32 },
+    /// The nominal value scaled with a factor uniform between low and high
+    Scale { low: f32, high: f32 },
+    /// The nominal value with gaussian noise added
+    Jitter { std: f32 },
+}
+
+impl Distribution {
+    pub fn sample(&self, nominal: rapier::Real, rng: &mut impl Rng) -> rapier::Real {
+        match *self {
+            Distribution::Uniform { low, high } => uniform(low, high, rng),
+            Distribution::Normal { mean, std } => mean + std * standard_normal(rng),
+            Distribution::Scale { low, high } => nominal * uniform(low, high, rng),
+            Distribution::Jitter { std } => nominal + std * standard_normal(rng),
+        }
+    }
+}
+
+fn uniform(low: f32, high: f32, rng: &mut impl Rng) -> f32 {
+    low + (high - low) * rng.gen::<f32>()
+}
+
+fn standard_normal(rng: &mut impl Rng) -> f32 {
+    rng.sample(StandardNormal)
+}
+
+/// Sample `distribution` if there is one, values are clamped to be valid physical parameters
+fn sample_or(
+    distribution: Option<Distribution>,
+    nominal: rapier::Real,
+    min: rapier::Real,
+    max: rapier::Real,
+    rng: &mut impl Rng,
+) -> rapier::Real {
+    distribution.map_or(nominal, |distribution| {
+        distribution.sample(nominal, rng).clamp(min, max)
+    })
+}
+
+/// Distributions for the parameters of a rigid body and its collider
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct BodyRandomization {
+    /// Mass of the body, the inertia is scaled with it
+    pub mass: Option<Distribution>,
+    pub friction: Option<Distribution>,
+    pub restitution: Option<Distribution>,
+}
+
+impl BodyRandomization {
+    /// Distributions of `self` where they are set, else the ones of `other`
+    fn or(self, other: Self) -> Self {
+        Self {
+            mass: self.mass.or(other.mass),
+            friction: self.friction.or(other.friction),
+            restitution: self.restitution.or(other.restitution),
+        }
+    }
+}
+
+/// Distributions for the motor parameters of revolute and prismatic joints
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct JointRandomization {
+    pub stiffness: Option<Distribution>,
+    pub damping: Option<Distribution>,
+    pub max_motor_force: Option<Distribution>,
+}
+
+impl JointRandomization {
+    /// Distributions of `self` where they are set, else the ones of `other`
+    fn or(self, other: Self) -> Self {
+        Self {
+            stiffness: self.stiffness.or(other.stiffness),
+            damping: self.damping.or(other.damping),
+            max_motor_force: self.max_motor_force.or(other.max_motor_force),
+        }
+    }
+}
+
+/// Distributions for the parameters of the solver
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct IntegrationRandomization {
+    pub erp: Option<Distribution>,
+    pub damping_ratio: Option<Distribution>,
+    pub joint_erp: Option<Distribution>,
+    pub joint_damping_ratio: Option<Distribution>,
+}
+
+/// Which parameters to randomize and how.
+///
+/// Bodies and joints are sampled when they are spawned and the whole world is sampled again
+/// when it is reset, that is when a snapshot is restored or a [`PhysicRequestEvent::Randomize`]
+/// is sent. Gravity and the solver parameters are also sampled when all bodies are despawned.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct RandomizationConfig {
+    /// Seed for the random numbers, a random seed is used if not set
+    pub seed: Option<u64>,
+    /// Used for the parameters that are not set for a body in `bodies`
+    pub all_bodies: BodyRandomization,
+    /// Randomization for the bodies with the name
+    pub bodies: BTreeMap<String, BodyRandomization>,
+    /// Used for the parameters that are not set for a joint in `joints`
+    pub all_joints: JointRandomization,
+    /// Randomization for the joints with the name, joints are named by their child body
+    pub joints: BTreeMap<String, JointRandomization>,
+    /// Applied to each axis of the gravity
+    pub gravity: Option<Distribution>,
+    pub integration: IntegrationRandomization,
+}
+
+impl RandomizationConfig {
+    fn body(&self, name: Option<&Name>) -> BodyRandomization {
+        name.and_then(|name| self.bodies.get(name.as_str()))
+            .map_or(self.all_bodies, |body| body.or(self.all_bodies))
+    }
+
+    fn joint(&self, name: Option<&Name>) -> JointRandomization {
+        name.and_then(|name| self.joints.get(name.as_str()))
+            .map_or(self.all_joints, |joint| joint.or(self.all_joints))
+    }
+}
+
+/// The randomization config together with the random number generator and the nominal global
+/// parameters
+#[derive(Resource)]
+pub struct Randomization {
+    config: RandomizationConfig,
+    rng: StdRng,
+    nominal_gravity: Vec3,
+    nominal_integration: rapier::IntegrationParameters,
+}
+
+impl Randomization {
+    pub fn new(gravity: Vec3, integration_parameters: rapier::IntegrationParameters) -> Self {
+        Self {
+            config: RandomizationConfig::default(),
+            rng: StdRng::from_entropy(),
+            nominal_gravity: gravity,
+            nominal_integration: integration_parameters,
+        }
+    }
+
+    pub fn config(&self) -> &RandomizationConfig {
+        &self.config
+    }
+
+    /// Replaces the config, the generator is seeded again if the config has a seed
+    pub fn set_config(&mut self, config: RandomizationConfig) {
+        if let Some(seed) = config.seed {
+            self.rng = StdRng::seed_from_u64(seed);
+        }
+        self.config = config;
+    }
+}
+
+/// Parameters of a body and its joint before they were randomized, added when it is spawned
+#[derive(Component, Debug, Clone, Copy)]
+pub struct NominalParameters {
+    mass: rapier::Real,
+    density: rapier::Real,
+    friction: rapier::Real,
+    restitution: rapier::Real,
+    joint: Option<NominalJoint>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NominalJoint {
+    stiffness: rapier::Real,
+    damping: rapier::Real,
+    max_motor_force: rapier::Real,
+}
+
+impl NominalParameters {
+    fn new(
+        mass: rapier::Real,
+        physical_props: Option<&ColliderPhysicalProperties>,
+        revolute_joint: Option<&RevoluteJoint>,
+        prismatic_joint: Option<&PrismaticJoint>,
+    ) -> Self {
+        let props = physical_props.cloned().unwrap_or_default();
+        let joint = match (revolute_joint, prismatic_joint) {
+            (Some(joint), _) => Some(NominalJoint {
+                stiffness: joint.stiffness,
+                damping: joint.damping,
+                max_motor_force: joint.max_motor_force,
+            }),
+            (None, Some(joint)) => Some(NominalJoint {
+                stiffness: joint.stiffness,
+                damping: joint.damping,
+                max_motor_force: joint.max_motor_force,
+            }),
+            (None, None) => None,
+        };
+        Self {
+            mass,
+            density: props.density,
+            friction: props.friction,
+            restitution: props.restitution,
+            joint,
+        }
+    }
+}
+
+type RandomizedComponents = (
+    Entity,
+    &'static NominalParameters,
+    &'static RigidBodyHandle,
+    Option<&'static Name>,
+    Option<&'static MultibodyJointHandle>,
+    Option<&'static ImpulseJointHandle>,
+);
+
+/// The parts of the physics world that are randomized
+#[derive(SystemParam)]
+pub(crate) struct RandomizedWorld<'w> {
+    rigid_bodies: ResMut<'w, KeskoRes<rapier::RigidBodySet>>,
+    colliders: ResMut<'w, KeskoRes<rapier::ColliderSet>>,
+    multibody_joints: ResMut<'w, KeskoRes<rapier::MultibodyJointSet>>,
+    impulse_joints: ResMut<'w, KeskoRes<rapier::ImpulseJointSet>>,
+    entity_2_collider: Res<'w, KeskoRes<Entity2Collider>>,
+}
+
+impl RandomizedWorld<'_> {
+    /// Samples the parameters of a body and its joint, returns the new mass of the body
+    fn randomize(
+        &mut self,
+        (entity, nominal, body_handle, name, multibody_joint, impulse_joint): (
+            Entity,
+            &NominalParameters,
+            &RigidBodyHandle,
+            Option<&Name>,
+            Option<&MultibodyJointHandle>,
+            Option<&ImpulseJointHandle>,
+        ),
+        config: &RandomizationConfig,
+        rng: &mut impl Rng,
+    ) -> rapier::Real {
+        let body_config = config.body(name);
+        let mass = sample_or(
+            body_config.mass,
+            nominal.mass,
+            rapier::Real::EPSILON,
+            rapier::Real::MAX,
+            rng,
+        );
+
+        if let Some(collider) = self
+            .entity_2_collider
+            .get(&entity)
+            .and_then(|handle| self.colliders.get_mut(*handle))
+        {
+            // scaling the density keeps the center of mass and scales the inertia with the mass
+            if nominal.mass > 0.0 {
+                collider.set_density(nominal.density * mass / nominal.mass);
+            }
+            collider.set_friction(sample_or(
+                body_config.friction,
+                nominal.friction,
+                0.0,
+                rapier::Real::MAX,
+                rng,
+            ));
+            collider.set_restitution(sample_or(
+                body_config.restitution,
+                nominal.restitution,
+                0.0,
+                rapier::Real::MAX,
+                rng,
+            ));
+        }
+
+        if let Some(nominal_joint) = nominal.joint {
+            let joint_config = config.joint(name);
+            let stiffness = sample_or(
+                joint_config.stiffness,
+                nominal_joint.stiffness,
+                0.0,
+                rapier::Real::MAX,
+                rng,
+            );
+            let damping = sample_or(
+                joint_config.damping,
+                nominal_joint.damping,
+                0.0,
+                rapier::Real::MAX,
+                rng,
+            );
+            let max_motor_force = sample_or(
+                joint_config.max_motor_force,
+                nominal_joint.max_motor_force,
+                0.0,
+                rapier::Real::MAX,
+                rng,
+            );
+
+            let data = match (multibody_joint, impulse_joint) {
+                (Some(handle), _) => self
+                    .multibody_joints
+                    .get_mut(handle.0)
+                    .and_then(|(mb, id)| mb.link_mut(id))
+                    .map(|link| &mut link.joint.data),
+                (None, Some(handle)) => self
+                    .impulse_joints
+                    .get_mut(handle.0)
+                    .map(|joint| &mut joint.data),
+                (None, None) => None,
+            };
+            if let Some(data) = data {
+                set_motor_params(data, stiffness, damping, max_motor_force);
+            }
+        }
+
+        if let Some(body) = self.rigid_bodies.get_mut(body_handle.0) {
+            body.wake_up(true);
+        }
+
+        mass
+    }
+}
+
+/// Sets the motor parameters of a revolute or prismatic joint and keeps its targets
+fn set_motor_params(
+    data: &mut rapier::GenericJoint,
+    stiffness: rapier::Real,
+    damping: rapier::Real,
+    max_motor_force: rapier::Real,
+) {
+    if let Some(joint) = data.as_revolute_mut() {
+        let (target_pos, target_vel) = joint
+            .motor()
+            .map_or((0.0, 0.0), |motor| (motor.target_pos, motor.target_vel));
+        joint.set_motor(target_pos, target_vel, stiffness, damping);
+        joint.set_motor_max_force(max_motor_force);
+    } else if let Some(joint) = data.as_prismatic_mut() {
+        let (target_pos, target_vel) = joint
+            .motor()
+            .map_or((0.0, 0.0), |motor| (motor.target_pos, motor.target_vel));
+        joint.set_motor(target_pos, target_vel, stiffness, damping);
+        joint.set_motor_max_force(max_motor_force);
+    }
+}
+
+/// Samples the gravity and the solver parameters
+fn randomize_global(
+    randomization: &mut Randomization,
+    gravity: &mut Gravity,
+    integration_parameters: &mut rapier::IntegrationParameters,
+) {
+    let Randomization {
+        config,
+        rng,
+        nominal_gravity,
+        nominal_integration,
+    } = randomization;
+
+    let gravity_value = match config.gravity {
+        Some(distribution) => Vec3::new(
+            distribution.sample(nominal_gravity.x, rng),
+            distribution.sample(nominal_gravity.y, rng),
+            distribution.sample(nominal_gravity.z, rng),
+        ),
+        None => *nominal_gravity,
+    };
+    *gravity = Gravity::new(gravity_value);
+
+    // the time step is set by the physics time and is left as it is
+    let integration = config.integration;
+    integration_parameters.erp = sample_or(integration.erp, nominal_integration.erp, 0.0, 1.0, rng);
+    integration_parameters.damping_ratio = sample_or(
+        integration.damping_ratio,
+        nominal_integration.damping_ratio,
+        0.0,
+        rapier::Real::MAX,
+        rng,
+    );
+    integration_parameters.joint_erp = sample_or(
+        integration.joint_erp,
+        nominal_integration.joint_erp,
+        0.0,
+        1.0,
+        rng,
+    );
+    integration_parameters.joint_damping_ratio = sample_or(
+        integration.joint_damping_ratio,
+        nominal_integration.joint_damping_ratio,
+        0.0,
+        rapier::Real::MAX,
+        rng,
+    );
+}
+
+/// System that stores the nominal parameters of new bodies and randomizes them before their
+/// first physics step
+#[allow(clippy::type_complexity)]
+pub(crate) fn randomize_spawned_system(
+    mut commands: Commands,
+    mut randomization: ResMut<Randomization>,
+    mut world: RandomizedWorld,
+    query: Query<
+        (
+            Entity,
+            &RigidBodyHandle,
+            Option<&Name>,
+            Option<&ColliderPhysicalProperties>,
+            Option<&RevoluteJoint>,
+            Option<&PrismaticJoint>,
+            Option<&MultibodyJointHandle>,
+            Option<&ImpulseJointHandle>,
+        ),
+        Without<NominalParameters>,
+    >,
+) {
+    let Randomization { config, rng, .. } = &mut *randomization;
+
+    for (
+        entity,
+        body_handle,
+        name,
+        physical_props,
+        revolute_joint,
+        prismatic_joint,
+        multibody_joint,
+        impulse_joint,
+    ) in query.iter()
+    {
+        let Some(body) = world.rigid_bodies.get(body_handle.0) else {
+            continue;
+        };
+
+        // only dynamic bodies get a new mass
+        let nominal_mass = if body.is_dynamic() { body.mass() } else { 0.0 };
+        let nominal = NominalParameters::new(
+            nominal_mass,
+            physical_props,
+            revolute_joint,
+            prismatic_joint,
+        );
+
+        let mass = world.randomize(
+            (
+                entity,
+                &nominal,
+                body_handle,
+                name,
+                multibody_joint,
+                impulse_joint,
+            ),
+            config,
+            rng,
+        );
+
+        let mut entity_commands = commands.entity(entity);
+        entity_commands.insert(nominal);
+        if nominal_mass > 0.0 {
+            entity_commands.insert(Mass { val: mass });
+        }
+    }
+}
+
+/// System that handles changes of the config and randomizes the world when it is reset
+pub(crate) fn handle_randomization_events(
+    mut commands: Commands,
+    mut randomization: ResMut<Randomization>,
+    mut gravity: ResMut<Gravity>,
+    mut integration_parameters: ResMut<KeskoRes<rapier::IntegrationParameters>>,
+    mut world: RandomizedWorld,
+    mut request_events: EventReader<PhysicRequestEvent>,
+    query: Query<RandomizedComponents>,
+) {
+    for event in request_events.iter() {
+        match event {
+            PhysicRequestEvent::SetRandomization(config) => {
+                randomization.set_config(config.clone());
+            }
+            PhysicRequestEvent::DespawnAll => {
+                randomize_global(
+                    &mut randomization,
+                    &mut gravity,
+                    &mut integration_parameters,
+                );
+            }
+            PhysicRequestEvent::Randomize | PhysicRequestEvent::RestoreSnapshot(_) => {
+                randomize_global(
+                    &mut randomization,
+                    &mut gravity,
+                    &mut integration_parameters,
+                );
+
+                let Randomization { config, rng, .. } = &mut *randomization;
+                for components in query.iter() {
+                    let (entity, nominal, ..) = components;
+                    let mass = world.randomize(components, config, rng);
+                    if nominal.mass > 0.0 {
+                        // the multibody mass is computed again after the next step
+                        commands
+                            .entity(entity)
+                            .insert(Mass { val: mass })
+                            .remove::<MultibodyMass>();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{collider::ColliderShape, rigid_body::RigidBody, PhysicsPlugin};
+
+    #[test]
+    fn seeded_samples() {
+        let sample = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            [
+                Distribution::Uniform {
+                    low: 1.0,
+                    high: 2.0,
+                }
+                .sample(10.0, &mut rng),
+                Distribution::Scale {
+                    low: 0.5,
+                    high: 0.5,
+                }
+                .sample(10.0, &mut rng),
+                Distribution::Jitter { std: 0.0 }.sample(10.0, &mut rng),
+            ]
+        };
+
+        let samples = sample(1);
+        assert_eq!(samples, sample(1));
+        assert!((1.0..=2.0).contains(&samples[0]));
+        assert_eq!(samples[1], 5.0);
+        assert_eq!(samples[2], 10.0);
+    }
+
+    #[test]
+    fn randomize_bodies_and_gravity() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        let config = RandomizationConfig {
+            seed: Some(0),
+            bodies: BTreeMap::from([(
+                "box".to_owned(),
+                BodyRandomization {
+                    mass: Some(Distribution::Uniform {
+                        low: 2.0,
+                        high: 2.0,
+                    }),
+                    ..default()
+                },
+            )]),
+            all_bodies: BodyRandomization {
+                friction: Some(Distribution::Uniform {
+                    low: 0.3,
+                    high: 0.3,
+                }),
+                ..default()
+            },
+            gravity: Some(Distribution::Scale {
+                low: 2.0,
+                high: 2.0,
+            }),
+            ..default()
+        };
+        app.world
+            .send_event(PhysicRequestEvent::SetRandomization(config));
+        app.update();
+
+        let body = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Dynamic,
+                ColliderShape::Cuboid {
+                    x_half: 0.5,
+                    y_half: 0.5,
+                    z_half: 0.5,
+                },
+                Name::new("box"),
+            ))
+            .id();
+        app.update();
+
+        let handle = app.world.get::<RigidBodyHandle>(body).unwrap().0;
+        let rigid_bodies = app.world.resource::<KeskoRes<rapier::RigidBodySet>>();
+        let colliders = app.world.resource::<KeskoRes<rapier::ColliderSet>>();
+        let rigid_body = rigid_bodies.get(handle).unwrap();
+        let collider = colliders.get(rigid_body.colliders()[0]).unwrap();
+        assert!((rigid_body.mass() - 2.0).abs() < 1e-4);
+        assert!((collider.friction() - 0.3).abs() < 1e-6);
+        assert_eq!(app.world.get::<Mass>(body).unwrap().val, 2.0);
+
+        // gravity is sampled on reset
+        assert_eq!(
+            *app.world.resource::<Gravity>().get(),
+            Vec3::new(0.0, -9.81, 0.0)
+        );
+        app.world.send_event(PhysicRequestEvent::Randomize);
+        app.update();
+        assert_eq!(
+            *app.world.resource::<Gravity>().get(),
+            Vec3::new(0.0, -19.62, 0.0)
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use rand_distr::{Distribution, Normal};
+
+use crate::rapier_extern::rapier::prelude as rapier;
+use crate::{
+    collider::CollisionGroups,
+    scene_query::{SceneQuery, SceneQueryFilter},
+    timestep::PhysicsTime,
+};
+
+/// Component that measures distances with physics ray casts from the body it is added to.
+///
+/// The rays are spread evenly over the field of view in the local xz-plane of the body, centered
+/// on the direction. One ray works as a range sensor and several rays as a planar lidar.
+/// Rays that don't hit anything within the max range report the max range.
+#[derive(Component, Debug, Clone)]
+pub struct RangeSensor {
+    /// Number of rays
+    pub rays: usize,
+    /// Angle in radians covered by the rays
+    pub fov: f32,
+    /// Max distance that can be measured
+    pub max_range: rapier::Real,
+    /// Measurements per second of simulated time, measures every frame when None
+    pub update_rate: Option<f32>,
+    /// Standard deviation of the gaussian noise added to each measurement
+    pub noise: rapier::Real,
+    /// Position of the sensor relative to the body
+    pub offset: Vec3,
+    /// Direction of the center ray relative to the body, defaults to the forward direction -z
+    pub direction: Vec3,
+    /// Only colliders matching the groups are measured
+    pub groups: Option<CollisionGroups>,
+    ranges: Vec<rapier::Real>,
+    since_update: f32,
+}
+
+impl RangeSensor {
+    /// Sensor with a single ray
+    pub fn new(max_range: rapier::Real) -> Self {
+        Self::lidar(1, 0.0, max_range)
+    }
+
+    /// Sensor with `rays` rays spread over `fov` radians, use `TAU` for a full turn
+    pub fn lidar(rays: usize, fov: f32, max_range: rapier::Real) -> Self {
+        Self {
+            rays,
+            fov,
+            max_range,
+            update_rate: None,
+            noise: 0.0,
+            offset: Vec3::ZERO,
+            direction: Vec3::NEG_Z,
+            groups: None,
+            ranges: Vec::new(),
+            since_update: 0.0,
+        }
+    }
+
+    pub fn with_update_rate(mut self, update_rate: f32) -> Self {
+        self.update_rate = Some(update_rate);
+        self
+    }
+
+    pub fn with_noise(mut self, noise: rapier::Real) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Vec3) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_direction(mut self, direction: Vec3) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_groups(mut self, groups: CollisionGroups) -> Self {
+        self.groups = Some(groups);
+        self
+    }
+
+    /// Latest measurement of each ray, empty until the first measurement
+    pub fn ranges(&self) -> &[rapier::Real] {
+        &self.ranges
+    }
+
+    /// Direction of each ray relative to the body, ordered counterclockwise around the y-axis
+    pub fn ray_directions(&self) -> Vec<Vec3> {
+        if self.rays <= 1 {
+            return vec![self.direction];
+        }
+
+        // a full turn should not measure the same direction twice
+        let step = if self.fov >= TAU {
+            self.fov / self.rays as f32
+        } else {
+            self.fov / (self.rays - 1) as f32
+        };
+        (0..self.rays)
+            .map(|i| Quat::from_rotation_y(-self.fov / 2.0 + i as f32 * step) * self.direction)
+            .collect()
+    }
+
+    /// If a new measurement should be made after `dt` seconds of simulated time
+    fn should_update(&mut self, dt: f32) -> bool {
+        self.since_update += dt;
+        match self.update_rate {
+            Some(rate) if rate > 0.0 && !self.ranges.is_empty() => {
+                let period = 1.0 / rate;
+                if self.since_update < period {
+                    return false;
+                }
+                self.since_update %= period;
+                true
+            }
+            _ => {
+                self.since_update = 0.0;
+                true
+            }
+        }
+    }
+}
+
+/// System that casts the rays of the range sensors after the physics step
+pub(crate) fn update_range_sensors_system(
+    physics_time: Res<PhysicsTime>,
+    scene_query: SceneQuery,
+    mut query: Query<(Entity, &Transform, &mut RangeSensor)>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for (entity, transform, mut sensor) in query.iter_mut() {
+        if !sensor.should_update(physics_time.delta()) {
+            continue;
+        }
+
+        let filter = SceneQueryFilter {
+            groups: sensor.groups,
+            exclude_sensors: true,
+            exclude_body: Some(entity),
+        };
+        let noise = Normal::new(0.0, sensor.noise)
+            .ok()
+            .filter(|_| sensor.noise > 0.0);
+
+        let origin = transform.transform_point(sensor.offset);
+        let ranges = sensor
+            .ray_directions()
+            .into_iter()
+            .map(|direction| {
+                let range = scene_query
+                    .cast_ray(
+                        origin,
+                        transform.rotation * direction,
+                        sensor.max_range,
+                        filter,
+                    )
+                    .map_or(sensor.max_range, |hit| hit.distance);
+
+                match &noise {
+                    Some(noise) => (range + noise.sample(&mut rng)).clamp(0.0, sensor.max_range),
+                    None => range,
+                }
+            })
+            .collect();
+        sensor.ranges = ranges;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{collider::ColliderShape, rigid_body::RigidBody, PhysicsPlugin};
+
+    fn setup_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::ZERO,
+            ..default()
+        });
+
+        // wall 5 meters in front of the sensor
+        app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0.0, 0.0, -5.5)),
+            RigidBody::Fixed,
+            ColliderShape::Cuboid {
+                x_half: 2.0,
+                y_half: 2.0,
+                z_half: 0.5,
+            },
+        ));
+        app
+    }
+
+    fn spawn_sensor(app: &mut App, sensor: RangeSensor) -> Entity {
+        app.world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Fixed,
+                ColliderShape::Sphere { radius: 0.2 },
+                sensor,
+            ))
+            .id()
+    }
+
+    fn ranges(app: &App, entity: Entity) -> Vec<rapier::Real> {
+        app.world
+            .get::<RangeSensor>(entity)
+            .unwrap()
+            .ranges()
+            .to_vec()
+    }
+
+    #[test]
+    fn ray_directions() {
+        let sensor = RangeSensor::lidar(3, std::f32::consts::PI, 10.0);
+        let directions = sensor.ray_directions();
+        assert_eq!(directions.len(), 3);
+        assert!(directions[0].abs_diff_eq(Vec3::X, 1e-5));
+        assert!(directions[1].abs_diff_eq(Vec3::NEG_Z, 1e-5));
+        assert!(directions[2].abs_diff_eq(Vec3::NEG_X, 1e-5));
+
+        let sensor = RangeSensor::lidar(4, TAU, 10.0);
+        let directions = sensor.ray_directions();
+        assert!(!directions[0].abs_diff_eq(directions[3], 1e-5));
+    }
+
+    #[test]
+    fn measure_ranges() {
+        let mut app = setup_app();
+        let sensor = spawn_sensor(
+            &mut app,
+            RangeSensor::lidar(3, std::f32::consts::PI, 10.0)
+                .with_offset(Vec3::new(0.0, 0.0, -1.0)),
+        );
+        app.update();
+
+        // the sensor body is not measured, misses report the max range
+        let ranges = ranges(&app, sensor);
+        assert!((ranges[0] - 10.0).abs() < 1e-4);
+        assert!((ranges[1] - 4.0).abs() < 1e-4);
+        assert!((ranges[2] - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn update_rate_and_noise() {
+        let mut app = setup_app();
+        let sensor = spawn_sensor(
+            &mut app,
+            RangeSensor::new(10.0)
+                .with_update_rate(25.0)
+                .with_noise(0.1),
+        );
+        app.update();
+        let first = ranges(&app, sensor);
+        assert_eq!(first.len(), 1);
+        assert!((0.0..=10.0).contains(&first[0]));
+
+        // a physics step is 1/60 s so the next measurement is made on the third frame after
+        app.update();
+        app.update();
+        assert_eq!(ranges(&app, sensor), first);
+        app.update();
+        assert_ne!(ranges(&app, sensor), first);
+    }
+}
@@ -4,7 +4,10 @@ use fnv::FnvHashMap;
 use kesko_types::resource::KeskoRes;
 
 use crate::{conversions::IntoRapier, gravity::GravityScale};
-use crate::{mass::Mass, rapier_extern::rapier::prelude as rapier};
+use crate::{
+    mass::{Mass, MassProperties},
+    rapier_extern::rapier::prelude as rapier,
+};
 
 pub type Entity2Body = FnvHashMap<Entity, rapier::RigidBodyHandle>;
 pub type Body2Entity = FnvHashMap<rapier::RigidBodyHandle, Entity>;
@@ -13,6 +16,10 @@ pub type Body2Entity = FnvHashMap<rapier::RigidBodyHandle, Entity>;
 pub enum RigidBody {
     Fixed,
     Dynamic,
+    /// Moved by setting its next position, not affected by forces or contacts
+    KinematicPositionBased,
+    /// Moved by setting its velocity, not affected by forces or contacts
+    KinematicVelocityBased,
 }
 
 /// If a rigid body can sleep or not
@@ -34,16 +41,25 @@ pub(crate) fn add_rigid_bodies(
             &RigidBody,
             &Transform,
             Option<&Mass>,
+            Option<&MassProperties>,
             Option<&GravityScale>,
             Option<&CanSleep>,
         ),
         Without<RigidBodyHandle>,
     >,
 ) {
-    for (entity, rigid_body_comp, transform, mass, gravity_scale, can_sleep) in query.iter() {
+    for (entity, rigid_body_comp, transform, mass, mass_properties, gravity_scale, can_sleep) in
+        query.iter()
+    {
         let mut rigid_body_builder = match rigid_body_comp {
             RigidBody::Fixed => rapier::RigidBodyBuilder::fixed(),
             RigidBody::Dynamic => rapier::RigidBodyBuilder::dynamic(),
+            RigidBody::KinematicPositionBased => {
+                rapier::RigidBodyBuilder::kinematic_position_based()
+            }
+            RigidBody::KinematicVelocityBased => {
+                rapier::RigidBodyBuilder::kinematic_velocity_based()
+            }
         };
 
         if let Some(gravity_scale) = gravity_scale {
@@ -56,6 +72,10 @@ pub(crate) fn add_rigid_bodies(
         if let Some(mass) = mass {
             rigid_body_builder = rigid_body_builder.additional_mass(mass.val);
         }
+        if let Some(mass_properties) = mass_properties {
+            rigid_body_builder =
+                rigid_body_builder.additional_mass_properties((*mass_properties).into());
+        }
 
         let rigid_body = rigid_body_builder
             .position((transform.translation, transform.rotation).into_rapier())
@@ -76,11 +96,14 @@ pub(crate) fn add_rigid_bodies(
 #[cfg(test)]
 mod tests {
 
+    use crate::collider::{ColliderPhysicalProperties, ColliderShape};
     use crate::conversions::IntoBevy;
+    use crate::mass::MassProperties;
     use crate::rapier_extern::rapier::prelude as rapier;
     use crate::rigid_body::{
         add_rigid_bodies, Body2Entity, Entity2Body, RigidBody, RigidBodyHandle,
     };
+    use crate::PhysicsPlugin;
     use bevy::prelude::*;
     use kesko_types::resource::KeskoRes;
 
@@ -128,4 +151,44 @@ mod tests {
         assert_eq!(translation, expected_transform.translation);
         assert_eq!(rotation, expected_transform.rotation);
     }
+
+    #[test]
+    fn mass_properties() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::ZERO,
+            ..default()
+        });
+
+        let mass_properties = MassProperties {
+            mass: 2.0,
+            center_of_mass: Vec3::new(0.0, 0.5, 0.0),
+            inertia: Mat3::from_diagonal(Vec3::new(0.1, 0.2, 0.3)),
+        };
+        let entity = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(1.0, 0.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.5 },
+                ColliderPhysicalProperties {
+                    density: 0.0,
+                    ..default()
+                },
+                mass_properties,
+            ))
+            .id();
+        app.update();
+
+        let handle = app.world.get::<RigidBodyHandle>(entity).unwrap().0;
+        let rigid_body = &app.world.resource::<KeskoRes<rapier::RigidBodySet>>()[handle];
+
+        // the collider adds no mass so the body has exactly the given properties
+        assert_eq!(rigid_body.mass(), 2.0);
+        assert!(rigid_body
+            .center_of_mass()
+            .coords
+            .into_bevy()
+            .abs_diff_eq(Vec3::new(1.0, 0.5, 0.0), 1e-6));
+    }
 }
@@ -0,0 +1,396 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use kesko_types::resource::KeskoRes;
+
+use crate::rapier_extern::rapier::prelude as rapier;
+use crate::{
+    collider::{ColliderShape, CollisionGroups},
+    conversions::{IntoBevy, IntoRapier},
+    event::{PhysicRequestEvent, PhysicResponseEvent},
+    rigid_body::Entity2Body,
+};
+
+/// Filter for which colliders a scene query can hit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneQueryFilter {
+    /// Only colliders that are members of a group in the filter, and have one of the memberships
+    /// in their own filter, can be hit
+    pub groups: Option<CollisionGroups>,
+    pub exclude_sensors: bool,
+    /// Body to ignore, e.g. the body a ray is cast from
+    pub exclude_body: Option<Entity>,
+}
+
+/// Closest hit of a ray
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RayHit {
+    pub entity: Entity,
+    /// Distance along the normalized direction of the ray
+    pub distance: rapier::Real,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+/// First hit of a shape moving along a linear path
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ShapeHit {
+    pub entity: Entity,
+    /// Time until the hit, the shape has moved `velocity * toi` when it happens
+    pub toi: rapier::Real,
+    /// Contact point on the hit collider
+    pub point: Vec3,
+    /// Contact normal on the hit collider
+    pub normal: Vec3,
+}
+
+/// Closest point on a collider to a queried point
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PointProjection {
+    pub entity: Entity,
+    pub point: Vec3,
+    /// If the queried point is inside the collider
+    pub is_inside: bool,
+}
+
+/// Ray casts, shape casts, point projections and overlap tests against all colliders.
+///
+/// Queries use the collider positions of the last physics step, colliders added the same frame are included.
+#[derive(SystemParam)]
+pub struct SceneQuery<'w> {
+    query_pipeline: Res<'w, KeskoRes<rapier::QueryPipeline>>,
+    rigid_bodies: Res<'w, KeskoRes<rapier::RigidBodySet>>,
+    colliders: Res<'w, KeskoRes<rapier::ColliderSet>>,
+    entity_2_body: Res<'w, KeskoRes<Entity2Body>>,
+}
+
+impl SceneQuery<'_> {
+    /// Closest hit of a ray, the direction does not need to be normalized but a zero direction never hits
+    pub fn cast_ray(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: rapier::Real,
+        filter: SceneQueryFilter,
+    ) -> Option<RayHit> {
+        let direction = direction.try_normalize()?;
+        let ray = rapier::Ray::new(origin.into_rapier(), direction.into_rapier());
+        let (handle, intersection) = self.query_pipeline.cast_ray_and_get_normal(
+            &self.rigid_bodies,
+            &self.colliders,
+            &ray,
+            max_distance,
+            true,
+            self.rapier_filter(&filter),
+        )?;
+
+        Some(RayHit {
+            entity: self.entity(handle),
+            distance: intersection.toi,
+            point: ray.point_at(intersection.toi).coords.into_bevy(),
+            normal: intersection.normal.into_bevy(),
+        })
+    }
+
+    /// First hit of a shape moving from `transform` with a constant velocity
+    pub fn cast_shape(
+        &self,
+        shape: &ColliderShape,
+        transform: Transform,
+        velocity: Vec3,
+        max_toi: rapier::Real,
+        filter: SceneQueryFilter,
+    ) -> Option<ShapeHit> {
+        let shape = shape.shared_shape()?;
+        let (handle, toi) = self.query_pipeline.cast_shape(
+            &self.rigid_bodies,
+            &self.colliders,
+            &transform.into_rapier(),
+            &velocity.into_rapier(),
+            &*shape,
+            max_toi,
+            true,
+            self.rapier_filter(&filter),
+        )?;
+
+        // the witness and normal are in the local space of the hit collider
+        let collider_pos = self.colliders.get(handle)?.position();
+        Some(ShapeHit {
+            entity: self.entity(handle),
+            toi: toi.toi,
+            point: (collider_pos * toi.witness1).coords.into_bevy(),
+            normal: (collider_pos * toi.normal1.into_inner()).into_bevy(),
+        })
+    }
+
+    /// Closest point on any collider, points inside a collider project onto themselves
+    pub fn project_point(&self, point: Vec3, filter: SceneQueryFilter) -> Option<PointProjection> {
+        let (handle, projection) = self.query_pipeline.project_point(
+            &self.rigid_bodies,
+            &self.colliders,
+            &point.into_rapier(),
+            true,
+            self.rapier_filter(&filter),
+        )?;
+
+        Some(PointProjection {
+            entity: self.entity(handle),
+            point: projection.point.coords.into_bevy(),
+            is_inside: projection.is_inside,
+        })
+    }
+
+    /// All entities with a collider that overlaps the shape
+    pub fn overlaps(
+        &self,
+        shape: &ColliderShape,
+        transform: Transform,
+        filter: SceneQueryFilter,
+    ) -> Vec<Entity> {
+        let Some(shape) = shape.shared_shape() else {
+            return Vec::new();
+        };
+
+        let mut entities = Vec::new();
+        self.query_pipeline.intersections_with_shape(
+            &self.rigid_bodies,
+            &self.colliders,
+            &transform.into_rapier(),
+            &*shape,
+            self.rapier_filter(&filter),
+            |handle| {
+                entities.push(self.entity(handle));
+                true
+            },
+        );
+        entities
+    }
+
+    fn rapier_filter(&self, filter: &SceneQueryFilter) -> rapier::QueryFilter {
+        let mut rapier_filter = rapier::QueryFilter::new();
+        if let Some(groups) = filter.groups {
+            rapier_filter = rapier_filter.groups(groups.into());
+        }
+        if filter.exclude_sensors {
+            rapier_filter = rapier_filter.exclude_sensors();
+        }
+        if let Some(body) = filter
+            .exclude_body
+            .and_then(|entity| self.entity_2_body.get(&entity))
+        {
+            rapier_filter = rapier_filter.exclude_rigid_body(*body);
+        }
+        rapier_filter
+    }
+
+    /// The entity is stored as user data when the collider is added
+    fn entity(&self, handle: rapier::ColliderHandle) -> Entity {
+        Entity::from_bits(self.colliders[handle].user_data as u64)
+    }
+}
+
+/// Updates the query pipeline with the current collider positions, also when the physics is paused
+pub(crate) fn update_query_pipeline_system(
+    mut query_pipeline: ResMut<KeskoRes<rapier::QueryPipeline>>,
+    rigid_bodies: Res<KeskoRes<rapier::RigidBodySet>>,
+    colliders: Res<KeskoRes<rapier::ColliderSet>>,
+) {
+    query_pipeline.update(&rigid_bodies, &colliders);
+}
+
+pub(crate) fn handle_scene_query_events(
+    scene_query: SceneQuery,
+    mut request_events: EventReader<PhysicRequestEvent>,
+    mut response_events: EventWriter<PhysicResponseEvent>,
+) {
+    for event in request_events.iter() {
+        match event {
+            PhysicRequestEvent::Raycast {
+                origin,
+                direction,
+                max_distance,
+                filter,
+            } => {
+                let hit = scene_query.cast_ray(*origin, *direction, *max_distance, *filter);
+                response_events.send(PhysicResponseEvent::RaycastHit(hit));
+            }
+            PhysicRequestEvent::Overlap {
+                shape,
+                transform,
+                filter,
+            } => response_events.send(PhysicResponseEvent::Overlaps(
+                scene_query.overlaps(shape, *transform, *filter),
+            )),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{collider::Sensor, rigid_body::RigidBody, PhysicsPlugin};
+
+    fn setup_app() -> (App, Entity, Entity) {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin {
+            gravity: Vec3::ZERO,
+            ..default()
+        });
+
+        let ground = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Fixed,
+                ColliderShape::Cuboid {
+                    x_half: 5.0,
+                    y_half: 0.5,
+                    z_half: 5.0,
+                },
+                CollisionGroups::new(0b01, u32::MAX),
+            ))
+            .id();
+
+        let ball = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 3.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.5 },
+                CollisionGroups::new(0b10, u32::MAX),
+            ))
+            .id();
+
+        app.update();
+        (app, ground, ball)
+    }
+
+    fn cast_ray(app: &mut App, origin: Vec3, filter: SceneQueryFilter) -> Option<RayHit> {
+        let mut state = bevy::ecs::system::SystemState::<SceneQuery>::new(&mut app.world);
+        state
+            .get(&app.world)
+            .cast_ray(origin, Vec3::NEG_Y, 100.0, filter)
+    }
+
+    #[test]
+    fn ray_cast() {
+        let (mut app, ground, ball) = setup_app();
+
+        let hit = cast_ray(&mut app, Vec3::new(0.0, 10.0, 0.0), default()).unwrap();
+        assert_eq!(hit.entity, ball);
+        assert!((hit.distance - 6.5).abs() < 1e-4);
+        assert!(hit.point.abs_diff_eq(Vec3::new(0.0, 3.5, 0.0), 1e-4));
+        assert!(hit.normal.abs_diff_eq(Vec3::Y, 1e-4));
+
+        // only hit the ground group
+        let filter = SceneQueryFilter {
+            groups: Some(CollisionGroups::new(u32::MAX, 0b01)),
+            ..default()
+        };
+        let hit = cast_ray(&mut app, Vec3::new(0.0, 10.0, 0.0), filter).unwrap();
+        assert_eq!(hit.entity, ground);
+
+        let filter = SceneQueryFilter {
+            exclude_body: Some(ball),
+            ..default()
+        };
+        let hit = cast_ray(&mut app, Vec3::new(0.0, 10.0, 0.0), filter).unwrap();
+        assert_eq!(hit.entity, ground);
+
+        assert!(cast_ray(&mut app, Vec3::new(10.0, 10.0, 0.0), default()).is_none());
+
+        // a zero direction has no ray to cast
+        let mut state = bevy::ecs::system::SystemState::<SceneQuery>::new(&mut app.world);
+        assert!(state
+            .get(&app.world)
+            .cast_ray(Vec3::new(0.0, 10.0, 0.0), Vec3::ZERO, 100.0, default())
+            .is_none());
+    }
+
+    #[test]
+    fn shape_cast_and_point_projection() {
+        let (mut app, ground, ball) = setup_app();
+
+        let mut state = bevy::ecs::system::SystemState::<SceneQuery>::new(&mut app.world);
+        let scene_query = state.get(&app.world);
+
+        let hit = scene_query
+            .cast_shape(
+                &ColliderShape::Sphere { radius: 0.5 },
+                Transform::from_xyz(0.0, 10.0, 0.0),
+                Vec3::NEG_Y,
+                100.0,
+                default(),
+            )
+            .unwrap();
+        assert_eq!(hit.entity, ball);
+        assert!((hit.toi - 6.0).abs() < 1e-3);
+        assert!(hit.point.abs_diff_eq(Vec3::new(0.0, 3.5, 0.0), 1e-3));
+
+        let projection = scene_query
+            .project_point(Vec3::new(2.0, 1.0, 0.0), default())
+            .unwrap();
+        assert_eq!(projection.entity, ground);
+        assert!(!projection.is_inside);
+        assert!(projection.point.abs_diff_eq(Vec3::new(2.0, 0.5, 0.0), 1e-4));
+    }
+
+    #[test]
+    fn overlap_requests() {
+        let (mut app, ground, ball) = setup_app();
+
+        let sensor = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 1.5, 0.0)),
+                RigidBody::Fixed,
+                ColliderShape::Sphere { radius: 0.5 },
+                Sensor,
+            ))
+            .id();
+        app.update();
+
+        let shape = ColliderShape::Cuboid {
+            x_half: 1.0,
+            y_half: 2.0,
+            z_half: 1.0,
+        };
+        app.world.send_event(PhysicRequestEvent::Overlap {
+            shape: shape.clone(),
+            transform: Transform::from_xyz(0.0, 2.0, 0.0),
+            filter: default(),
+        });
+        app.world.send_event(PhysicRequestEvent::Overlap {
+            shape,
+            transform: Transform::from_xyz(0.0, 2.0, 0.0),
+            filter: SceneQueryFilter {
+                exclude_sensors: true,
+                ..default()
+            },
+        });
+        app.update();
+
+        let events = app.world.resource::<Events<PhysicResponseEvent>>();
+        let overlaps = events
+            .get_reader()
+            .iter(events)
+            .filter_map(|event| match event {
+                PhysicResponseEvent::Overlaps(entities) => {
+                    let mut entities = entities.clone();
+                    entities.sort();
+                    Some(entities)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut all = vec![ground, ball, sensor];
+        all.sort();
+        let mut without_sensor = vec![ground, ball];
+        without_sensor.sort();
+        assert_eq!(overlaps, vec![all, without_sensor]);
+    }
+}
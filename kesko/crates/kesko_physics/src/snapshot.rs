@@ -0,0 +1,625 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use kesko_types::resource::KeskoRes;
+
+use crate::conversions::IntoBevy;
+use crate::rapier_extern::rapier::prelude as rapier;
+use crate::{
+    collider::Entity2Collider,
+    event::{PhysicRequestEvent, PhysicResponseEvent},
+    force::Force,
+    impulse::Impulse,
+    imu::Imu,
+    joint::{
+        actuator::Actuator, prismatic::PrismaticJoint, revolute::RevoluteJoint,
+        spherical::SphericalJoint, Entity2JointHandle,
+    },
+    kinematic::KinematicDriver,
+    multibody::MultibodyRoot,
+    rigid_body::{Body2Entity, Entity2Body, RigidBodyHandle},
+};
+
+/// Snapshots of the physics world by id, the ids are not reused after a snapshot is deleted
+#[derive(Resource, Default)]
+pub struct Snapshots {
+    saved: HashMap<u64, Snapshot>,
+    next_id: u64,
+}
+
+pub(crate) struct Snapshot {
+    /// Serialized [`PhysicsSnapshot`]
+    physics: Vec<u8>,
+    /// Components that keep physics state between frames, by entity
+    components: HashMap<Entity, CachedComponents>,
+}
+
+/// Components with state from earlier frames, restored together with the physics world so the
+/// joint velocities, forces and actuators continue as they were when the snapshot was saved
+#[derive(Clone)]
+struct CachedComponents {
+    revolute_joint: Option<RevoluteJoint>,
+    prismatic_joint: Option<PrismaticJoint>,
+    spherical_joint: Option<SphericalJoint>,
+    /// Linear and angular velocity of a multibody root
+    multibody_vel: Option<(Vec3, Vec3)>,
+    force: Option<Force>,
+    impulse: Option<Impulse>,
+    actuator: Option<Actuator>,
+    kinematic_driver: Option<KinematicDriver>,
+    /// The velocity of the last measurement and the drifted biases
+    imu: Option<Imu>,
+}
+
+type CachedComponentsQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        Option<&'static mut RevoluteJoint>,
+        Option<&'static mut PrismaticJoint>,
+        Option<&'static mut SphericalJoint>,
+        Option<&'static mut MultibodyRoot>,
+        Option<&'static mut Force>,
+        Option<&'static mut Impulse>,
+        Option<&'static mut Actuator>,
+        Option<&'static mut KinematicDriver>,
+        Option<&'static mut Imu>,
+    ),
+    With<RigidBodyHandle>,
+>;
+
+fn save_components(query: &CachedComponentsQuery) -> HashMap<Entity, CachedComponents> {
+    query
+        .iter()
+        .map(
+            |(
+                entity,
+                revolute,
+                prismatic,
+                spherical,
+                root,
+                force,
+                impulse,
+                actuator,
+                driver,
+                imu,
+            )| {
+                let components = CachedComponents {
+                    revolute_joint: revolute.copied(),
+                    prismatic_joint: prismatic.copied(),
+                    spherical_joint: spherical.copied(),
+                    multibody_vel: root.map(|root| (root.linvel, root.angvel)),
+                    force: force.cloned(),
+                    impulse: impulse.cloned(),
+                    actuator: actuator.cloned(),
+                    kinematic_driver: driver.cloned(),
+                    imu: imu.cloned(),
+                };
+                (entity, components)
+            },
+        )
+        .collect()
+}
+
+/// Puts back the saved components without marking them as changed, a changed force or impulse
+/// would otherwise be applied again on top of the restored rapier state
+fn restore_components(
+    commands: &mut Commands,
+    query: &mut CachedComponentsQuery,
+    saved: &HashMap<Entity, CachedComponents>,
+) {
+    for (entity, revolute, prismatic, spherical, root, force, impulse, actuator, driver, imu) in
+        query.iter_mut()
+    {
+        let Some(saved) = saved.get(&entity).cloned() else {
+            continue;
+        };
+        restore_component(commands, entity, revolute, saved.revolute_joint);
+        restore_component(commands, entity, prismatic, saved.prismatic_joint);
+        restore_component(commands, entity, spherical, saved.spherical_joint);
+        restore_component(commands, entity, force, saved.force);
+        restore_component(commands, entity, impulse, saved.impulse);
+        restore_component(commands, entity, actuator, saved.actuator);
+        restore_component(commands, entity, driver, saved.kinematic_driver);
+        restore_component(commands, entity, imu, saved.imu);
+        if let (Some(mut root), Some((linvel, angvel))) = (root, saved.multibody_vel) {
+            let root = root.bypass_change_detection();
+            root.linvel = linvel;
+            root.angvel = angvel;
+        }
+    }
+}
+
+fn restore_component<T: Component>(
+    commands: &mut Commands,
+    entity: Entity,
+    current: Option<Mut<T>>,
+    saved: Option<T>,
+) {
+    match (current, saved) {
+        (Some(mut current), Some(saved)) => *current.bypass_change_detection() = saved,
+        (None, Some(saved)) => {
+            commands.entity(entity).insert(saved);
+        }
+        (_, None) => {}
+    }
+}
+
+/// Everything needed to put the physics world back into an earlier state
+#[derive(Serialize, Deserialize)]
+struct PhysicsSnapshot {
+    rigid_bodies: rapier::RigidBodySet,
+    colliders: rapier::ColliderSet,
+    impulse_joints: rapier::ImpulseJointSet,
+    multibody_joints: rapier::MultibodyJointSet,
+    islands: rapier::IslandManager,
+    broad_phase: rapier::BroadPhase,
+    narrow_phase: rapier::NarrowPhase,
+    ccd_solver: rapier::CCDSolver,
+    entity_2_body: Entity2Body,
+    body_2_entity: Body2Entity,
+    entity_2_collider: Entity2Collider,
+    entity_2_joint: Entity2JointHandle,
+}
+
+/// The rapier state and the maps between entities and rapier handles
+#[derive(SystemParam)]
+pub(crate) struct PhysicsWorld<'w> {
+    rigid_bodies: ResMut<'w, KeskoRes<rapier::RigidBodySet>>,
+    colliders: ResMut<'w, KeskoRes<rapier::ColliderSet>>,
+    impulse_joints: ResMut<'w, KeskoRes<rapier::ImpulseJointSet>>,
+    multibody_joints: ResMut<'w, KeskoRes<rapier::MultibodyJointSet>>,
+    islands: ResMut<'w, KeskoRes<rapier::IslandManager>>,
+    broad_phase: ResMut<'w, KeskoRes<rapier::BroadPhase>>,
+    narrow_phase: ResMut<'w, KeskoRes<rapier::NarrowPhase>>,
+    ccd_solver: ResMut<'w, KeskoRes<rapier::CCDSolver>>,
+    entity_2_body: ResMut<'w, KeskoRes<Entity2Body>>,
+    body_2_entity: ResMut<'w, KeskoRes<Body2Entity>>,
+    entity_2_collider: ResMut<'w, KeskoRes<Entity2Collider>>,
+    entity_2_joint: ResMut<'w, KeskoRes<Entity2JointHandle>>,
+}
+
+impl PhysicsWorld<'_> {
+    fn save(&self) -> Result<Vec<u8>, String> {
+        let snapshot = PhysicsSnapshot {
+            rigid_bodies: self.rigid_bodies.0.clone(),
+            colliders: self.colliders.0.clone(),
+            impulse_joints: self.impulse_joints.0.clone(),
+            multibody_joints: self.multibody_joints.0.clone(),
+            islands: self.islands.0.clone(),
+            broad_phase: self.broad_phase.0.clone(),
+            narrow_phase: self.narrow_phase.0.clone(),
+            ccd_solver: self.ccd_solver.0.clone(),
+            entity_2_body: self.entity_2_body.0.clone(),
+            body_2_entity: self.body_2_entity.0.clone(),
+            entity_2_collider: self.entity_2_collider.0.clone(),
+            entity_2_joint: self.entity_2_joint.0.clone(),
+        };
+        bincode::serialize(&snapshot).map_err(|e| format!("Failed to serialize snapshot: {}", e))
+    }
+
+    fn restore(&mut self, snapshot: PhysicsSnapshot) {
+        self.rigid_bodies.0 = snapshot.rigid_bodies;
+        self.colliders.0 = snapshot.colliders;
+        self.impulse_joints.0 = snapshot.impulse_joints;
+        self.multibody_joints.0 = snapshot.multibody_joints;
+        self.islands.0 = snapshot.islands;
+        self.broad_phase.0 = snapshot.broad_phase;
+        self.narrow_phase.0 = snapshot.narrow_phase;
+        self.ccd_solver.0 = snapshot.ccd_solver;
+        self.entity_2_body.0 = snapshot.entity_2_body;
+        self.body_2_entity.0 = snapshot.body_2_entity;
+        self.entity_2_collider.0 = snapshot.entity_2_collider;
+        self.entity_2_joint.0 = snapshot.entity_2_joint;
+    }
+
+    /// Moves the bodies to their restored positions right away instead of after the next step
+    fn update_transforms(&self, transforms: &mut Query<&mut Transform, With<RigidBodyHandle>>) {
+        for (entity, handle) in self.entity_2_body.iter() {
+            let (Ok(mut transform), Some(body)) =
+                (transforms.get_mut(*entity), self.rigid_bodies.get(*handle))
+            else {
+                continue;
+            };
+            let (translation, rotation) = body.position().into_bevy();
+            transform.translation = translation;
+            transform.rotation = rotation;
+        }
+    }
+}
+
+/// Restores the physics world and the cached components of a saved snapshot
+fn restore_snapshot(
+    saved: &Snapshot,
+    commands: &mut Commands,
+    physics_world: &mut PhysicsWorld,
+    bodies: &Query<Entity, With<RigidBodyHandle>>,
+    components: &mut CachedComponentsQuery,
+    transforms: &mut Query<&mut Transform, With<RigidBodyHandle>>,
+) -> Result<(), String> {
+    let snapshot = bincode::deserialize::<PhysicsSnapshot>(&saved.physics)
+        .map_err(|e| format!("Failed to deserialize snapshot: {}", e))?;
+
+    let entities = bodies.iter().collect::<HashSet<Entity>>();
+    if let Some(entity) = snapshot
+        .entity_2_body
+        .keys()
+        .find(|entity| !entities.contains(*entity))
+    {
+        return Err(format!("Entity {:?} has been despawned", entity));
+    }
+
+    for entity in entities.iter() {
+        if !snapshot.entity_2_body.contains_key(entity) {
+            commands.entity(*entity).despawn_recursive();
+        }
+    }
+
+    physics_world.restore(snapshot);
+    physics_world.update_transforms(transforms);
+    restore_components(commands, components, &saved.components);
+    Ok(())
+}
+
+/// Saves, restores and deletes snapshots of the physics world.
+///
+/// Besides the rapier state a snapshot contains the joint positions and velocities, the
+/// velocities of multibody roots, the forces, impulses, actuators, kinematic drivers and imus of
+/// the bodies. The transforms of the bodies are updated when restoring, so observations can be
+/// read before the next step.
+/// A snapshot can only be restored as long as the bodies it contains have not been despawned.
+/// Bodies spawned after the snapshot was taken are despawned when restoring it.
+/// Every snapshot keeps a copy of the whole world, delete the ones that are not needed anymore.
+/// Requests that fail are answered with [`PhysicResponseEvent::SnapshotFailed`].
+pub(crate) fn handle_snapshot_events(
+    mut commands: Commands,
+    mut physics_world: PhysicsWorld,
+    mut snapshots: ResMut<Snapshots>,
+    mut request_events: EventReader<PhysicRequestEvent>,
+    mut response_events: EventWriter<PhysicResponseEvent>,
+    bodies: Query<Entity, With<RigidBodyHandle>>,
+    mut components: CachedComponentsQuery,
+    mut transforms: Query<&mut Transform, With<RigidBodyHandle>>,
+) {
+    for event in request_events.iter() {
+        let response = match event {
+            PhysicRequestEvent::SaveSnapshot => physics_world
+                .save()
+                .map(|physics| {
+                    let id = snapshots.next_id;
+                    snapshots.next_id += 1;
+                    let components = save_components(&components);
+                    snapshots.saved.insert(
+                        id,
+                        Snapshot {
+                            physics,
+                            components,
+                        },
+                    );
+                    PhysicResponseEvent::SnapshotSaved(id)
+                })
+                .map_err(|e| (None, e)),
+            PhysicRequestEvent::RestoreSnapshot(id) => match snapshots.saved.get(id) {
+                Some(saved) => restore_snapshot(
+                    saved,
+                    &mut commands,
+                    &mut physics_world,
+                    &bodies,
+                    &mut components,
+                    &mut transforms,
+                ),
+                None => Err("No snapshot with that id".to_owned()),
+            }
+            .map(|()| PhysicResponseEvent::SnapshotRestored(*id))
+            .map_err(|e| (Some(*id), format!("Can't restore snapshot {}: {}", id, e))),
+            PhysicRequestEvent::DeleteSnapshot(id) => snapshots
+                .saved
+                .remove(id)
+                .map(|_| PhysicResponseEvent::SnapshotDeleted(*id))
+                .ok_or_else(|| (Some(*id), format!("No snapshot with id {} to delete", id))),
+            _ => continue,
+        };
+
+        match response {
+            Ok(response) => response_events.send(response),
+            Err((id, error)) => {
+                error!("{}", error);
+                response_events.send(PhysicResponseEvent::SnapshotFailed { id, error });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        collider::ColliderShape,
+        joint::{JointMotorEvent, KeskoAxis, MotorCommand},
+        kinematic::KinematicPath,
+        rigid_body::RigidBody,
+        PhysicsPlugin,
+    };
+
+    fn position(app: &App, entity: Entity) -> Vec3 {
+        app.world.get::<Transform>(entity).unwrap().translation
+    }
+
+    /// Root position and velocity, joint angle and joint velocity
+    fn state(app: &App, root: Entity, child: Entity) -> (Vec3, Vec3, f32, f32) {
+        let joint = app.world.get::<RevoluteJoint>(child).unwrap();
+        (
+            position(app, root),
+            app.world.get::<MultibodyRoot>(root).unwrap().linvel,
+            joint.rotation(),
+            joint.angular_velocity(),
+        )
+    }
+
+    #[test]
+    fn restore_snapshot() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        let body = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 10.0, 0.0)),
+                RigidBody::Dynamic,
+            ))
+            .id();
+
+        for _ in 0..5 {
+            app.update();
+        }
+
+        app.world.send_event(PhysicRequestEvent::SaveSnapshot);
+        app.update();
+        let saved = position(&app, body);
+
+        // a body spawned after the snapshot should be removed when restoring
+        let late_body = app
+            .world
+            .spawn((TransformBundle::default(), RigidBody::Dynamic))
+            .id();
+
+        for _ in 0..5 {
+            app.update();
+        }
+        let expected = position(&app, body);
+
+        // the snapshot is restored after this frame's physics step
+        app.world.send_event(PhysicRequestEvent::RestoreSnapshot(0));
+        app.update();
+        assert_eq!(position(&app, body), saved);
+        for _ in 0..5 {
+            app.update();
+        }
+
+        assert_eq!(position(&app, body), expected);
+        assert!(app.world.get_entity(late_body).is_none());
+        assert_eq!(
+            app.world.resource::<KeskoRes<rapier::RigidBodySet>>().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn restored_state_continues_as_before() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        let root = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 10.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.2 },
+                Force {
+                    vec: Vec3::new(1.0, 5.0, 0.0),
+                    ..default()
+                },
+            ))
+            .id();
+        let child = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 9.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.1 },
+                RevoluteJoint::attach_to(root)
+                    .with_parent_anchor(Transform::from_xyz(0.0, -1.0, 0.0))
+                    .with_axis(KeskoAxis::X)
+                    .with_motor_params(5.0, 1.0),
+                Actuator::default().with_latency(0.1).with_friction(0.1),
+            ))
+            .id();
+        for _ in 0..5 {
+            app.update();
+        }
+
+        // the command is still queued in the actuator when the snapshot is saved
+        app.world.send_event(JointMotorEvent {
+            entity: child,
+            command: MotorCommand::PositionRevolute {
+                position: 1.0,
+                stiffness: None,
+                damping: None,
+            },
+        });
+        app.world.send_event(PhysicRequestEvent::SaveSnapshot);
+        app.update();
+
+        let first_run = (0..20)
+            .map(|_| {
+                app.update();
+                state(&app, root, child)
+            })
+            .collect::<Vec<_>>();
+
+        app.world.send_event(PhysicRequestEvent::RestoreSnapshot(0));
+        app.update();
+        let second_run = (0..20)
+            .map(|_| {
+                app.update();
+                state(&app, root, child)
+            })
+            .collect::<Vec<_>>();
+
+        // the joint moved during the runs, so the queued command was replayed
+        assert!(first_run.last().unwrap().2.abs() > 0.1);
+        for (first, second) in first_run.iter().zip(second_run.iter()) {
+            assert!(first.0.abs_diff_eq(second.0, 1e-5));
+            assert!(first.1.abs_diff_eq(second.1, 1e-5));
+            assert!((first.2 - second.2).abs() < 1e-5);
+            assert!((first.3 - second.3).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn restore_kinematic_driver() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        let platform = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::KinematicPositionBased,
+                KinematicDriver::new(KinematicPath::Oscillate {
+                    amplitude: Vec3::X,
+                    frequency: 0.5,
+                    phase: 0.0,
+                }),
+            ))
+            .id();
+        for _ in 0..5 {
+            app.update();
+        }
+
+        app.world.send_event(PhysicRequestEvent::SaveSnapshot);
+        app.update();
+        let first_run = (0..10)
+            .map(|_| {
+                app.update();
+                position(&app, platform)
+            })
+            .collect::<Vec<_>>();
+
+        // the path time is restored as well, so the platform doesn't jump ahead
+        app.world.send_event(PhysicRequestEvent::RestoreSnapshot(0));
+        app.update();
+        let second_run = (0..10)
+            .map(|_| {
+                app.update();
+                position(&app, platform)
+            })
+            .collect::<Vec<_>>();
+
+        for (first, second) in first_run.iter().zip(second_run.iter()) {
+            assert!(first.abs_diff_eq(*second, 1e-5), "{} {}", first, second);
+        }
+    }
+
+    #[test]
+    fn restore_imu() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        let body = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, 10.0, 0.0)),
+                RigidBody::Dynamic,
+                ColliderShape::Sphere { radius: 0.5 },
+                Imu::default(),
+            ))
+            .id();
+        for _ in 0..3 {
+            app.update();
+        }
+
+        app.world.send_event(PhysicRequestEvent::SaveSnapshot);
+        app.update();
+        for _ in 0..10 {
+            app.update();
+        }
+
+        app.world.send_event(PhysicRequestEvent::RestoreSnapshot(0));
+        app.update();
+        app.update();
+
+        // still in free fall, the velocity before the restore is not used for the acceleration
+        let reading = app.world.get::<Imu>(body).unwrap().reading();
+        assert!(
+            reading.linear_acceleration.abs_diff_eq(Vec3::ZERO, 1e-3),
+            "{}",
+            reading.linear_acceleration
+        );
+    }
+
+    fn responses(app: &App) -> Vec<PhysicResponseEvent> {
+        app.world
+            .resource::<Events<PhysicResponseEvent>>()
+            .iter_current_update_events()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn restore_missing_snapshot() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+
+        app.world.send_event(PhysicRequestEvent::RestoreSnapshot(3));
+        app.update();
+
+        assert!(matches!(
+            responses(&app)[..],
+            [PhysicResponseEvent::SnapshotFailed { id: Some(3), .. }]
+        ));
+    }
+
+    #[test]
+    fn delete_snapshot() {
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin::default());
+        app.world
+            .spawn((TransformBundle::default(), RigidBody::Dynamic));
+
+        app.world.send_event(PhysicRequestEvent::SaveSnapshot);
+        app.update();
+        assert!(matches!(
+            responses(&app)[..],
+            [PhysicResponseEvent::SnapshotSaved(0)]
+        ));
+
+        app.world.send_event(PhysicRequestEvent::DeleteSnapshot(0));
+        app.update();
+        assert!(matches!(
+            responses(&app)[..],
+            [PhysicResponseEvent::SnapshotDeleted(0)]
+        ));
+        assert!(app.world.resource::<Snapshots>().saved.is_empty());
+
+        // deleted snapshots can't be restored or deleted again, and their ids are not reused
+        app.world.send_event(PhysicRequestEvent::RestoreSnapshot(0));
+        app.world.send_event(PhysicRequestEvent::DeleteSnapshot(0));
+        app.world.send_event(PhysicRequestEvent::SaveSnapshot);
+        app.update();
+        assert!(matches!(
+            responses(&app)[..],
+            [
+                PhysicResponseEvent::SnapshotFailed { id: Some(0), .. },
+                PhysicResponseEvent::SnapshotFailed { id: Some(0), .. },
+                PhysicResponseEvent::SnapshotSaved(1)
+            ]
+        ));
+    }
+}
@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+
+use crate::PhysicState;
+
+/// How the number of physics steps for each frame is decided.
+///
+/// Kinematic bodies are moved and the joint friction is updated before each pipeline step. The
+/// force fields, the actuator models and the motor commands are only updated once each frame and
+/// held during all the `steps * substeps` pipeline steps of the frame, like a controller running at
+/// the frame rate. With many steps each frame the drag therefore reacts late to changes in velocity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimestepMode {
+    /// Accumulate the real frame time and take as many steps as fits, used when running with a window
+    RealTime,
+    /// Take exactly this many steps for each update, used when running headless or in lockstep with a client
+    Fixed { steps_per_update: usize },
+}
+
+/// Keeps track of the physics time, controllers can use `delta` to get the simulated time of the last frame
+#[derive(Resource, Debug, Clone)]
+pub struct PhysicsTime {
+    /// Simulated time for each step
+    pub timestep: f32,
+    /// Number of pipeline steps each step is split into
+    pub substeps: usize,
+    /// Max steps for one frame when running in real time, so a slow frame doesn't make the next one slower
+    pub max_steps_per_frame: usize,
+    pub mode: TimestepMode,
+    accumulator: f32,
+    steps: usize,
+}
+
+impl PhysicsTime {
+    pub fn new(
+        timestep: f32,
+        substeps: usize,
+        max_steps_per_frame: usize,
+        mode: TimestepMode,
+    ) -> Self {
+        Self {
+            timestep,
+            substeps: substeps.max(1),
+            max_steps_per_frame,
+            mode,
+            accumulator: 0.0,
+            steps: 0,
+        }
+    }
+
+    /// Time step used by the physics pipeline
+    pub fn substep_dt(&self) -> f32 {
+        self.timestep / self.substeps.max(1) as f32
+    }
+
+    /// Number of steps taken during the current frame
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Simulated time during the current frame
+    pub fn delta(&self) -> f32 {
+        self.steps as f32 * self.timestep
+    }
+
+    /// Advances the time with the real frame time and returns the number of steps to take
+    fn advance(&mut self, frame_time: f32) -> usize {
+        self.steps = match self.mode {
+            TimestepMode::Fixed { steps_per_update } => steps_per_update,
+            TimestepMode::RealTime => {
+                self.accumulator += frame_time;
+                let steps =
+                    ((self.accumulator / self.timestep) as usize).min(self.max_steps_per_frame);
+                // keep the time left of the last step, or drop all of it if we could not catch up
+                self.accumulator = if steps == self.max_steps_per_frame {
+                    0.0
+                } else {
+                    self.accumulator - steps as f32 * self.timestep
+                };
+                steps
+            }
+        };
+        self.steps
+    }
+}
+
+impl Default for PhysicsTime {
+    fn default() -> Self {
+        Self::new(
+            1.0 / 60.0,
+            1,
+            4,
+            TimestepMode::Fixed {
+                steps_per_update: 1,
+            },
+        )
+    }
+}
+
+pub(crate) fn update_physics_time(
+    time: Option<Res<Time>>,
+    physic_state: Res<State<PhysicState>>,
+    mut physics_time: ResMut<PhysicsTime>,
+) {
+    match physic_state.get() {
+        PhysicState::Running => {
+            physics_time.advance(time.map_or(0.0, |time| time.delta_seconds()));
+        }
+        PhysicState::Stopped => physics_time.steps = 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn fixed_steps() {
+        let mut time = PhysicsTime::new(
+            0.01,
+            2,
+            4,
+            TimestepMode::Fixed {
+                steps_per_update: 3,
+            },
+        );
+
+        assert_eq!(time.advance(1.0), 3);
+        assert_eq!(time.advance(0.0), 3);
+        assert_eq!(time.substep_dt(), 0.005);
+        assert!((time.delta() - 0.03).abs() < 1e-6);
+    }
+
+    #[test]
+    fn real_time_steps() {
+        let mut time = PhysicsTime::new(0.01, 1, 4, TimestepMode::RealTime);
+
+        assert_eq!(time.advance(0.005), 0);
+        assert_eq!(time.advance(0.0251), 3);
+        // a long frame is capped and the remaining time is dropped
+        assert_eq!(time.advance(1.0), 4);
+        assert_eq!(time.advance(0.0), 0);
+        assert_eq!(time.advance(0.005), 0);
+        assert_eq!(time.advance(0.006), 1);
+    }
+}
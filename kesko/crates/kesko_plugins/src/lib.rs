@@ -9,7 +9,7 @@ use bevy::{
 
 use self::{main_camera::MainCameraPlugin, physics::DefaultPhysicsPlugin};
 use kesko_core::interaction::groups::{GroupDynamic, GroupStatic};
-use kesko_core::CorePlugin;
+use kesko_core::{CoreMinimalPlugin, CorePlugin};
 use kesko_models::ModelPlugin;
 pub use kesko_object_interaction::InteractionPlugin;
 pub use kesko_ui::UIPlugin;
@@ -64,3 +64,15 @@ impl Default for HeadlessRenderPlugins {
         }
     }
 }
+
+/// Plugins for running only the simulation, without any rendering
+pub struct HeadlessPhysicsPlugins;
+
+impl PluginGroup for HeadlessPhysicsPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(CoreMinimalPlugin)
+            .add(kesko_physics::PhysicsPlugin::default())
+            .add(ModelPlugin)
+    }
+}
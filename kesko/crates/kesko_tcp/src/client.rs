@@ -0,0 +1,311 @@
+use std::io;
+use std::net::{Shutdown, TcpStream};
+use std::sync::{
+    mpsc::{self, Receiver, Sender, TryRecvError},
+    Mutex,
+};
+use std::thread;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::frame::{self, Encoding};
+use crate::response::Response;
+use crate::TcpMode;
+
+/// Time a new client has to complete the handshake before the connection is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time an observer has to receive a frame, observers that can't keep up are dropped so they
+/// can't block the simulation
+pub(crate) const OBSERVER_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// What a client is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Role {
+    /// Sends requests and steps the simulation, there can only be one at the time
+    Controller,
+    /// Only receives the state and collision events for each step
+    Observer,
+}
+
+/// A connected client and the encoding it requested
+pub(crate) struct TcpClient {
+    stream: TcpStream,
+    encoding: Encoding,
+    pub(crate) address: String,
+}
+
+impl TcpClient {
+    /// Reads the handshake from a new client, a frame with the encoding optionally followed by `observer`,
+    /// e.g. `msgpack` or `json observer`. The handshake is echoed back when accepted.
+    pub(crate) fn handshake(mut stream: TcpStream) -> Result<(Self, Role), String> {
+        let address = match stream.peer_addr() {
+            Ok(addr) => addr.to_string(),
+            Err(_) => "Unknown".to_owned(),
+        };
+
+        // the listener is non-blocking, make sure the client is not
+        stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+        // frames are small and sent in lockstep, don't wait to fill up packets
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+
+        let handshake = frame::read_frame(&mut stream).map_err(|e| e.to_string())?;
+        let handshake = String::from_utf8_lossy(&handshake);
+        let mut parts = handshake.split_whitespace();
+
+        let encoding = Encoding::from_name(parts.next().unwrap_or_default())?;
+        let role = match parts.next() {
+            None | Some("controller") => Role::Controller,
+            Some("observer") => Role::Observer,
+            Some(role) => return Err(format!("Unknown role '{}'", role)),
+        };
+
+        frame::write_frame(&mut stream, handshake.as_bytes()).map_err(|e| e.to_string())?;
+        stream.set_read_timeout(None).map_err(|e| e.to_string())?;
+
+        Ok((
+            Self {
+                stream,
+                encoding,
+                address,
+            },
+            role,
+        ))
+    }
+
+    pub(crate) fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Sends fail instead of blocking for longer than the timeout, the connection should be
+    /// dropped after a failed send since only part of the frame may have been written
+    pub(crate) fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_write_timeout(timeout)
+    }
+
+    /// Blocks until the next frame is received
+    pub(crate) fn read_frame(&mut self) -> io::Result<Vec<u8>> {
+        frame::read_frame(&mut self.stream)
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, String> {
+        self.encoding.decode(data)
+    }
+
+    /// Encodes and sends the value as one frame
+    pub(crate) fn send<T: Serialize>(&mut self, value: &T) -> Result<(), String> {
+        let data = self.encoding.encode(value)?;
+        frame::write_frame(&mut self.stream, &data).map_err(|e| e.to_string())
+    }
+
+    /// Reads frames on a background thread and sends them over the returned channel.
+    /// The thread stops when the connection is closed.
+    fn spawn_reader(&self) -> io::Result<Receiver<io::Result<Vec<u8>>>> {
+        let mut stream = self.stream.try_clone()?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            let frame = frame::read_frame(&mut stream);
+            let failed = frame.is_err();
+            if sender.send(frame).is_err() || failed {
+                break;
+            }
+        });
+        Ok(receiver)
+    }
+
+    pub(crate) fn shutdown(&self) {
+        if let Err(e) = self.stream.shutdown(Shutdown::Both) {
+            warn!("Failed to shutdown connection to {}: {}", self.address, e);
+        }
+    }
+}
+
+/// The client stepping the simulation
+#[derive(Resource)]
+pub(crate) struct Controller {
+    pub(crate) client: TcpClient,
+    /// If a request has been read this frame that should get a response
+    pub(crate) awaiting_response: bool,
+    /// Responses to send with the response to the next request
+    pub(crate) pending: Vec<Response>,
+    /// Frames read on a background thread when free running
+    incoming: Option<Mutex<Receiver<io::Result<Vec<u8>>>>>,
+}
+
+impl Controller {
+    pub(crate) fn new(client: TcpClient, mode: TcpMode) -> io::Result<Self> {
+        let incoming = match mode {
+            TcpMode::LockStep => None,
+            TcpMode::FreeRunning => Some(Mutex::new(client.spawn_reader()?)),
+        };
+        Ok(Self {
+            client,
+            awaiting_response: false,
+            pending: Vec::new(),
+            incoming,
+        })
+    }
+
+    /// Next request frame, blocks in lock-step mode. Returns `None` when free running and
+    /// nothing has been received since the last frame.
+    pub(crate) fn next_frame(&mut self) -> Option<io::Result<Vec<u8>>> {
+        let Some(incoming) = &self.incoming else {
+            return Some(self.client.read_frame());
+        };
+
+        match incoming.lock().map(|receiver| receiver.try_recv()) {
+            Ok(Ok(frame)) => Some(frame),
+            Ok(Err(TryRecvError::Empty)) => None,
+            Ok(Err(TryRecvError::Disconnected)) => Some(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "Reader thread stopped",
+            ))),
+            Err(e) => Some(Err(io::Error::new(io::ErrorKind::Other, e.to_string()))),
+        }
+    }
+}
+
+/// Clients that only receive the states and collisions
+#[derive(Resource, Default)]
+pub(crate) struct Observers(pub(crate) Vec<TcpClient>);
+
+type HandshakeResult = Result<(TcpClient, Role), String>;
+
+/// Handshakes with new clients on background threads, so a slow client can't stall the simulation
+#[derive(Resource)]
+pub(crate) struct Handshakes {
+    sender: Mutex<Sender<HandshakeResult>>,
+    finished: Mutex<Receiver<HandshakeResult>>,
+}
+
+impl Default for Handshakes {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: Mutex::new(sender),
+            finished: Mutex::new(receiver),
+        }
+    }
+}
+
+impl Handshakes {
+    /// Starts the handshake with a new client
+    pub(crate) fn start(&self, stream: TcpStream) {
+        let Ok(sender) = self.sender.lock().map(|sender| sender.clone()) else {
+            error!("Failed to start handshake, the lock is poisoned");
+            return;
+        };
+        thread::spawn(move || {
+            // the receiver is only dropped together with the app
+            let _ = sender.send(TcpClient::handshake(stream));
+        });
+    }
+
+    /// Handshakes that have finished since the last call, never blocks
+    pub(crate) fn finished(&self) -> Vec<HandshakeResult> {
+        self.finished
+            .lock()
+            .map(|receiver| receiver.try_iter().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn connect(handshake: &str) -> (TcpStream, Result<(TcpClient, Role), String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        frame::write_frame(&mut stream, handshake.as_bytes()).unwrap();
+
+        let (server_stream, _) = listener.accept().unwrap();
+        (stream, TcpClient::handshake(server_stream))
+    }
+
+    #[test]
+    fn handshake() {
+        let (mut stream, result) = connect("json observer");
+        let (client, role) = result.unwrap();
+        assert_eq!(role, Role::Observer);
+        assert_eq!(client.encoding(), Encoding::Json);
+        assert_eq!(frame::read_frame(&mut stream).unwrap(), b"json observer");
+
+        let (_, result) = connect("msgpack");
+        assert_eq!(result.unwrap().1, Role::Controller);
+
+        assert!(connect("msgpack spectator").1.is_err());
+    }
+
+    #[test]
+    fn silent_client_does_not_block_handshakes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let handshakes = Handshakes::default();
+
+        // a client that never sends the handshake
+        let _silent = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        handshakes.start(listener.accept().unwrap().0);
+
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        frame::write_frame(&mut stream, b"json observer").unwrap();
+        handshakes.start(listener.accept().unwrap().0);
+
+        let finished = loop {
+            let finished = handshakes.finished();
+            if !finished.is_empty() {
+                break finished;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].as_ref().unwrap().1, Role::Observer);
+    }
+
+    #[test]
+    fn send_times_out() {
+        let (_stream, result) = connect("msgpack observer");
+        let (mut client, _) = result.unwrap();
+        client
+            .set_write_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+
+        // the other end never reads, so the buffers fill up and a send fails instead of blocking
+        let data = vec![0u8; 1 << 20];
+        assert!((0..1000).any(|_| client.send(&data).is_err()));
+    }
+
+    #[test]
+    fn free_running_controller() {
+        let (mut stream, result) = connect("msgpack");
+        let mut controller = Controller::new(result.unwrap().0, TcpMode::FreeRunning).unwrap();
+
+        // nothing sent yet, should not block
+        assert!(controller.next_frame().is_none());
+
+        frame::write_frame(&mut stream, b"request").unwrap();
+        let frame = loop {
+            if let Some(frame) = controller.next_frame() {
+                break frame.unwrap();
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(frame, b"request");
+
+        // the controller disconnecting is reported as an error
+        drop(stream);
+        let result = loop {
+            if let Some(result) = controller.next_frame() {
+                break result;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert!(result.is_err());
+    }
+}
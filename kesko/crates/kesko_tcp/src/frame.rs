@@ -0,0 +1,136 @@
+use std::io::{self, Read, Write};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Max size of a single frame, larger frames are rejected to not allocate huge buffers on bad input
+pub(crate) const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// Encoding used for the frames of a connection, decided by the client in the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    /// Compact JSON, mainly for debugging
+    Json,
+    /// MessagePack with named fields
+    MessagePack,
+}
+
+impl Encoding {
+    pub(crate) fn from_name(name: &str) -> Result<Self, String> {
+        match name.trim() {
+            "json" => Ok(Self::Json),
+            "msgpack" => Ok(Self::MessagePack),
+            name => Err(format!("Unknown encoding '{}'", name)),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::MessagePack => "msgpack",
+        }
+    }
+
+    pub(crate) fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, String> {
+        match self {
+            Self::Json => serde_json::from_slice(data).map_err(|e| e.to_string()),
+            Self::MessagePack => rmp_serde::from_slice(data).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Reads one frame, a big endian u32 with the length followed by the payload
+pub(crate) fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Frame of {} bytes is larger than {}", len, MAX_FRAME_LEN),
+        ));
+    }
+
+    let mut data = vec![0; len];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Writes one frame, see [`read_frame`]
+pub(crate) fn write_frame(writer: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    if data.len() > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Frame of {} bytes is larger than {}",
+                data.len(),
+                MAX_FRAME_LEN
+            ),
+        ));
+    }
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(data)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Command {
+        Close,
+        Apply { id: u64, values: HashMap<u64, f32> },
+    }
+
+    #[test]
+    fn frames() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, b"first").unwrap();
+        write_frame(&mut buffer, &[7; 5000]).unwrap();
+        assert_eq!(&buffer[..4], &[0, 0, 0, 5]);
+
+        let mut reader = Cursor::new(buffer);
+        assert_eq!(read_frame(&mut reader).unwrap(), b"first");
+        assert_eq!(read_frame(&mut reader).unwrap(), vec![7; 5000]);
+        assert!(read_frame(&mut reader).is_err());
+    }
+
+    #[test]
+    fn too_large_frame() {
+        let mut reader = Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        let err = read_frame(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn encodings() {
+        let commands = vec![
+            Command::Close,
+            Command::Apply {
+                id: u64::MAX,
+                values: (0..100).map(|i| (i, i as f32)).collect(),
+            },
+        ];
+
+        for name in ["json", "msgpack"] {
+            let encoding = Encoding::from_name(name).unwrap();
+            assert_eq!(encoding.name(), name);
+            let data = encoding.encode(&commands).unwrap();
+            assert_eq!(encoding.decode::<Vec<Command>>(&data).unwrap(), commands);
+        }
+        assert!(Encoding::from_name("xml").is_err());
+    }
+}
@@ -1,14 +1,23 @@
+mod client;
+mod frame;
 mod request;
 mod response;
 
+use std::io;
 use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
 
 use bevy::prelude::*;
 
-use kesko_types::resource::KeskoRes;
 use kesko_core::HandleEventsSet;
+use kesko_types::resource::KeskoRes;
+
+use client::{Controller, Handshakes, Observers, Role, TcpClient, OBSERVER_WRITE_TIMEOUT};
 
-const URL: &str = "127.0.0.1:8080";
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+/// How often to check for new connections while waiting for a controller
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(10);
 
 #[derive(Debug, Default, Clone, Eq, PartialEq, Hash, States)]
 enum TcpConnectionState {
@@ -17,31 +26,68 @@ enum TcpConnectionState {
     Connected,
 }
 
+/// How the simulation is driven by the controller
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TcpMode {
+    /// Each frame waits for a request from the controller, the app freezes in between
+    #[default]
+    LockStep,
+    /// The simulation keeps running and requests are handled as they arrive, at most one each frame.
+    /// The responses from the frames in between are sent with the response to the next request.
+    FreeRunning,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, SystemSet)]
 enum TcpSet {
     Request,
     Response,
 }
 
-struct TcpBuffer {
-    data: [u8; 2048],
+/// Plugin for adding a tcp server that will progress the simulation only when getting a step request.
+/// It is mainly used for the python API
+///
+/// Messages are sent as frames, a big endian u32 with the length of the payload followed by the payload.
+/// The first frame from the client is the name of the encoding to use for the rest of the connection,
+/// either `msgpack` or `json`, which the server echoes back when accepted.
+/// After that the client sends one request per frame and gets one response frame back for each.
+///
+/// By default the simulation is run in lock-step with the controller, see [`TcpMode`] for running freely.
+/// There is one controller that steps the simulation. When it disconnects the server waits for a new one.
+/// Clients connecting with `<encoding> observer` don't send any requests, they get a frame with
+/// the multibody states and collisions after each step instead. Observers that don't receive
+/// their frames in time are disconnected, and the handshakes are done on background threads,
+/// so neither can stall the simulation.
+pub struct TcpPlugin {
+    /// Address to listen on, e.g. `127.0.0.1:8080`
+    pub address: String,
+    pub mode: TcpMode,
 }
-impl TcpBuffer {
-    fn new() -> Self {
-        Self { data: [0; 2048] }
+
+impl Default for TcpPlugin {
+    fn default() -> Self {
+        Self {
+            address: DEFAULT_ADDRESS.to_owned(),
+            mode: TcpMode::default(),
+        }
     }
 }
 
-/// Plugin for adding a tcp server that will progress the simulation only when getting a step request.
-/// It is mainly used for the python API
-pub struct TcpPlugin;
 impl Plugin for TcpPlugin {
     fn build(&self, app: &mut App) {
-        match TcpListener::bind(URL) {
+        let listener = TcpListener::bind(&self.address).and_then(|listener| {
+            // don't block when checking for new observers
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        });
+
+        match listener {
             Ok(listener) => {
+                info!("TCP server listening on {}", self.address);
                 app.add_state::<TcpConnectionState>()
                     .insert_resource(KeskoRes(listener))
-                    .insert_resource(KeskoRes(TcpBuffer::new()))
+                    .insert_resource(self.mode)
+                    .init_resource::<Observers>()
+                    .init_resource::<Handshakes>()
                     .configure_set(First, TcpSet::Request)
                     .configure_set(Last, TcpSet::Response.after(HandleEventsSet))
                     .add_systems(
@@ -53,7 +99,7 @@ impl Plugin for TcpPlugin {
                     )
                     .add_systems(
                         First,
-                        request::handle_requests
+                        (accept_observers, request::handle_requests)
                             .run_if(in_state(TcpConnectionState::Connected))
                             .in_set(TcpSet::Request),
                     )
@@ -65,7 +111,7 @@ impl Plugin for TcpPlugin {
                     );
             }
             Err(e) => {
-                error!("{}", e)
+                error!("Could not listen on {}: {}", self.address, e)
             }
         }
     }
@@ -75,24 +121,98 @@ impl Plugin for TcpPlugin {
     }
 }
 
+/// Waits for a controller to connect, observers connecting in the meantime are added as well.
+/// When free running it only checks for new connections and lets the simulation continue.
 pub(crate) fn handle_incoming_connections(
     mut next_connection_state: ResMut<NextState<TcpConnectionState>>,
     mut commands: Commands,
     listener: Res<KeskoRes<TcpListener>>,
+    handshakes: Res<Handshakes>,
+    mode: Res<TcpMode>,
+    mut observers: ResMut<Observers>,
 ) {
-    info!("Waiting for TCP connection...");
-    match listener.accept() {
-        Ok((stream, _)) => {
-            let ip = match stream.peer_addr() {
-                Ok(ip) => ip.ip().to_string(),
-                Err(_) => "Unknown".to_owned(),
-            };
+    if *mode == TcpMode::LockStep {
+        info!("Waiting for TCP connection...");
+    }
+    loop {
+        accept_connections(&listener, &handshakes);
 
-            info!("TCP connection established with {}!", ip);
+        let mut controller = None;
+        for handshake in handshakes.finished() {
+            match handshake {
+                Ok((client, Role::Controller)) if controller.is_none() => {
+                    info!(
+                        "TCP connection established with {} using {}!",
+                        client.address,
+                        client.encoding().name()
+                    );
+                    match Controller::new(client, *mode) {
+                        Ok(new_controller) => controller = Some(new_controller),
+                        Err(e) => error!("Failed to setup controller: {}", e),
+                    }
+                }
+                Ok((client, Role::Controller)) => reject_controller(client),
+                Ok((client, Role::Observer)) => add_observer(&mut observers, client),
+                Err(e) => error!("TCP handshake failed: {}", e),
+            }
+        }
 
+        if let Some(controller) = controller {
             next_connection_state.set(TcpConnectionState::Connected);
-            commands.insert_resource(KeskoRes(stream));
+            commands.insert_resource(controller);
+            return;
+        }
+        match *mode {
+            TcpMode::LockStep => thread::sleep(ACCEPT_INTERVAL),
+            TcpMode::FreeRunning => return,
         }
-        Err(e) => error!("{}", e),
     }
 }
+
+/// Adds observers that connected since the last frame, only one controller is allowed at the time
+pub(crate) fn accept_observers(
+    listener: Res<KeskoRes<TcpListener>>,
+    handshakes: Res<Handshakes>,
+    mut observers: ResMut<Observers>,
+) {
+    accept_connections(&listener, &handshakes);
+    for handshake in handshakes.finished() {
+        match handshake {
+            Ok((client, Role::Observer)) => add_observer(&mut observers, client),
+            Ok((client, Role::Controller)) => reject_controller(client),
+            Err(e) => error!("TCP handshake failed: {}", e),
+        }
+    }
+}
+
+/// Starts the handshakes with the clients that connected since the last call
+fn accept_connections(listener: &TcpListener, handshakes: &Handshakes) {
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => handshakes.start(stream),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        }
+    }
+}
+
+fn add_observer(observers: &mut Observers, client: TcpClient) {
+    if let Err(e) = client.set_write_timeout(Some(OBSERVER_WRITE_TIMEOUT)) {
+        error!("Failed to setup observer {}: {}", client.address, e);
+        client.shutdown();
+        return;
+    }
+    info!("Observer connected from {}", client.address);
+    observers.0.push(client);
+}
+
+fn reject_controller(client: TcpClient) {
+    warn!(
+        "Rejecting controller {}, there is already a controller connected",
+        client.address
+    );
+    client.shutdown();
+}
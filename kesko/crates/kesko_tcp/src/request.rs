@@ -1,25 +1,46 @@
-use std::io::Read;
-use std::net::TcpStream;
-
 use bevy::{prelude::*, utils::hashbrown::HashMap};
 use serde::{Deserialize, Serialize};
 
 use kesko_core::event::SimulatorRequestEvent;
-use kesko_models::{Model, SpawnEvent};
-use kesko_physics::event::PhysicRequestEvent;
-use kesko_types::resource::KeskoRes;
+use kesko_models::{terrain::Terrain, Model, SpawnCollision, SpawnEvent};
+use kesko_physics::{
+    collider::ColliderShape,
+    event::PhysicRequestEvent,
+    force::ForcePoint,
+    force_field::{Drag, ForceFields},
+    randomization::RandomizationConfig,
+    rapier_extern::rapier::prelude as rapier,
+    scene_query::SceneQueryFilter,
+};
 
-use super::TcpBuffer;
+use crate::{
+    client::{Controller, Observers},
+    TcpConnectionState,
+};
 
-/// Commands that can be requested by http clients
+/// Commands that can be requested by tcp clients
 #[derive(Deserialize, Serialize, Debug)]
 pub(crate) enum TcpCommand {
     Close,
     GetState,
+    GetDepthImages,
     SpawnModel {
         model: Model,
         position: Vec3,
         color: Color,
+        #[serde(default)]
+        collision: SpawnCollision,
+    },
+    SpawnUrdf {
+        path: String,
+        position: Vec3,
+        #[serde(default)]
+        collision: SpawnCollision,
+    },
+    SpawnTerrain {
+        terrain: Terrain,
+        position: Vec3,
+        color: Color,
     },
     Despawn {
         id: u64,
@@ -30,119 +51,259 @@ pub(crate) enum TcpCommand {
         id: u64,
         command: HashMap<u64, f32>,
     },
+    /// Targets for the x, y and z axis of spherical joints
+    ApplySphericalMotorCommand {
+        id: u64,
+        command: HashMap<u64, Vec3>,
+    },
     PausePhysics,
     RunPhysics,
+    SaveSnapshot,
+    RestoreSnapshot {
+        id: u64,
+    },
+    DeleteSnapshot {
+        id: u64,
+    },
     IsAlive,
+    Raycast {
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: rapier::Real,
+        #[serde(default)]
+        filter: SceneQueryFilter,
+    },
+    Overlap {
+        shape: ColliderShape,
+        position: Vec3,
+        #[serde(default)]
+        rotation: Quat,
+        #[serde(default)]
+        filter: SceneQueryFilter,
+    },
+    SetRandomization {
+        config: RandomizationConfig,
+    },
+    Randomize,
+    /// Force on a body or a named link of a multibody, only acts during the next frame unless
+    /// persistent
+    ApplyForce {
+        id: u64,
+        #[serde(default)]
+        link: Option<String>,
+        force: Vec3,
+        #[serde(default)]
+        torque: Vec3,
+        #[serde(default)]
+        point: Option<ForcePoint>,
+        #[serde(default)]
+        persistent: bool,
+    },
+    ApplyImpulse {
+        id: u64,
+        #[serde(default)]
+        link: Option<String>,
+        impulse: Vec3,
+        #[serde(default)]
+        torque: Vec3,
+        #[serde(default)]
+        point: Option<ForcePoint>,
+    },
+    SetForceFields {
+        fields: ForceFields,
+    },
+    SetDrag {
+        id: u64,
+        #[serde(default)]
+        link: Option<String>,
+        drag: Drag,
+    },
 }
 
-/// Holds parsed http requests
+/// A request from a tcp client, the commands are handled in order
 #[derive(Debug, Deserialize, Serialize)]
-pub(crate) struct HttpRequest {
+pub(crate) struct TcpRequest {
     pub(crate) commands: Vec<TcpCommand>,
 }
 
-impl HttpRequest {
-    pub(crate) fn parse(request_str: String) -> Result<String, String> {
-        for line in request_str.lines() {
-            if line.starts_with('{') {
-                return Ok(line.to_owned());
-            }
-        }
-        Err("Failed to parse http request".to_owned())
-    }
-
-    pub(crate) fn from_http_str(req: String) -> Result<HttpRequest, String> {
-        match Self::parse(req) {
-            Ok(json) => match serde_json::from_str::<HttpRequest>(json.as_str()) {
-                Ok(req) => Ok(req),
-                Err(e) => Err(format!(
-                    "Failed to convert http request to SimHttpRequest: {}",
-                    e
-                )),
-            },
-            Err(e) => Err(e),
-        }
-    }
-}
-
 pub(crate) fn handle_requests(
-    mut tcp_stream: ResMut<KeskoRes<TcpStream>>,
-    mut tcp_buffer: ResMut<KeskoRes<TcpBuffer>>,
+    mut commands: Commands,
+    mut next_connection_state: ResMut<NextState<TcpConnectionState>>,
+    mut controller: ResMut<Controller>,
+    observers: Res<Observers>,
     mut system_event_writer: EventWriter<SimulatorRequestEvent>,
     mut spawn_event_writer: EventWriter<SpawnEvent>,
     mut physic_event_writer: EventWriter<PhysicRequestEvent>,
 ) {
-    info!("Waiting for request...");
-    let mut got_msg = false;
-    while !got_msg {
-        match tcp_stream.read(&mut tcp_buffer.data) {
-            Ok(msg_len) => {
-                if msg_len == 0 {
-                    continue;
+    // when free running only one request is handled each frame, so every request gets its own response
+    let request = match controller.next_frame() {
+        Some(Ok(data)) => {
+            // a bad request still gets a response so the client is not left waiting
+            controller.awaiting_response = true;
+            match controller.client.decode::<TcpRequest>(&data) {
+                Ok(request) => Some(request),
+                Err(e) => {
+                    error!("Failed to parse request: {}", e);
+                    None
                 }
+            }
+        }
+        None => None,
+        Some(Err(e)) => {
+            // wait for a new controller instead of shutting down
+            warn!(
+                "Controller {} disconnected: {}",
+                controller.client.address, e
+            );
+            commands.remove_resource::<Controller>();
+            next_connection_state.set(TcpConnectionState::NotConnected);
+            return;
+        }
+    };
+    let tcp_commands = match request {
+        Some(request) => {
+            info!("Got Request: {:?}", request.commands);
+            request.commands
+        }
+        None => Vec::new(),
+    };
 
-                got_msg = true;
-
-                let http_str = String::from_utf8_lossy(&tcp_buffer.data[..msg_len]).to_string();
-                match HttpRequest::from_http_str(http_str) {
-                    Ok(mut request) => {
-                        info!("Got Request: {:?}", request.commands);
+    // observers should get the state for every step, not only when the controller asks for it,
+    // that includes the frames without any request when free running
+    if !observers.0.is_empty()
+        && !tcp_commands
+            .iter()
+            .any(|command| matches!(command, TcpCommand::GetState))
+    {
+        system_event_writer.send(SimulatorRequestEvent::GetState);
+    }
 
-                        for command in request.commands.drain(..) {
-                            match command {
-                                TcpCommand::Close => {
-                                    system_event_writer.send(SimulatorRequestEvent::ExitApp)
-                                }
-                                TcpCommand::SpawnModel {
-                                    model,
-                                    position,
-                                    color,
-                                } => {
-                                    spawn_event_writer.send(SpawnEvent::Spawn {
-                                        model,
-                                        transform: Transform::from_translation(position),
-                                        color,
-                                    });
-                                }
-                                TcpCommand::GetState => {
-                                    system_event_writer.send(SimulatorRequestEvent::GetState)
-                                }
-                                TcpCommand::PausePhysics => {
-                                    physic_event_writer.send(PhysicRequestEvent::PausePhysics)
-                                }
-                                TcpCommand::RunPhysics => {
-                                    physic_event_writer.send(PhysicRequestEvent::RunPhysics)
-                                }
-                                TcpCommand::IsAlive => {
-                                    system_event_writer.send(SimulatorRequestEvent::IsAlive)
-                                }
-                                TcpCommand::ApplyMotorCommand { id, command } => {
-                                    system_event_writer.send(
-                                        SimulatorRequestEvent::ApplyMotorCommand {
-                                            entity: Entity::from_bits(id),
-                                            command,
-                                        },
-                                    )
-                                }
-                                TcpCommand::Despawn { id } => {
-                                    physic_event_writer.send(PhysicRequestEvent::DespawnBody(id))
-                                }
-                                TcpCommand::DespawnAll => {
-                                    physic_event_writer.send(PhysicRequestEvent::DespawnAll)
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        got_msg = false;
-                        error!("{}", e)
-                    }
-                }
+    for command in tcp_commands {
+        match command {
+            TcpCommand::Close => system_event_writer.send(SimulatorRequestEvent::ExitApp),
+            TcpCommand::SpawnModel {
+                model,
+                position,
+                color,
+                collision,
+            } => {
+                spawn_event_writer.send(SpawnEvent::Spawn {
+                    model,
+                    transform: Transform::from_translation(position),
+                    color,
+                    collision,
+                });
+            }
+            TcpCommand::SpawnUrdf {
+                path,
+                position,
+                collision,
+            } => {
+                spawn_event_writer.send(SpawnEvent::SpawnUrdf {
+                    path: path.into(),
+                    transform: Transform::from_translation(position),
+                    collision,
+                });
+            }
+            TcpCommand::SpawnTerrain {
+                terrain,
+                position,
+                color,
+            } => {
+                spawn_event_writer.send(SpawnEvent::SpawnTerrain {
+                    terrain,
+                    transform: Transform::from_translation(position),
+                    color,
+                });
+            }
+            TcpCommand::GetState => system_event_writer.send(SimulatorRequestEvent::GetState),
+            TcpCommand::GetDepthImages => {
+                system_event_writer.send(SimulatorRequestEvent::GetDepthImages)
+            }
+            TcpCommand::PausePhysics => physic_event_writer.send(PhysicRequestEvent::PausePhysics),
+            TcpCommand::RunPhysics => physic_event_writer.send(PhysicRequestEvent::RunPhysics),
+            TcpCommand::SaveSnapshot => physic_event_writer.send(PhysicRequestEvent::SaveSnapshot),
+            TcpCommand::RestoreSnapshot { id } => {
+                physic_event_writer.send(PhysicRequestEvent::RestoreSnapshot(id))
+            }
+            TcpCommand::DeleteSnapshot { id } => {
+                physic_event_writer.send(PhysicRequestEvent::DeleteSnapshot(id))
+            }
+            TcpCommand::IsAlive => system_event_writer.send(SimulatorRequestEvent::IsAlive),
+            TcpCommand::ApplyMotorCommand { id, command } => {
+                system_event_writer.send(SimulatorRequestEvent::ApplyMotorCommand {
+                    entity: Entity::from_bits(id),
+                    command,
+                })
+            }
+            TcpCommand::ApplySphericalMotorCommand { id, command } => {
+                system_event_writer.send(SimulatorRequestEvent::ApplySphericalMotorCommand {
+                    entity: Entity::from_bits(id),
+                    command,
+                })
+            }
+            TcpCommand::Despawn { id } => {
+                physic_event_writer.send(PhysicRequestEvent::DespawnBody(id))
+            }
+            TcpCommand::DespawnAll => physic_event_writer.send(PhysicRequestEvent::DespawnAll),
+            TcpCommand::Raycast {
+                origin,
+                direction,
+                max_distance,
+                filter,
+            } => physic_event_writer.send(PhysicRequestEvent::Raycast {
+                origin,
+                direction,
+                max_distance,
+                filter,
+            }),
+            TcpCommand::Overlap {
+                shape,
+                position,
+                rotation,
+                filter,
+            } => physic_event_writer.send(PhysicRequestEvent::Overlap {
+                shape,
+                transform: Transform::from_translation(position).with_rotation(rotation),
+                filter,
+            }),
+            TcpCommand::SetRandomization { config } => {
+                physic_event_writer.send(PhysicRequestEvent::SetRandomization(config))
+            }
+            TcpCommand::Randomize => physic_event_writer.send(PhysicRequestEvent::Randomize),
+            TcpCommand::ApplyForce {
+                id,
+                link,
+                force,
+                torque,
+                point,
+                persistent,
+            } => physic_event_writer.send(PhysicRequestEvent::ApplyForce {
+                id,
+                link,
+                force,
+                torque,
+                point,
+                persistent,
+            }),
+            TcpCommand::ApplyImpulse {
+                id,
+                link,
+                impulse,
+                torque,
+                point,
+            } => physic_event_writer.send(PhysicRequestEvent::ApplyImpulse {
+                id,
+                link,
+                impulse,
+                torque,
+                point,
+            }),
+            TcpCommand::SetForceFields { fields } => {
+                physic_event_writer.send(PhysicRequestEvent::SetForceFields(fields))
             }
-            Err(e) => {
-                error!("Could not read tcp stream: {}", e);
-                system_event_writer.send(SimulatorRequestEvent::ExitApp);
-                return;
+            TcpCommand::SetDrag { id, link, drag } => {
+                physic_event_writer.send(PhysicRequestEvent::SetDrag { id, link, drag })
             }
         }
     }
@@ -1,5 +1,4 @@
-use std::io::Write;
-use std::net::{Shutdown, TcpListener, TcpStream};
+use std::net::TcpListener;
 
 use bevy::prelude::*;
 
@@ -7,62 +6,164 @@ use kesko_core::event::SimulatorResponseEvent;
 use kesko_physics::event::{collision::CollisionEvent, PhysicResponseEvent};
 use kesko_types::resource::KeskoRes;
 
+use crate::{
+    client::{Controller, Observers},
+    TcpConnectionState,
+};
+
+/// Max number of responses kept for the controller between two requests, the oldest ones are
+/// dropped when a free running controller doesn't send requests for a long time
+const MAX_PENDING_RESPONSES: usize = 10_000;
+
+/// A response waiting to be sent to the controller
+pub(crate) enum Response {
+    Physic(PhysicResponseEvent),
+    Collision(CollisionEvent),
+    Simulator(SimulatorResponseEvent),
+}
+
+impl Response {
+    fn boxed(self) -> serde_traitobject::Box<dyn serde_traitobject::Any> {
+        match self {
+            Self::Physic(event) => serde_traitobject::Box::new(event),
+            Self::Collision(event) => serde_traitobject::Box::new(event),
+            Self::Simulator(event) => serde_traitobject::Box::new(event),
+        }
+    }
+}
+
+/// Sends the responses to the controller when it has made a request, and the states and
+/// collisions of this frame to the observers.
+///
+/// When free running the responses from the frames without a request are kept and sent together
+/// with the response to the next request.
 pub(crate) fn handle_responses(
     mut commands: Commands,
-    mut tcp_stream: ResMut<KeskoRes<TcpStream>>,
+    mut next_connection_state: ResMut<NextState<TcpConnectionState>>,
+    mut controller: ResMut<Controller>,
+    mut observers: ResMut<Observers>,
     mut response_events: EventReader<SimulatorResponseEvent>,
     mut physic_events: EventReader<PhysicResponseEvent>,
     mut collision_events: EventReader<CollisionEvent>,
 ) {
     let mut should_shutdown = false;
-    let mut responses: Vec<serde_traitobject::Box<dyn serde_traitobject::Any>> = Vec::new();
+    let mut observed: Vec<serde_traitobject::Box<dyn serde_traitobject::Any>> = Vec::new();
 
     for event in physic_events.iter() {
-        responses.push(serde_traitobject::Box::new(event.clone()));
+        controller.pending.push(Response::Physic(event.clone()));
     }
 
     for event in collision_events.iter() {
-        responses.push(serde_traitobject::Box::new(event.clone()));
+        controller.pending.push(Response::Collision(event.clone()));
+        observed.push(serde_traitobject::Box::new(event.clone()));
     }
 
     for event in response_events.iter() {
-        if let SimulatorResponseEvent::WillExitApp = event {
-            should_shutdown = true
+        match event {
+            SimulatorResponseEvent::WillExitApp => should_shutdown = true,
+            SimulatorResponseEvent::MultibodyStates(_) => {
+                observed.push(serde_traitobject::Box::new(event.clone()));
+                // the states requested for the observers on the frames in between are outdated
+                // by the time the controller gets them
+                if !controller.awaiting_response {
+                    continue;
+                }
+            }
+            _ => {}
         }
-        responses.push(serde_traitobject::Box::new(event.clone()));
+        controller.pending.push(Response::Simulator(event.clone()));
     }
 
-    if !responses.is_empty() {
-        match serde_json::to_string_pretty(&responses) {
-            Ok(json) => {
-                // create http response
-                let response = format!(
-                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nContent-Type: application/json\r\n\r\n{}",
-                    json.len(),
-                    json
-                );
-
-                // send response
-                info!("Sending response");
-                if let Err(e) = tcp_stream.write(response.as_bytes()) {
-                    error!("{:?}", e);
-                    return;
-                }
+    let pending = controller.pending.len();
+    if pending > MAX_PENDING_RESPONSES {
+        warn!(
+            "Dropping {} responses, the controller has not made a request in a long time",
+            pending - MAX_PENDING_RESPONSES
+        );
+        controller.pending.drain(..pending - MAX_PENDING_RESPONSES);
+    }
 
-                if let Err(e) = tcp_stream.flush() {
-                    error!("{:?}", e);
-                    return;
-                }
-            }
-            Err(e) => error!("{:?}", e),
+    // always respond, the client waits for one response for each request
+    if controller.awaiting_response {
+        controller.awaiting_response = false;
+        info!("Sending response");
+        let responses = controller
+            .pending
+            .drain(..)
+            .map(Response::boxed)
+            .collect::<Vec<_>>();
+        if let Err(e) = controller.client.send(&responses) {
+            warn!(
+                "Controller {} disconnected: {}",
+                controller.client.address, e
+            );
+            commands.remove_resource::<Controller>();
+            next_connection_state.set(TcpConnectionState::NotConnected);
+            return;
         }
     }
 
+    if !observed.is_empty() {
+        observers
+            .0
+            .retain_mut(|observer| match observer.send(&observed) {
+                Ok(()) => true,
+                Err(e) => {
+                    // also when the send timed out, part of the frame may have been written
+                    info!("Dropping observer {}: {}", observer.address, e);
+                    observer.shutdown();
+                    false
+                }
+            });
+    }
+
     if should_shutdown {
         info!("Shuting down TCP");
-        tcp_stream
-            .shutdown(Shutdown::Both)
-            .expect("tcp stream shutdown failed");
+        controller.client.shutdown();
+        for observer in observers.0.drain(..) {
+            observer.shutdown();
+        }
         commands.remove_resource::<KeskoRes<TcpListener>>();
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use std::net::TcpStream;
+
+    use super::*;
+    use crate::{client::TcpClient, frame, TcpMode};
+
+    #[test]
+    fn responses_are_kept_until_the_next_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        frame::write_frame(&mut stream, b"json").unwrap();
+        let (client, _) = TcpClient::handshake(listener.accept().unwrap().0).unwrap();
+        frame::read_frame(&mut stream).unwrap();
+
+        let mut app = App::new();
+        app.add_state::<TcpConnectionState>()
+            .add_event::<SimulatorResponseEvent>()
+            .add_event::<PhysicResponseEvent>()
+            .add_event::<CollisionEvent>()
+            .init_resource::<Observers>()
+            .insert_resource(Controller::new(client, TcpMode::FreeRunning).unwrap())
+            .add_systems(Update, handle_responses);
+
+        // a frame without any request from the controller
+        app.world.send_event(PhysicResponseEvent::SnapshotSaved(1));
+        app.update();
+        assert_eq!(app.world.resource::<Controller>().pending.len(), 1);
+
+        app.world.resource_mut::<Controller>().awaiting_response = true;
+        app.world.send_event(PhysicResponseEvent::SnapshotSaved(2));
+        app.update();
+        assert!(app.world.resource::<Controller>().pending.is_empty());
+
+        let response = String::from_utf8(frame::read_frame(&mut stream).unwrap()).unwrap();
+        assert!(response.contains(r#"{"SnapshotSaved":1}"#), "{}", response);
+        assert!(response.contains(r#"{"SnapshotSaved":2}"#), "{}", response);
+    }
+}
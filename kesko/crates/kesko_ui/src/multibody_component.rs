@@ -141,6 +141,14 @@ impl MultibodyUIComponent {
                                     KeskoAxis::Z => joint_data.current_val_z = val,
                                     _ => {}
                                 }
+                            } else if let Ok(joint) = spherical_joints.get(*joint_entity) {
+                                let rotation = joint.rotation();
+                                joint_data.current_val_x =
+                                    Self::smooth_joint_val(rotation.x.to_degrees());
+                                joint_data.current_val_y =
+                                    Self::smooth_joint_val(rotation.y.to_degrees());
+                                joint_data.current_val_z =
+                                    Self::smooth_joint_val(rotation.z.to_degrees());
                             }
                         }
                     }
@@ -96,6 +96,7 @@ impl SpawnComponent {
                         model: model.clone(),
                         transform: Transform::from_xyz(*x, *y, *z),
                         color: Color::rgb_u8(color.r(), color.g(), color.b()),
+                        collision: default(),
                     });
                     ui.close_menu();
                 }
@@ -0,0 +1,648 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bevy::prelude::*;
+
+/// Pose of an element relative to its parent frame, `<origin xyz="..." rpy="..."/>`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Origin {
+    pub xyz: Vec3,
+    /// fixed axis roll, pitch and yaw
+    pub rpy: Vec3,
+}
+
+impl Origin {
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(self.xyz).with_rotation(Quat::from_euler(
+            EulerRot::ZYX,
+            self.rpy.z,
+            self.rpy.y,
+            self.rpy.x,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Box {
+        size: Vec3,
+    },
+    /// Cylinder along the z-axis of its frame
+    Cylinder {
+        radius: f32,
+        length: f32,
+    },
+    Sphere {
+        radius: f32,
+    },
+    Mesh {
+        /// path to the mesh file, relative paths and ROS packages are resolved from the directory
+        /// of the URDF file if possible
+        filename: PathBuf,
+        scale: Vec3,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Visual {
+    pub origin: Origin,
+    pub geometry: Geometry,
+    pub color: Option<Color>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collision {
+    pub origin: Origin,
+    pub geometry: Geometry,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inertial {
+    /// Pose of the center of mass and the inertia frame relative to the link
+    pub origin: Origin,
+    pub mass: f32,
+    /// Inertia tensor around the center of mass, expressed in the frame given by `origin`
+    pub inertia: Mat3,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub name: String,
+    pub inertial: Option<Inertial>,
+    pub visual: Option<Visual>,
+    pub collision: Option<Collision>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JointType {
+    Revolute,
+    Continuous,
+    Prismatic,
+    Fixed,
+    Floating,
+    Planar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub lower: f32,
+    pub upper: f32,
+    pub effort: f32,
+    pub velocity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Dynamics {
+    pub damping: f32,
+    pub friction: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Joint {
+    pub name: String,
+    pub joint_type: JointType,
+    pub parent: String,
+    pub child: String,
+    pub origin: Origin,
+    /// Normalized joint axis expressed in the joint frame
+    pub axis: Vec3,
+    pub limit: Option<Limit>,
+    pub dynamics: Option<Dynamics>,
+}
+
+/// Robot description parsed from a URDF file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Robot {
+    pub name: String,
+    pub links: Vec<Link>,
+    pub joints: Vec<Joint>,
+}
+
+impl Robot {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let urdf = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read URDF file {}: {}", path.display(), e))?;
+        Self::parse(&urdf, path.parent())
+    }
+
+    /// Parses a URDF string, mesh filenames are resolved relative to `base_dir` if given
+    fn parse(urdf: &str, base_dir: Option<&Path>) -> Result<Self, String> {
+        let doc =
+            roxmltree::Document::parse(urdf).map_err(|e| format!("Failed to parse URDF: {}", e))?;
+
+        let robot = doc.root_element();
+        if !robot.has_tag_name("robot") {
+            return Err(format!(
+                "Expected root element 'robot', got '{}'",
+                robot.tag_name().name()
+            ));
+        }
+
+        // materials can be declared at the top level and referenced by name in the links
+        let mut materials = HashMap::<String, Color>::new();
+        for node in robot
+            .children()
+            .filter(|node| node.has_tag_name("material"))
+        {
+            if let (Some(name), Some(color)) = (node.attribute("name"), parse_color(&node)?) {
+                materials.insert(name.to_owned(), color);
+            }
+        }
+
+        let links = robot
+            .children()
+            .filter(|node| node.has_tag_name("link"))
+            .map(|node| parse_link(&node, &materials, base_dir))
+            .collect::<Result<Vec<Link>, String>>()?;
+
+        let joints = robot
+            .children()
+            .filter(|node| node.has_tag_name("joint"))
+            .map(|node| parse_joint(&node))
+            .collect::<Result<Vec<Joint>, String>>()?;
+
+        Ok(Self {
+            name: required_attribute(&robot, "name")?.to_owned(),
+            links,
+            joints,
+        })
+    }
+
+    pub fn link(&self, name: &str) -> Option<&Link> {
+        self.links.iter().find(|link| link.name == name)
+    }
+
+    /// The link that is not the child of any joint
+    pub fn root_link(&self) -> Result<&Link, String> {
+        let mut roots = self
+            .links
+            .iter()
+            .filter(|link| !self.joints.iter().any(|joint| joint.child == link.name));
+
+        match (roots.next(), roots.next()) {
+            (Some(root), None) => Ok(root),
+            (None, _) => Err(format!("Robot '{}' has no root link", self.name)),
+            (Some(_), Some(_)) => Err(format!("Robot '{}' has more than one root link", self.name)),
+        }
+    }
+
+    /// Joints that has the link as parent
+    pub fn child_joints<'a>(&'a self, link_name: &'a str) -> impl Iterator<Item = &'a Joint> {
+        self.joints
+            .iter()
+            .filter(move |joint| joint.parent == link_name)
+    }
+}
+
+impl FromStr for Robot {
+    type Err = String;
+
+    fn from_str(urdf: &str) -> Result<Self, Self::Err> {
+        Self::parse(urdf, None)
+    }
+}
+
+fn required_attribute<'a>(node: &roxmltree::Node<'a, '_>, name: &str) -> Result<&'a str, String> {
+    node.attribute(name).ok_or(format!(
+        "Element '{}' is missing attribute '{}'",
+        node.tag_name().name(),
+        name
+    ))
+}
+
+fn child<'a, 'input>(
+    node: &roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn parse_f32(val: &str) -> Result<f32, String> {
+    val.trim()
+        .parse::<f32>()
+        .map_err(|e| format!("Failed to parse '{}' as a number: {}", val, e))
+}
+
+fn parse_floats(val: &str) -> Result<Vec<f32>, String> {
+    val.split_whitespace().map(parse_f32).collect()
+}
+
+pub(crate) fn parse_vec3(val: &str) -> Result<Vec3, String> {
+    match parse_floats(val)?.as_slice() {
+        [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+        _ => Err(format!("Expected three values, got '{}'", val)),
+    }
+}
+
+fn parse_origin(node: &roxmltree::Node) -> Result<Origin, String> {
+    match child(node, "origin") {
+        Some(origin) => Ok(Origin {
+            xyz: origin.attribute("xyz").map_or(Ok(Vec3::ZERO), parse_vec3)?,
+            rpy: origin.attribute("rpy").map_or(Ok(Vec3::ZERO), parse_vec3)?,
+        }),
+        None => Ok(Origin::default()),
+    }
+}
+
+/// Parses a `<color rgba="..."/>` element under the node, if any
+fn parse_color(node: &roxmltree::Node) -> Result<Option<Color>, String> {
+    match child(node, "color").and_then(|color| color.attribute("rgba")) {
+        Some(rgba) => match parse_floats(rgba)?.as_slice() {
+            [r, g, b, a] => Ok(Some(Color::rgba(*r, *g, *b, *a))),
+            _ => Err(format!("Expected four values for rgba, got '{}'", rgba)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn parse_geometry(node: &roxmltree::Node, base_dir: Option<&Path>) -> Result<Geometry, String> {
+    let geometry = child(node, "geometry").ok_or(format!(
+        "Element '{}' is missing geometry",
+        node.tag_name().name()
+    ))?;
+
+    let shape = geometry
+        .children()
+        .find(|node| node.is_element())
+        .ok_or("Geometry is missing a shape")?;
+
+    match shape.tag_name().name() {
+        "box" => Ok(Geometry::Box {
+            size: parse_vec3(required_attribute(&shape, "size")?)?,
+        }),
+        "cylinder" => Ok(Geometry::Cylinder {
+            radius: parse_f32(required_attribute(&shape, "radius")?)?,
+            length: parse_f32(required_attribute(&shape, "length")?)?,
+        }),
+        "sphere" => Ok(Geometry::Sphere {
+            radius: parse_f32(required_attribute(&shape, "radius")?)?,
+        }),
+        "mesh" => {
+            let filename = required_attribute(&shape, "filename")?;
+            Ok(Geometry::Mesh {
+                filename: mesh_path(filename, base_dir),
+                scale: shape.attribute("scale").map_or(Ok(Vec3::ONE), parse_vec3)?,
+            })
+        }
+        other => Err(format!("Unsupported geometry '{}'", other)),
+    }
+}
+
+/// Resolves the filename of a mesh.
+///
+/// ROS `package://` filenames are looked up in the package directory, which is found by searching
+/// the directories above the URDF file for a directory with the name of the package. If there is
+/// none the package name is dropped and the rest of the path is taken relative to the URDF file.
+fn mesh_path(filename: &str, base_dir: Option<&Path>) -> PathBuf {
+    let relative_to_urdf = |path: &str| match base_dir {
+        Some(base_dir) => base_dir.join(path),
+        None => PathBuf::from(path),
+    };
+
+    if let Some(path) = filename.strip_prefix("file://") {
+        return relative_to_urdf(path);
+    }
+    let Some(path) = filename.strip_prefix("package://") else {
+        return relative_to_urdf(filename);
+    };
+
+    let (package, path) = path.split_once('/').unwrap_or(("", path));
+    let package_dir = base_dir.and_then(|base_dir| {
+        base_dir.ancestors().find_map(|dir| {
+            if dir.file_name().map_or(false, |name| name == package) {
+                Some(dir.to_path_buf())
+            } else {
+                let dir = dir.join(package);
+                dir.is_dir().then_some(dir)
+            }
+        })
+    });
+    match package_dir {
+        Some(package_dir) => package_dir.join(path),
+        None => relative_to_urdf(path),
+    }
+}
+
+fn parse_link(
+    node: &roxmltree::Node,
+    materials: &HashMap<String, Color>,
+    base_dir: Option<&Path>,
+) -> Result<Link, String> {
+    let name = required_attribute(node, "name")?.to_owned();
+
+    let inertial = match child(node, "inertial") {
+        Some(inertial) => Some(parse_inertial(&inertial, &name)?),
+        None => None,
+    };
+
+    // a link can be made up of several visual and collision elements, only the first ones are used
+    for element in ["visual", "collision"] {
+        let count = node
+            .children()
+            .filter(|child| child.has_tag_name(element))
+            .count();
+        if count > 1 {
+            warn!(
+                "Link '{}' has {} {} elements, only the first one is used",
+                name, count, element
+            );
+        }
+    }
+
+    let visual = match child(node, "visual") {
+        Some(visual) => {
+            // use the inline color if any, otherwise look up the named material
+            let color = match child(&visual, "material") {
+                Some(material) => match parse_color(&material)? {
+                    Some(color) => Some(color),
+                    None => material
+                        .attribute("name")
+                        .and_then(|name| materials.get(name))
+                        .copied(),
+                },
+                None => None,
+            };
+            Some(Visual {
+                origin: parse_origin(&visual)?,
+                geometry: parse_geometry(&visual, base_dir)?,
+                color,
+            })
+        }
+        None => None,
+    };
+
+    let collision = match child(node, "collision") {
+        Some(collision) => Some(Collision {
+            origin: parse_origin(&collision)?,
+            geometry: parse_geometry(&collision, base_dir)?,
+        }),
+        None => None,
+    };
+
+    Ok(Link {
+        name,
+        inertial,
+        visual,
+        collision,
+    })
+}
+
+fn parse_inertial(node: &roxmltree::Node, link_name: &str) -> Result<Inertial, String> {
+    let mass = child(node, "mass")
+        .ok_or(format!("Inertial of link '{}' is missing mass", link_name))
+        .and_then(|mass| required_attribute(&mass, "value"))
+        .and_then(parse_f32)?;
+    if !mass.is_finite() || mass <= 0.0 {
+        return Err(format!(
+            "Link '{}' has mass {}, it must be positive",
+            link_name, mass
+        ));
+    }
+
+    let inertia = child(node, "inertia").ok_or(format!(
+        "Inertial of link '{}' is missing inertia",
+        link_name
+    ))?;
+    let moment = |name| required_attribute(&inertia, name).and_then(parse_f32);
+    let (ixx, ixy, ixz) = (moment("ixx")?, moment("ixy")?, moment("ixz")?);
+    let (iyy, iyz, izz) = (moment("iyy")?, moment("iyz")?, moment("izz")?);
+
+    Ok(Inertial {
+        origin: parse_origin(node)?,
+        mass,
+        inertia: Mat3::from_cols(
+            Vec3::new(ixx, ixy, ixz),
+            Vec3::new(ixy, iyy, iyz),
+            Vec3::new(ixz, iyz, izz),
+        ),
+    })
+}
+
+fn parse_joint(node: &roxmltree::Node) -> Result<Joint, String> {
+    let name = required_attribute(node, "name")?.to_owned();
+
+    let joint_type = match required_attribute(node, "type")? {
+        "revolute" => JointType::Revolute,
+        "continuous" => JointType::Continuous,
+        "prismatic" => JointType::Prismatic,
+        "fixed" => JointType::Fixed,
+        "floating" => JointType::Floating,
+        "planar" => JointType::Planar,
+        other => return Err(format!("Joint '{}' has unknown type '{}'", name, other)),
+    };
+
+    let parent = child(node, "parent")
+        .ok_or(format!("Joint '{}' is missing parent", name))
+        .and_then(|parent| required_attribute(&parent, "link"))?
+        .to_owned();
+    let child_link = child(node, "child")
+        .ok_or(format!("Joint '{}' is missing child", name))
+        .and_then(|child| required_attribute(&child, "link"))?
+        .to_owned();
+
+    let axis = match child(node, "axis").and_then(|axis| axis.attribute("xyz")) {
+        Some(xyz) => parse_vec3(xyz)?
+            .try_normalize()
+            .ok_or(format!("Joint '{}' has a zero axis", name))?,
+        // default axis according to the URDF specification
+        None => Vec3::X,
+    };
+
+    let limit = match child(node, "limit") {
+        Some(limit) => Some(Limit {
+            lower: limit.attribute("lower").map_or(Ok(0.0), parse_f32)?,
+            upper: limit.attribute("upper").map_or(Ok(0.0), parse_f32)?,
+            effort: limit.attribute("effort").map_or(Ok(0.0), parse_f32)?,
+            velocity: limit.attribute("velocity").map_or(Ok(0.0), parse_f32)?,
+        }),
+        None => None,
+    };
+
+    let dynamics = match child(node, "dynamics") {
+        Some(dynamics) => Some(Dynamics {
+            damping: dynamics.attribute("damping").map_or(Ok(0.0), parse_f32)?,
+            friction: dynamics.attribute("friction").map_or(Ok(0.0), parse_f32)?,
+        }),
+        None => None,
+    };
+
+    Ok(Joint {
+        name,
+        joint_type,
+        parent,
+        child: child_link,
+        origin: parse_origin(node)?,
+        axis,
+        limit,
+        dynamics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URDF: &str = r#"
+        <robot name="arm">
+            <material name="blue">
+                <color rgba="0 0 1 1"/>
+            </material>
+            <link name="base">
+                <inertial>
+                    <origin xyz="0 0 0.05"/>
+                    <mass value="2.0"/>
+                    <inertia ixx="0.1" ixy="0.01" ixz="0" iyy="0.2" iyz="0" izz="0.3"/>
+                </inertial>
+                <visual>
+                    <geometry><box size="0.4 0.2 0.4"/></geometry>
+                    <material name="blue"/>
+                </visual>
+                <collision>
+                    <geometry><box size="0.4 0.2 0.4"/></geometry>
+                </collision>
+            </link>
+            <link name="upper_arm">
+                <collision>
+                    <origin xyz="0 0 0.25"/>
+                    <geometry><cylinder radius="0.05" length="0.5"/></geometry>
+                </collision>
+            </link>
+            <joint name="shoulder" type="revolute">
+                <parent link="base"/>
+                <child link="upper_arm"/>
+                <origin xyz="0 0 0.1" rpy="0 0 1.5"/>
+                <axis xyz="0 1 0"/>
+                <limit lower="-1.0" upper="1.0" effort="10" velocity="2"/>
+                <dynamics damping="0.5"/>
+            </joint>
+        </robot>
+    "#;
+
+    #[test]
+    fn parse_links_and_joints() {
+        let robot = Robot::from_str(URDF).unwrap();
+
+        assert_eq!(robot.name, "arm");
+        assert_eq!(robot.links.len(), 2);
+        assert_eq!(robot.joints.len(), 1);
+
+        let base = robot.link("base").unwrap();
+        let inertial = base.inertial.as_ref().unwrap();
+        assert_eq!(inertial.mass, 2.0);
+        assert_eq!(inertial.origin.xyz, Vec3::new(0.0, 0.0, 0.05));
+        assert_eq!(inertial.inertia.y_axis, Vec3::new(0.01, 0.2, 0.0));
+        assert_eq!(inertial.inertia.x_axis.y, inertial.inertia.y_axis.x);
+        assert_eq!(
+            base.collision.as_ref().unwrap().geometry,
+            Geometry::Box {
+                size: Vec3::new(0.4, 0.2, 0.4)
+            }
+        );
+        // named material is resolved from the top level materials
+        assert_eq!(
+            base.visual.as_ref().unwrap().color,
+            Some(Color::rgba(0.0, 0.0, 1.0, 1.0))
+        );
+
+        let joint = &robot.joints[0];
+        assert_eq!(joint.joint_type, JointType::Revolute);
+        assert_eq!(joint.parent, "base");
+        assert_eq!(joint.child, "upper_arm");
+        assert_eq!(joint.axis, Vec3::Y);
+        assert_eq!(joint.origin.xyz, Vec3::new(0.0, 0.0, 0.1));
+        assert_eq!(joint.origin.rpy, Vec3::new(0.0, 0.0, 1.5));
+
+        let limit = joint.limit.unwrap();
+        assert_eq!(limit.lower, -1.0);
+        assert_eq!(limit.upper, 1.0);
+        assert_eq!(limit.effort, 10.0);
+        assert_eq!(joint.dynamics.unwrap().damping, 0.5);
+    }
+
+    #[test]
+    fn root_link() {
+        let robot = Robot::from_str(URDF).unwrap();
+        assert_eq!(robot.root_link().unwrap().name, "base");
+        assert_eq!(robot.child_joints("base").count(), 1);
+        assert_eq!(robot.child_joints("upper_arm").count(), 0);
+    }
+
+    #[test]
+    fn invalid_urdf() {
+        assert!(Robot::from_str("<model name=\"arm\"/>").is_err());
+        assert!(Robot::from_str(
+            r#"<robot name="arm"><joint name="j" type="ball"><parent link="a"/><child link="b"/></joint></robot>"#
+        )
+        .is_err());
+        assert!(Robot::from_str(
+            r#"<robot name="arm"><joint name="j" type="revolute"><parent link="a"/><child link="b"/><axis xyz="0 0 0"/></joint></robot>"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn invalid_inertial() {
+        let link = |inertial: &str| {
+            Robot::from_str(&format!(
+                r#"<robot name="arm"><link name="base"><inertial>{}</inertial></link></robot>"#,
+                inertial
+            ))
+        };
+        let inertia = r#"<inertia ixx="1" ixy="0" ixz="0" iyy="1" iyz="0" izz="1"/>"#;
+
+        assert!(link(&format!(r#"<mass value="1"/>{}"#, inertia)).is_ok());
+        assert!(link(inertia).is_err());
+        assert!(link(&format!(r#"<mass value="0"/>{}"#, inertia)).is_err());
+        assert!(link(r#"<mass value="1"/>"#).is_err());
+    }
+
+    #[test]
+    fn package_mesh_path() {
+        let dir = std::env::temp_dir().join(format!("kesko_urdf_{}", std::process::id()));
+        let urdf_dir = dir.join("robot_description").join("urdf");
+        std::fs::create_dir_all(&urdf_dir).unwrap();
+        std::fs::create_dir_all(dir.join("robot_meshes")).unwrap();
+
+        // the package is a directory above the URDF file or next to one of them
+        assert_eq!(
+            mesh_path(
+                "package://robot_description/meshes/arm.stl",
+                Some(&urdf_dir)
+            ),
+            dir.join("robot_description/meshes/arm.stl")
+        );
+        assert_eq!(
+            mesh_path("package://robot_meshes/arm.stl", Some(&urdf_dir)),
+            dir.join("robot_meshes/arm.stl")
+        );
+        // unknown packages are dropped
+        assert_eq!(
+            mesh_path("package://unknown_package/meshes/arm.stl", Some(&urdf_dir)),
+            urdf_dir.join("meshes/arm.stl")
+        );
+        assert_eq!(
+            mesh_path("meshes/arm.stl", Some(&urdf_dir)),
+            urdf_dir.join("meshes/arm.stl")
+        );
+        assert_eq!(
+            mesh_path("file:///meshes/arm.stl", None),
+            PathBuf::from("/meshes/arm.stl")
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn origin_rpy() {
+        let origin = Origin {
+            xyz: Vec3::ZERO,
+            rpy: Vec3::new(0.0, 0.0, std::f32::consts::FRAC_PI_2),
+        };
+
+        // yaw rotates the x-axis into the y-axis
+        let rotated = origin.transform().rotation * Vec3::X;
+        assert!(rotated.abs_diff_eq(Vec3::Y, 1e-6));
+    }
+}
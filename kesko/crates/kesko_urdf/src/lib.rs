@@ -0,0 +1,6 @@
+pub mod description;
+mod mesh;
+mod spawn;
+
+pub use description::Robot;
+pub use spawn::spawn;
@@ -0,0 +1,160 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+
+use crate::description::parse_vec3;
+
+type Triangle = [Vec3; 3];
+
+/// Loads the mesh file of a `<mesh>` geometry, only STL files are supported
+pub(crate) fn load(path: &Path, scale: Vec3) -> Result<Mesh, String> {
+    let is_stl = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("stl"));
+    if !is_stl {
+        return Err(format!(
+            "Mesh file {} is not supported, only STL files are",
+            path.display()
+        ));
+    }
+
+    let bytes = std::fs::read(path)
+        .map_err(|e| format!("Failed to read mesh file {}: {}", path.display(), e))?;
+    let triangles = parse_stl(&bytes)?;
+    if triangles.is_empty() {
+        return Err(format!("Mesh file {} has no triangles", path.display()));
+    }
+
+    Ok(triangle_mesh(&triangles, scale))
+}
+
+fn parse_stl(bytes: &[u8]) -> Result<Vec<Triangle>, String> {
+    // binary files have an 80 byte header followed by the number of triangles and 50 bytes for
+    // each triangle, files that don't have that size are read as ASCII
+    if let Some(count) = bytes.get(80..84) {
+        let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+        if count.checked_mul(50).and_then(|len| len.checked_add(84)) == Some(bytes.len()) {
+            return Ok(parse_binary_stl(&bytes[84..]));
+        }
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(stl) if stl.trim_start().starts_with("solid") => parse_ascii_stl(stl),
+        _ => Err("Mesh file is neither a binary nor an ASCII STL file".to_owned()),
+    }
+}
+
+fn parse_binary_stl(data: &[u8]) -> Vec<Triangle> {
+    data.chunks_exact(50)
+        .map(|chunk| {
+            let float =
+                |i: usize| f32::from_le_bytes([chunk[i], chunk[i + 1], chunk[i + 2], chunk[i + 3]]);
+            // the first 12 bytes are the normal, it is computed from the vertices instead
+            let vertex = |i: usize| {
+                let start = 12 + 12 * i;
+                Vec3::new(float(start), float(start + 4), float(start + 8))
+            };
+            [vertex(0), vertex(1), vertex(2)]
+        })
+        .collect()
+}
+
+fn parse_ascii_stl(stl: &str) -> Result<Vec<Triangle>, String> {
+    let vertices = stl
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("vertex"))
+        .map(parse_vec3)
+        .collect::<Result<Vec<Vec3>, String>>()?;
+
+    if vertices.len() % 3 != 0 {
+        return Err(format!(
+            "STL file has {} vertices which can't be split into triangles",
+            vertices.len()
+        ));
+    }
+
+    Ok(vertices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect())
+}
+
+/// Flat shaded mesh where each triangle has its own vertices
+fn triangle_mesh(triangles: &[Triangle], scale: Vec3) -> Mesh {
+    let mut positions = Vec::with_capacity(3 * triangles.len());
+    let mut normals = Vec::with_capacity(3 * triangles.len());
+
+    for triangle in triangles {
+        let [a, b, c] = triangle.map(|vertex| vertex * scale);
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        positions.extend([a.to_array(), b.to_array(), c.to_array()]);
+        normals.extend([normal.to_array(); 3]);
+    }
+
+    let indices = (0..positions.len() as u32).collect();
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: Triangle = [Vec3::ZERO, Vec3::X, Vec3::Y];
+
+    #[test]
+    fn binary_stl() {
+        let mut stl = vec![0u8; 80];
+        stl.extend(1u32.to_le_bytes());
+        stl.extend([0u8; 12]);
+        for vertex in TRIANGLE {
+            stl.extend(vertex.to_array().iter().flat_map(|val| val.to_le_bytes()));
+        }
+        stl.extend([0u8; 2]);
+
+        assert_eq!(parse_stl(&stl).unwrap(), vec![TRIANGLE]);
+
+        // a truncated file is not a valid binary file and not text either
+        assert!(parse_stl(&stl[..stl.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn ascii_stl() {
+        let stl = "solid triangle
+            facet normal 0 0 1
+                outer loop
+                    vertex 0 0 0
+                    vertex 1 0 0
+                    vertex 0 1 0
+                endloop
+            endfacet
+        endsolid triangle";
+
+        assert_eq!(parse_stl(stl.as_bytes()).unwrap(), vec![TRIANGLE]);
+        assert!(parse_stl(b"solid broken\nvertex 0 0 0\nendsolid").is_err());
+    }
+
+    #[test]
+    fn scaled_mesh() {
+        let mesh = triangle_mesh(&[TRIANGLE], Vec3::new(2.0, 3.0, 1.0));
+
+        assert_eq!(
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+                .unwrap()
+                .as_float3()
+                .unwrap(),
+            &[[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 3.0, 0.0]]
+        );
+        assert_eq!(
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+                .unwrap()
+                .as_float3()
+                .unwrap(),
+            &[[0.0, 0.0, 1.0]; 3]
+        );
+    }
+}
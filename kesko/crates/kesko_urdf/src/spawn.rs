@@ -0,0 +1,487 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+
+use kesko_core::{
+    bundle::MeshPhysicBodyBundle,
+    interaction::groups::GroupDynamic,
+    shape::{MeshCollider, Shape},
+};
+use kesko_object_interaction::InteractiveBundle;
+use kesko_physics::{
+    collider::ColliderPhysicalProperties,
+    joint::{fixed::FixedJoint, prismatic::PrismaticJoint, revolute::RevoluteJoint, KeskoAxis},
+    mass::MassProperties,
+    rapier_extern::rapier::prelude as rapier,
+    rigid_body::RigidBody,
+};
+
+use crate::description::{Geometry, Joint, JointType, Link, Robot};
+use crate::mesh;
+
+/// Motor stiffness used for the joints since URDF does not describe motors
+const DEFAULT_STIFFNESS: rapier::Real = 1.0;
+/// Motor damping used if the joint does not specify any dynamics
+const DEFAULT_DAMPING: rapier::Real = 0.2;
+/// Radius of the collider used for links without any supported geometry
+const PLACEHOLDER_RADIUS: f32 = 0.01;
+
+/// Spawns a robot described by URDF as a multibody and returns the root entity.
+///
+/// URDF uses the z-axis as up while Kesko uses the y-axis, the robot is therefore rotated so that
+/// its z-axis points along the world y-axis before `transform` is applied.
+pub fn spawn(
+    commands: &mut Commands,
+    robot: &Robot,
+    transform: Transform,
+    materials: &mut Assets<StandardMaterial>,
+    meshes: &mut Assets<Mesh>,
+) -> Result<Entity, String> {
+    let root = robot.root_link()?;
+    let root_transform = transform * Transform::from_rotation(Quat::from_rotation_x(-FRAC_PI_2));
+    let default_material = materials.add(Color::GRAY.into());
+
+    let root_body_transform = root_transform * geometry_transform(root);
+    let root_entity = spawn_link(
+        commands,
+        root,
+        &robot.name,
+        root_body_transform,
+        &default_material,
+        materials,
+        meshes,
+    )
+    .ok_or(format!(
+        "Root link '{}' could not be made into a rigid body",
+        root.name
+    ))?;
+
+    // walk the kinematic tree from the root, keeping track of the link frame and the body frame
+    let mut stack = vec![(root, root_entity, root_transform, root_body_transform)];
+    while let Some((link, entity, link_transform, body_transform)) = stack.pop() {
+        for joint in robot.child_joints(&link.name) {
+            let child = robot.link(&joint.child).ok_or(format!(
+                "Joint '{}' has unknown child link '{}'",
+                joint.name, joint.child
+            ))?;
+
+            let child_link_transform = link_transform * joint.origin.transform();
+            let child_body_transform = child_link_transform * geometry_transform(child);
+
+            let Some(child_entity) = spawn_link(
+                commands,
+                child,
+                &joint.name,
+                child_body_transform,
+                &default_material,
+                materials,
+                meshes,
+            ) else {
+                error!(
+                    "Link '{}' could not be made into a rigid body, skipping it and its children",
+                    child.name
+                );
+                continue;
+            };
+
+            attach_joint(
+                commands,
+                joint,
+                entity,
+                child_entity,
+                body_transform,
+                child_link_transform,
+                child_body_transform,
+            );
+
+            stack.push((
+                child,
+                child_entity,
+                child_link_transform,
+                child_body_transform,
+            ));
+        }
+    }
+
+    Ok(root_entity)
+}
+
+/// Spawns the rigid body of a link, None if its shape can't be made into a mesh and a collider
+fn spawn_link(
+    commands: &mut Commands,
+    link: &Link,
+    name: &str,
+    transform: Transform,
+    default_material: &Handle<StandardMaterial>,
+    materials: &mut Assets<StandardMaterial>,
+    meshes: &mut Assets<Mesh>,
+) -> Option<Entity> {
+    let material = match link.visual.as_ref().and_then(|visual| visual.color) {
+        Some(color) => materials.add(color.into()),
+        None => default_material.clone(),
+    };
+
+    let bundle = MeshPhysicBodyBundle::try_from(
+        RigidBody::Dynamic,
+        shape_from_link(link),
+        material,
+        transform,
+        meshes,
+    )?;
+    let mut entity = commands.spawn((
+        bundle,
+        InteractiveBundle::<GroupDynamic>::default(),
+        Name::new(name.to_owned()),
+    ));
+
+    // the body gets the mass and inertia of the link, its collider adds none
+    if let Some(inertial) = &link.inertial {
+        let inertial_frame =
+            relative_transform(&geometry_transform(link), &inertial.origin.transform());
+        let rotation = Mat3::from_quat(inertial_frame.rotation);
+        entity.insert((
+            MassProperties {
+                mass: inertial.mass as rapier::Real,
+                center_of_mass: inertial_frame.translation,
+                inertia: rotation * inertial.inertia * rotation.transpose(),
+            },
+            ColliderPhysicalProperties {
+                density: 0.0,
+                ..default()
+            },
+        ));
+    }
+
+    Some(entity.id())
+}
+
+/// Adds the Kesko joint component corresponding to the URDF joint.
+///
+/// The joint frame is placed at the child link origin and oriented as the child body, that way the
+/// joint axis is expressed in the child body frame. Kesko joints turn around or slide along one of
+/// the principal axes of the joint frame, axes that are not aligned with the child body therefore
+/// get a joint frame rotated so its x-axis is along the joint axis.
+fn attach_joint(
+    commands: &mut Commands,
+    joint: &Joint,
+    parent: Entity,
+    child: Entity,
+    parent_body_transform: Transform,
+    child_link_transform: Transform,
+    child_body_transform: Transform,
+) {
+    let axis =
+        child_body_transform.rotation.inverse() * (child_link_transform.rotation * joint.axis);
+    let (axis_rotation, axis) = match principal_axis(axis) {
+        Some(axis) => (Quat::IDENTITY, axis),
+        None => (
+            Quat::from_rotation_arc(Vec3::X, axis.normalize()),
+            KeskoAxis::X,
+        ),
+    };
+
+    let joint_transform = Transform::from_translation(child_link_transform.translation)
+        .with_rotation(child_body_transform.rotation * axis_rotation);
+    let parent_anchor = relative_transform(&parent_body_transform, &joint_transform);
+    let child_anchor = relative_transform(&child_body_transform, &joint_transform);
+
+    let damping = joint
+        .dynamics
+        .map_or(DEFAULT_DAMPING, |dynamics| dynamics.damping as rapier::Real);
+
+    match joint.joint_type {
+        JointType::Revolute | JointType::Continuous => {
+            let mut revolute = RevoluteJoint::attach_to(parent)
+                .with_parent_anchor(parent_anchor)
+                .with_child_anchor(child_anchor)
+                .with_axis(axis)
+                .with_motor_params(DEFAULT_STIFFNESS, damping);
+
+            if let Some(limit) = joint.limit {
+                if joint.joint_type == JointType::Revolute {
+                    revolute = revolute.with_limits(Vec2::new(limit.lower, limit.upper));
+                }
+                if limit.effort > 0.0 {
+                    revolute = revolute.with_max_motor_force(limit.effort as rapier::Real);
+                }
+            }
+            commands.entity(child).insert(revolute);
+        }
+        JointType::Prismatic => {
+            let mut prismatic = PrismaticJoint::attach_to(parent)
+                .with_parent_anchor(parent_anchor)
+                .with_child_anchor(child_anchor)
+                .with_axis(axis)
+                .with_motor_params(DEFAULT_STIFFNESS, damping);
+
+            if let Some(limit) = joint.limit {
+                prismatic = prismatic.with_limits(Vec2::new(limit.lower, limit.upper));
+                if limit.effort > 0.0 {
+                    prismatic = prismatic.with_max_motor_force(limit.effort as rapier::Real);
+                }
+            }
+            commands.entity(child).insert(prismatic);
+        }
+        JointType::Fixed => {
+            commands.entity(child).insert(
+                FixedJoint::attach_to(parent)
+                    .with_parent_anchor(parent_anchor)
+                    .with_child_anchor(child_anchor),
+            );
+        }
+        JointType::Floating | JointType::Planar => {
+            warn!(
+                "Joint '{}' is of type {:?} which is not supported, the child will be a free body",
+                joint.name, joint.joint_type
+            );
+        }
+    }
+}
+
+/// Transformation from the link frame to the frame of its rigid body.
+///
+/// The rigid body is placed at the collision origin since colliders can't be offset from their body.
+/// URDF cylinders are aligned along z while Kesko cylinders are aligned along y.
+fn geometry_transform(link: &Link) -> Transform {
+    let (origin, geometry) = match (&link.collision, &link.visual) {
+        (Some(collision), _) => (collision.origin, &collision.geometry),
+        (None, Some(visual)) => (visual.origin, &visual.geometry),
+        (None, None) => return Transform::default(),
+    };
+
+    match geometry {
+        Geometry::Cylinder { .. } => {
+            origin.transform() * Transform::from_rotation(Quat::from_rotation_x(FRAC_PI_2))
+        }
+        _ => origin.transform(),
+    }
+}
+
+fn shape_from_link(link: &Link) -> Shape {
+    let geometry = link
+        .collision
+        .as_ref()
+        .map(|collision| &collision.geometry)
+        .or(link.visual.as_ref().map(|visual| &visual.geometry));
+
+    match geometry {
+        Some(Geometry::Box { size }) => Shape::Box {
+            x_length: size.x,
+            y_length: size.y,
+            z_length: size.z,
+        },
+        Some(Geometry::Cylinder { radius, length }) => Shape::Cylinder {
+            radius: *radius,
+            length: *length,
+            resolution: 32,
+        },
+        Some(Geometry::Sphere { radius }) => Shape::Sphere {
+            radius: *radius,
+            subdivisions: 5,
+        },
+        Some(Geometry::Mesh { filename, scale }) => match mesh::load(filename, *scale) {
+            // a convex hull keeps the collisions of dynamic bodies fast and stable
+            Ok(mesh) => Shape::Mesh {
+                mesh,
+                collider: MeshCollider::ConvexHull,
+            },
+            Err(e) => {
+                warn!("{}, using a placeholder for link '{}'", e, link.name);
+                placeholder_shape()
+            }
+        },
+        None => placeholder_shape(),
+    }
+}
+
+fn placeholder_shape() -> Shape {
+    Shape::Sphere {
+        radius: PLACEHOLDER_RADIUS,
+        subdivisions: 1,
+    }
+}
+
+/// Transformation of `to` expressed in the frame of `from`
+fn relative_transform(from: &Transform, to: &Transform) -> Transform {
+    Transform::from_matrix(from.compute_matrix().inverse() * to.compute_matrix())
+}
+
+/// Converts an axis to a Kesko axis if it is aligned with one of the principal axes
+fn principal_axis(axis: Vec3) -> Option<KeskoAxis> {
+    let axis = axis.normalize_or_zero();
+    let tolerance = 1e-3;
+
+    if axis.abs_diff_eq(Vec3::X, tolerance) {
+        Some(KeskoAxis::X)
+    } else if axis.abs_diff_eq(Vec3::NEG_X, tolerance) {
+        Some(KeskoAxis::NegX)
+    } else if axis.abs_diff_eq(Vec3::Y, tolerance) {
+        Some(KeskoAxis::Y)
+    } else if axis.abs_diff_eq(Vec3::NEG_Y, tolerance) {
+        Some(KeskoAxis::NegY)
+    } else if axis.abs_diff_eq(Vec3::Z, tolerance) {
+        Some(KeskoAxis::Z)
+    } else if axis.abs_diff_eq(Vec3::NEG_Z, tolerance) {
+        Some(KeskoAxis::NegZ)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bevy::asset::AssetPlugin;
+    use bevy::core::TypeRegistrationPlugin;
+    use bevy::ecs::system::CommandQueue;
+    use kesko_physics::PhysicsPlugin;
+
+    use super::*;
+
+    /// An arm turning around a tilted axis, with a rotated joint origin and a cylinder that is
+    /// offset from the link origin
+    const URDF: &str = r#"
+        <robot name="arm">
+            <link name="base">
+                <collision>
+                    <geometry><box size="0.4 0.4 0.2"/></geometry>
+                </collision>
+            </link>
+            <link name="upper_arm">
+                <inertial>
+                    <origin xyz="0 0 0.3"/>
+                    <mass value="1.0"/>
+                    <inertia ixx="0.1" ixy="0" ixz="0" iyy="0.2" iyz="0" izz="0.3"/>
+                </inertial>
+                <collision>
+                    <origin xyz="0 0 0.2"/>
+                    <geometry><cylinder radius="0.05" length="0.4"/></geometry>
+                </collision>
+            </link>
+            <joint name="shoulder" type="revolute">
+                <parent link="base"/>
+                <child link="upper_arm"/>
+                <origin xyz="0 0 0.5" rpy="0 0 1.5707963"/>
+                <axis xyz="0 1 1"/>
+                <limit lower="-1.0" upper="1.0"/>
+            </joint>
+        </robot>
+    "#;
+
+    fn spawn_robot(urdf: &str) -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            TypeRegistrationPlugin::default(),
+            AssetPlugin::default(),
+            PhysicsPlugin {
+                gravity: Vec3::ZERO,
+                ..default()
+            },
+        ))
+        .add_asset::<Mesh>()
+        .add_asset::<StandardMaterial>();
+
+        let robot = Robot::from_str(urdf).unwrap();
+        let mut queue = CommandQueue::default();
+        let root =
+            app.world
+                .resource_scope(|world, mut materials: Mut<Assets<StandardMaterial>>| {
+                    world.resource_scope(|world, mut meshes: Mut<Assets<Mesh>>| {
+                        let mut commands = Commands::new(&mut queue, world);
+                        spawn(
+                            &mut commands,
+                            &robot,
+                            Transform::default(),
+                            &mut materials,
+                            &mut meshes,
+                        )
+                        .unwrap()
+                    })
+                });
+        queue.apply(&mut app.world);
+        (app, root)
+    }
+
+    fn link(app: &mut App, name: &str) -> Entity {
+        app.world
+            .query::<(Entity, &Name)>()
+            .iter(&app.world)
+            .find(|(_, link_name)| link_name.as_str() == name)
+            .map(|(entity, _)| entity)
+            .unwrap()
+    }
+
+    #[test]
+    fn link_poses_and_joint_frames() {
+        let (mut app, root) = spawn_robot(URDF);
+        let arm = link(&mut app, "shoulder");
+
+        // the robot is turned so its z-axis is up, the link and the collision origins add up
+        let root_rotation = Quat::from_rotation_x(-FRAC_PI_2);
+        let link_transform = Transform::from_rotation(root_rotation)
+            * Transform::from_xyz(0.0, 0.0, 0.5).with_rotation(Quat::from_rotation_z(FRAC_PI_2));
+        let arm_transform = *app.world.get::<Transform>(arm).unwrap();
+        assert!(arm_transform
+            .translation
+            .abs_diff_eq(Vec3::new(0.0, 0.7, 0.0), 1e-5));
+        // the cylinder is turned from the z-axis of the link to the y-axis of the body
+        assert!(arm_transform.rotation.abs_diff_eq(
+            link_transform.rotation * Quat::from_rotation_x(FRAC_PI_2),
+            1e-5
+        ));
+
+        // the joint is at the link origin and the anchors map the x-axis onto the joint axis
+        let root_transform = *app.world.get::<Transform>(root).unwrap();
+        let joint = *app.world.get::<RevoluteJoint>(arm).unwrap();
+        let world_axis = link_transform.rotation * Vec3::new(0.0, 1.0, 1.0).normalize();
+        assert!(matches!(joint.axis, KeskoAxis::X));
+        assert_eq!(joint.limits, Some(Vec2::new(-1.0, 1.0)));
+        for (body, anchor) in [
+            (root_transform, joint.parent_anchor),
+            (arm_transform, joint.child_anchor),
+        ] {
+            let joint_frame = body * anchor;
+            assert!(joint_frame
+                .translation
+                .abs_diff_eq(link_transform.translation, 1e-5));
+            assert!((joint_frame.rotation * Vec3::X).abs_diff_eq(world_axis, 1e-5));
+        }
+
+        // the center of mass and the inertia are expressed in the body frame
+        let mass_properties = app.world.get::<MassProperties>(arm).unwrap();
+        assert_eq!(mass_properties.mass, 1.0);
+        assert!(mass_properties
+            .center_of_mass
+            .abs_diff_eq(Vec3::new(0.0, 0.1, 0.0), 1e-5));
+        assert!(mass_properties
+            .inertia
+            .abs_diff_eq(Mat3::from_diagonal(Vec3::new(0.1, 0.3, 0.2)), 1e-5));
+        assert!(app.world.get::<MassProperties>(root).is_none());
+    }
+
+    #[test]
+    fn joint_holds_the_arm_in_place() {
+        let (mut app, _) = spawn_robot(URDF);
+        let arm = link(&mut app, "shoulder");
+        let start = *app.world.get::<Transform>(arm).unwrap();
+
+        for _ in 0..10 {
+            app.update();
+        }
+
+        // consistent joint frames don't pull the arm anywhere when nothing acts on it
+        let transform = app.world.get::<Transform>(arm).unwrap();
+        assert!(transform.translation.abs_diff_eq(start.translation, 1e-3));
+        assert!(transform.rotation.abs_diff_eq(start.rotation, 1e-3));
+        assert!(
+            app.world
+                .get::<RevoluteJoint>(arm)
+                .unwrap()
+                .rotation()
+                .abs()
+                < 1e-3
+        );
+    }
+}
@@ -29,7 +29,7 @@ fn main() {
             InteractionPlugin::<GroupStatic>::default(),
             FrameTimeDiagnosticsPlugin,
             LogDiagnosticsPlugin::default(),
-            TcpPlugin,
+            TcpPlugin::default(),
         ))
         .add_systems(Startup, setup)
         .run();
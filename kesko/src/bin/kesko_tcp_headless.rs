@@ -14,7 +14,7 @@ fn main() {
         .add_plugins((
             FrameTimeDiagnosticsPlugin,
             LogDiagnosticsPlugin::default(),
-            TcpPlugin,
+            TcpPlugin::default(),
         ))
         .add_systems(Startup, setup)
         .run();
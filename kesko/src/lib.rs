@@ -23,3 +23,7 @@ pub mod diagnostic {
 pub mod tcp {
     pub use kesko_tcp::*;
 }
+
+pub mod urdf {
+    pub use kesko_urdf::*;
+}
@@ -1,6 +1,6 @@
 from time import time
 
-import gym
+import gymnasium as gym
 
 
 ENV = "kesko:kesko/Spider-v0"
@@ -1,6 +1,6 @@
 from datetime import datetime
 
-import gym
+import gymnasium as gym
 
 from stable_baselines3 import PPO
 from stable_baselines3.common.callbacks import CheckpointCallback
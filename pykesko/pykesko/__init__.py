@@ -2,9 +2,14 @@ from pykesko.kesko import Kesko
 
 # import the the rust bindings
 from .pykesko import KeskoApp as _KeskoApp
+from .pykesko import KeskoVecApp
 from .pykesko import Model as KeskoModel
 from .pykesko import run_kesko_tcp
 
-from gym.envs.registration import register
+from gymnasium.envs.registration import register
 
 register(id="Spider-v0", entry_point="pykesko.envs:SpiderEnv")
+register(id="Snake-v0", entry_point="pykesko.envs:SnakeEnv")
+register(id="Humanoid-v0", entry_point="pykesko.envs:HumanoidEnv")
+register(id="Car-v0", entry_point="pykesko.envs:CarEnv")
+register(id="Wheely-v0", entry_point="pykesko.envs:WheelyEnv")
@@ -5,19 +5,44 @@ from ..color import Color, Rgba
 from .backend import RenderMode
 from ..protocol.commands import (
     ApplyControl,
+    ApplyForce,
+    ApplyImpulse,
+    ApplySphericalControl,
     Command,
+    DeleteSnapshot,
     DespawnAll,
+    GetDepthImages,
+    Overlap,
     PausePhysics,
+    Randomize,
+    Raycast,
+    RestoreSnapshot,
     RunPhysics,
+    SaveSnapshot,
+    SetDrag,
+    SetForceFields,
+    SetRandomization,
     Spawn,
+    SpawnTerrain,
+    SpawnUrdf,
     Despawn,
 )
 from ..protocol.response import (
     CollisionStarted,
     CollisionStopped,
+    ContactForce,
+    DepthImage,
     KeskoResponse,
     MultibodyStates,
     MultibodySpawned,
+    Overlaps,
+    RaycastHit,
+    SnapshotDeleted,
+    SnapshotFailed,
+    SnapshotRestored,
+    SnapshotSaved,
+    TriggerEntered,
+    TriggerExited,
 )
 from ..pykesko import KeskoApp
 
@@ -52,7 +77,35 @@ class BindingBackend:
                     )
 
                 self.kesko.spawn(
-                    model=command.model, position=command.position, color=color
+                    model=command.model,
+                    position=command.position,
+                    color=color,
+                    collision_groups=command.collision_groups,
+                    self_collision=command.self_collision,
+                )
+
+            elif isinstance(command, SpawnUrdf):
+                self.kesko.spawn_urdf(
+                    path=command.path,
+                    position=command.position,
+                    collision_groups=command.collision_groups,
+                    self_collision=command.self_collision,
+                )
+
+            elif isinstance(command, SpawnTerrain):
+                if isinstance(command.color, Color):
+                    color = command.color.value.to_list()
+                elif isinstance(command.color, Rgba):
+                    color = command.color.to_list()
+                else:
+                    raise ValueError(
+                        f"SpawnTerrain had an invalid color type, {type(command.color)}"
+                    )
+
+                self.kesko.spawn_terrain(
+                    terrain=json.dumps(command.terrain_json()),
+                    position=command.position,
+                    color=color,
                 )
 
             elif isinstance(command, RunPhysics):
@@ -61,15 +114,91 @@ class BindingBackend:
             elif isinstance(command, PausePhysics):
                 self.kesko.stop_physics()
 
+            elif isinstance(command, SaveSnapshot):
+                self.kesko.save_snapshot()
+
+            elif isinstance(command, RestoreSnapshot):
+                self.kesko.restore_snapshot(command.id)
+
+            elif isinstance(command, DeleteSnapshot):
+                self.kesko.delete_snapshot(command.id)
+
+            elif isinstance(command, SetRandomization):
+                self.kesko.set_randomization(json.dumps(command.config))
+
+            elif isinstance(command, Randomize):
+                self.kesko.randomize()
+
+            elif isinstance(command, SetForceFields):
+                self.kesko.set_force_fields(json.dumps(command.config))
+
+            elif isinstance(command, SetDrag):
+                self.kesko.set_drag(
+                    body_id=command.body_id,
+                    linear=command.linear,
+                    quadratic=command.quadratic,
+                    angular=command.angular,
+                    link=command.link,
+                )
+
             elif isinstance(command, ApplyControl):
                 self.kesko.apply_motor_commands(command.values)
 
+            elif isinstance(command, ApplySphericalControl):
+                self.kesko.apply_spherical_motor_commands(command.values)
+
+            elif isinstance(command, ApplyForce):
+                self.kesko.apply_force(
+                    body_id=command.body_id,
+                    force=command.force,
+                    link=command.link,
+                    torque=command.torque,
+                    point=command.point,
+                    local_point=command.local_point,
+                    persistent=command.persistent,
+                )
+
+            elif isinstance(command, ApplyImpulse):
+                self.kesko.apply_impulse(
+                    body_id=command.body_id,
+                    impulse=command.impulse,
+                    link=command.link,
+                    torque=command.torque,
+                    point=command.point,
+                    local_point=command.local_point,
+                )
+
+            elif isinstance(command, Raycast):
+                self.kesko.raycast(
+                    origin=command.origin,
+                    direction=command.direction,
+                    max_distance=command.max_distance,
+                    collision_groups=command.collision_groups,
+                    exclude_sensors=command.exclude_sensors,
+                    exclude_body=command.exclude_body,
+                )
+
+            elif isinstance(command, Overlap):
+                self.kesko.overlap(
+                    shape=json.dumps(command.shape),
+                    position=command.position,
+                    rotation=command.rotation,
+                    collision_groups=command.collision_groups,
+                    exclude_sensors=command.exclude_sensors,
+                    exclude_body=command.exclude_body,
+                )
+
         # step simulation
         self.kesko.step()
 
         # Get responses
         responses = []
 
+        # depth images are only read when requested since they are large
+        if any(isinstance(command, GetDepthImages) for command in commands):
+            for name, entity, depth, segmentation in self.kesko.get_depth_images():
+                responses.append(DepthImage(name, entity, depth, segmentation))
+
         # body states
         body_states = json.loads(self.kesko.get_multibody_state())
         multibody_states = [MultibodyStates(**mb) for mb in body_states]
@@ -82,6 +211,18 @@ class BindingBackend:
                 if MultibodySpawned.__name__ in ev:
                     multibody = MultibodySpawned(**ev[MultibodySpawned.__name__])
                     responses.append(multibody)
+                elif SnapshotSaved.__name__ in ev:
+                    responses.append(SnapshotSaved(id=ev[SnapshotSaved.__name__]))
+                elif SnapshotRestored.__name__ in ev:
+                    responses.append(SnapshotRestored(id=ev[SnapshotRestored.__name__]))
+                elif SnapshotDeleted.__name__ in ev:
+                    responses.append(SnapshotDeleted(id=ev[SnapshotDeleted.__name__]))
+                elif SnapshotFailed.__name__ in ev:
+                    responses.append(SnapshotFailed(**ev[SnapshotFailed.__name__]))
+                elif RaycastHit.__name__ in ev:
+                    responses.append(RaycastHit(hit=ev[RaycastHit.__name__]))
+                elif Overlaps.__name__ in ev:
+                    responses.append(Overlaps(entities=ev[Overlaps.__name__]))
 
         # Collision events
         if (collision_events := self.kesko.get_collisions()) is not None:
@@ -99,6 +240,15 @@ class BindingBackend:
                     )
                     responses.append(collision_stopped)
 
+                elif ContactForce.__name__ in ev:
+                    responses.append(ContactForce(**ev[ContactForce.__name__]))
+
+                elif TriggerEntered.__name__ in ev:
+                    responses.append(TriggerEntered(**ev[TriggerEntered.__name__]))
+
+                elif TriggerExited.__name__ in ev:
+                    responses.append(TriggerExited(**ev[TriggerExited.__name__]))
+
         return KeskoResponse(responses)
 
     def close(self):
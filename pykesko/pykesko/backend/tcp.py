@@ -1,7 +1,6 @@
 from typing import Optional
 from multiprocessing import Process
 import logging
-import json
 
 from ..pykesko import run_kesko_tcp
 from .backend import RenderMode
@@ -14,6 +13,16 @@ from ..protocol.response import (
     MultibodyStates,
     CollisionStarted,
     CollisionStopped,
+    ContactForce,
+    TriggerEntered,
+    TriggerExited,
+    SnapshotSaved,
+    SnapshotRestored,
+    SnapshotDeleted,
+    SnapshotFailed,
+    RaycastHit,
+    Overlaps,
+    DepthImage,
 )
 
 
@@ -21,22 +30,34 @@ logger = logging.getLogger(__name__)
 
 
 class TcpBackend:
-    def __init__(self, url: str, log_level: int):
-        self.com = Communicator(url=url)
+    def __init__(
+        self, host: str, port: int, log_level: int, encoding: str = "msgpack", free_running: bool = False
+    ):
+        """
+        Args:
+            host: Host Kesko should listen on.
+            port: Port Kesko should listen on.
+            log_level: Log level of Kesko.
+            encoding: Encoding of the messages, either 'msgpack' or 'json'.
+            free_running: If Kesko should keep running between requests instead of waiting for the next one.
+        """
+        self.address = f"{host}:{port}"
+        self.free_running = free_running
+        self.com = Communicator(host=host, port=port, encoding=encoding)
         self.process: Optional[Process] = None
         self.log_level = log_level
 
     def initialize(self, render_mode: RenderMode):
         self.process = Process(
             target=run_kesko_tcp,
-            args=[render_mode == RenderMode.WINDOW, self.log_level],
+            args=[render_mode == RenderMode.WINDOW, self.log_level, self.address, self.free_running],
         )
         self.process.start()
 
     def close(self):
         try:
             resp = self.step([Shutdown()])
-            self.com.sess.close()
+            self.com.close()
             logger.info("Closing down...")
             return resp
         except Exception as e:
@@ -51,10 +72,10 @@ class TcpBackend:
             self.close()
             raise ValueError("Response was None")
 
-        logger.debug(f"Got response {json.dumps(response.json(), indent=4)}")
+        logger.debug(f"Got response {response}")
 
         # Because we get some strange things from the Serialization on Kesko's side
-        json_response = [resp[-1] for resp in response.json()]
+        json_response = [resp[-1] for resp in response]
         return self._parse_response(json_response)
 
     def _parse_response(self, json_response) -> KeskoResponse:
@@ -78,6 +99,38 @@ class TcpBackend:
                 )
                 response_objs.append(collision_stopped)
 
+            elif ContactForce.__name__ in response:
+                response_objs.append(ContactForce(**response[ContactForce.__name__]))
+
+            elif TriggerEntered.__name__ in response:
+                response_objs.append(TriggerEntered(**response[TriggerEntered.__name__]))
+
+            elif TriggerExited.__name__ in response:
+                response_objs.append(TriggerExited(**response[TriggerExited.__name__]))
+
+            elif SnapshotSaved.__name__ in response:
+                response_objs.append(SnapshotSaved(id=response[SnapshotSaved.__name__]))
+
+            elif SnapshotRestored.__name__ in response:
+                response_objs.append(
+                    SnapshotRestored(id=response[SnapshotRestored.__name__])
+                )
+
+            elif SnapshotDeleted.__name__ in response:
+                response_objs.append(SnapshotDeleted(id=response[SnapshotDeleted.__name__]))
+
+            elif SnapshotFailed.__name__ in response:
+                response_objs.append(SnapshotFailed(**response[SnapshotFailed.__name__]))
+
+            elif RaycastHit.__name__ in response:
+                response_objs.append(RaycastHit(hit=response[RaycastHit.__name__]))
+
+            elif Overlaps.__name__ in response:
+                response_objs.append(Overlaps(entities=response[Overlaps.__name__]))
+
+            elif "DepthImages" in response:
+                response_objs.extend(DepthImage.from_json(image) for image in response["DepthImages"])
+
             elif MultibodyStates.__name__ in response:
                 multibody_states = [
                     MultibodyStates(**mb) for mb in response[MultibodyStates.__name__]
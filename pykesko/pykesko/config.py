@@ -1,9 +1,8 @@
 from pathlib import Path
 
 
-IP = "http://localhost"
+HOST = "localhost"
 PORT = 8080
-URL = f"{IP}:{PORT}"
 
 ROOT = Path(__file__).parent.parent.parent.parent
 
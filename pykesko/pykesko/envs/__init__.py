@@ -1 +1,4 @@
+from .base import KeskoEnv
+from .models import CarEnv, HumanoidEnv, SnakeEnv, WheelyEnv
 from .spider import SpiderEnv
+from .spider_vec import SpiderVecEnv
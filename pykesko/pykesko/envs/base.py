@@ -0,0 +1,178 @@
+from typing import Optional, Tuple
+
+import gymnasium as gym
+from gymnasium.spaces.box import Box
+import numpy as np
+
+from ..backend import BackendType, RenderMode
+from ..kesko import Kesko
+from ..protocol.commands import (
+    ApplyControl,
+    GetState,
+    PausePhysics,
+    RestoreSnapshot,
+    RunPhysics,
+    SaveSnapshot,
+    Spawn,
+)
+from ..protocol.response import JointInfo, KeskoResponse, MultibodyStates, SnapshotFailed, SnapshotSaved
+from ..color import Color
+from ..pykesko import Model as KeskoModel, observation, progress, has_fallen
+
+
+class KeskoEnv(gym.Env):
+    """
+    Base environment for the built-in models. The agent is spawned on a plane and is rewarded
+    for moving along `direction` and for staying up. The episode is terminated when the agent
+    has fallen, that is when it is below `min_height` or tilted more than `max_tilt` radians.
+
+    Actions are joint targets in the same order as the joints sent when the model was spawned, joints
+    without a motor are left out. Joints with a motor that has no stiffness are velocity controlled,
+    the other ones are position controlled.
+
+    The models are only spawned once, the environment is reset by restoring a snapshot taken right
+    after they were spawned.
+    """
+
+    metadata = {"render_modes": ["human"]}
+
+    # subclasses decides which model to use and how it should behave
+    model: KeskoModel
+    name: str
+    color: Color = Color.DEEP_ORANGE
+    spawn_position: list[float] = [0.0, 0.5, 0.0]
+    direction: list[float] = [1.0, 0.0, 0.0]
+    min_height: float = 0.0
+    max_tilt: float = np.pi
+    max_velocity: float = 10.0
+
+    def __init__(
+        self,
+        max_steps: Optional[int] = None,
+        render_mode: Optional[str] = None,
+        backend_type: str = "bindings",
+        alive_reward: float = 1.0,
+        progress_reward_factor: float = 1.0,
+    ):
+        """
+        Args:
+            max_steps: Maximum steps before the episode is truncated. Defaults to None.
+            render_mode: If the environment should be rendered or run in headless. Defaults to None.
+            backend_type: Type of backend to use for communication with Kesko. Can be either 'tcp' or 'bindings'.
+            alive_reward: Reward for each step the agent has not fallen.
+            progress_reward_factor: Factor for the reward of moving along the direction.
+
+        Raises:
+            ValueError: If incorrect 'render_mode' or 'backend'.
+        """
+        assert render_mode is None or render_mode in self.metadata["render_modes"]
+        self.render_mode = render_mode
+
+        self.max_steps = max_steps
+        self.alive_reward = alive_reward
+        self.progress_reward_factor = progress_reward_factor
+        self.step_count = 0
+
+        mode = RenderMode.WINDOW if self.render_mode == "human" else RenderMode.HEADLESS
+        if backend_type not in ("bindings", "tcp"):
+            raise ValueError("Invalid option for backend, use 'bindings' or 'tcp'")
+
+        self.backend = BackendType.TCP if backend_type == "tcp" else BackendType.BINDINGS
+        self._kesko = Kesko(render_mode=mode, backend_type=self.backend)
+        self._kesko.initialize()
+        self._setup()
+
+    def _setup(self) -> Tuple[np.ndarray, dict]:
+        self._kesko.send(
+            [
+                Spawn(model=KeskoModel.Plane, position=[0.0, 0.0, 0.0], color=Color.WHITE),
+                Spawn(model=self.model, position=self.spawn_position, color=self.color),
+            ]
+        )
+
+        try:
+            self.body = [body for body in self._kesko.bodies.values() if self.name in body.name][0]
+        except IndexError as e:
+            self.close()
+            raise ValueError(f"Could not get body from Kesko: {e}")
+
+        initial_state = self._get_state(self._kesko.send(GetState()))
+        initial_obs = self._to_numpy(initial_state)
+
+        saved = [resp for resp in self._kesko.send(SaveSnapshot()).responses if isinstance(resp, SnapshotSaved)]
+        if not saved:
+            self.close()
+            raise ValueError("Could not save a snapshot of the initial state")
+        self._snapshot_id = saved[0].id
+
+        self._kesko.send(RunPhysics())
+
+        # only single axis joints with a motor can be controlled
+        self.joint_ids = [
+            joint_id
+            for joint_id, joint in self.body.joints.items()
+            if isinstance(joint, JointInfo) and (joint.stiffness > 0.0 or joint.damping > 0.0)
+        ]
+        self.action_space = self._action_space([self.body.joints[joint_id] for joint_id in self.joint_ids])
+        self.observation_space = Box(low=-np.inf, high=np.inf, shape=initial_obs.shape)
+
+        return initial_obs, {}
+
+    def _action_space(self, joints: list[JointInfo]) -> Box:
+        lows = []
+        highs = []
+        for joint in joints:
+            if joint.stiffness == 0.0:
+                low, high = -self.max_velocity, self.max_velocity
+            elif joint.limits is not None:
+                low, high = joint.limits
+            else:
+                low, high = -np.pi, np.pi
+            lows.append(low)
+            highs.append(high)
+
+        return Box(low=np.array(lows, dtype=np.float32), high=np.array(highs, dtype=np.float32))
+
+    def step(self, action: np.ndarray) -> Tuple[np.ndarray, float, bool, bool, dict]:
+        """Take one step in the environment and perform an action"""
+        values = {joint_id: float(val) for joint_id, val in zip(self.joint_ids, np.asarray(action).tolist())}
+        response = self._kesko.step(ApplyControl(self.body.id, values))
+        state = self._get_state(response)
+        state_json = state.json()
+
+        terminated = has_fallen(state_json, self.min_height, self.max_tilt)
+        reward = self.progress_reward_factor * progress(state_json, self.direction)
+        if not terminated:
+            reward += self.alive_reward
+
+        self.step_count += 1
+        truncated = self.max_steps is not None and self.step_count >= self.max_steps
+
+        return self._to_numpy(state), float(reward), terminated, truncated, {}
+
+    def reset(self, seed: Optional[int] = None, options: Optional[dict] = None) -> Tuple[np.ndarray, dict]:
+        """Reset the environment to it's initial state"""
+        super().reset(seed=seed)
+        response = self._kesko.send([PausePhysics(), RestoreSnapshot(self._snapshot_id)])
+        failed = [resp for resp in response.responses if isinstance(resp, SnapshotFailed)]
+        if failed:
+            raise ValueError(f"Could not reset the environment: {failed[0].error}")
+        self.step_count = 0
+
+        initial_state = self._get_state(self._kesko.send(GetState()))
+        self._kesko.send(RunPhysics())
+        return self._to_numpy(initial_state), {}
+
+    def close(self):
+        """Shutdown Kesko and the backend"""
+        self._kesko.close()
+
+    def _to_numpy(self, state: MultibodyStates) -> np.ndarray:
+        return np.array(observation(state.json()), dtype=np.float32)
+
+    def _get_state(self, response: KeskoResponse) -> MultibodyStates:
+        """Extract state from response"""
+        state = response.get_state_for_body(self.body.name)
+        if state is None:
+            raise ValueError("Could not get state")
+        return state
@@ -0,0 +1,38 @@
+import numpy as np
+
+from .base import KeskoEnv
+from ..pykesko import Model as KeskoModel
+
+
+class SnakeEnv(KeskoEnv):
+    """A snake that should learn to slither in the positive x-direction"""
+
+    model = KeskoModel.Snake
+    name = "snake"
+    spawn_position = [0.0, 0.2, 0.0]
+
+
+class HumanoidEnv(KeskoEnv):
+    """A humanoid that should learn to walk in the positive x-direction without falling"""
+
+    model = KeskoModel.Humanoid
+    name = "Humanoid"
+    spawn_position = [0.0, 2.2, 0.0]
+    min_height = 0.8
+    max_tilt = np.pi / 3
+
+
+class CarEnv(KeskoEnv):
+    """A car that should learn to drive in the positive x-direction without flipping over"""
+
+    model = KeskoModel.Car
+    name = "car"
+    max_tilt = np.pi / 2
+
+
+class WheelyEnv(KeskoEnv):
+    """A wheeled robot with an arm that should learn to drive in the positive x-direction without flipping over"""
+
+    model = KeskoModel.Wheely
+    name = "wheely"
+    max_tilt = np.pi / 2
@@ -1,8 +1,8 @@
 from typing import Optional, Tuple
 from queue import Queue
 
-import gym
-from gym.spaces.box import Box
+import gymnasium as gym
+from gymnasium.spaces.box import Box
 import numpy as np
 
 from ..backend import BackendType, RenderMode
@@ -53,7 +53,7 @@ class SpiderEnv(gym.Env):
         The episode will be terminated if the head collides with the ground.
 
         Args:
-            max_steps: Maximum steps before resetting. Defaults to None.
+            max_steps: Maximum steps before the episode is truncated. Defaults to None.
             render_mode: If the environment should be rendered or run in headless. Defaults to None.
             backend: Type of backend to use for communication with Kesko. Can be either 'tcp' or 'bindings'.
             reward_step_length: The step interval to use when calculating the reward. Defaults to 5.
@@ -154,15 +154,13 @@ class SpiderEnv(gym.Env):
         body_collision = response.get_collision_with_body(self.spider_body.entity)
         reward = self._calc_reward(state, body_collision)
 
-        done = True if body_collision is not None else False
-        if self.max_steps is not None:
-            if self.step_count > self.max_steps:
-                done = True
+        terminated = body_collision is not None
 
         self.step_count += 1
+        truncated = self.max_steps is not None and self.step_count >= self.max_steps
 
         state = self._to_numpy(state)
-        return state, reward, done, done, {}
+        return state, reward, terminated, truncated, {}
 
     def _calc_reward(self, state: MultibodyStates, collision: Optional[CollisionStarted]) -> float:
 
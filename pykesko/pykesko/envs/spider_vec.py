@@ -0,0 +1,101 @@
+from typing import Optional
+
+from gymnasium.spaces.box import Box
+from gymnasium.vector import VectorEnv
+import numpy as np
+
+from ..color import Color
+from ..pykesko import KeskoVecApp, Model as KeskoModel
+
+
+AGENT = "spider"
+# Index of the x-velocity in the observation, after position (3) and orientation (4)
+VELOCITY_X_INDEX = 7
+# Frames needed for the spawned models to be added to the physics
+SPAWN_FRAMES = 3
+
+
+class SpiderVecEnv(VectorEnv):
+    def __init__(self, num_envs: int = 64, max_steps: Optional[int] = None):
+        """
+        Runs several spider environments in the same process, each in its own physics world.
+        The agent is rewarded for moving in the positive x-direction and for not falling over,
+        an environment is terminated when the spider body collides with the ground.
+
+        Terminated and truncated environments are reset automatically by restoring a snapshot
+        taken right after the models were spawned. The observation returned for them is the first
+        one after the reset, the last one before it is in `info["final_observation"]`.
+
+        Args:
+            num_envs: Number of parallel environments. Defaults to 64.
+            max_steps: Maximum steps before an environment is truncated. Defaults to None.
+        """
+        self.max_steps = max_steps
+        self.step_counts = np.zeros(num_envs, dtype=np.int64)
+        self._actions = None
+
+        self._kesko = KeskoVecApp(num_envs)
+        self._kesko.spawn(KeskoModel.Plane, [0.0, 0.0, 0.0], Color.WHITE.value.to_list())
+        self._kesko.spawn(KeskoModel.Spider, [0.0, 0.4, 0.0], Color.DEEP_ORANGE.value.to_list())
+        for _ in range(SPAWN_FRAMES):
+            self._kesko.update()
+
+        self._snapshot_id = self._kesko.save_snapshot()
+
+        limits = np.array(self._kesko.get_joint_limits(AGENT), dtype=np.float32)
+        obs = self._get_observations()
+
+        super().__init__(
+            num_envs=num_envs,
+            observation_space=Box(low=-np.inf, high=np.inf, shape=obs.shape[1:]),
+            action_space=Box(low=limits[:, 0], high=limits[:, 1]),
+        )
+
+    def reset_async(self, seed: Optional[int] = None, options: Optional[dict] = None):
+        pass
+
+    def reset_wait(self, seed: Optional[int] = None, options: Optional[dict] = None):
+        """Resets all the environments"""
+        self._reset(list(range(self.num_envs)))
+        return self._get_observations(), {}
+
+    def step_async(self, actions: np.ndarray):
+        self._actions = actions
+
+    def step_wait(self):
+        """Step all the environments with the actions given to step_async"""
+        obs, collisions = self._kesko.step(AGENT, np.asarray(self._actions, dtype=np.float32))
+        terminated = np.array(collisions, dtype=bool)
+
+        self.step_counts += 1
+        if self.max_steps is not None:
+            truncated = self.step_counts >= self.max_steps
+        else:
+            truncated = np.zeros(self.num_envs, dtype=bool)
+
+        reward = np.clip(obs[:, VELOCITY_X_INDEX], -100.0, 100.0) + np.where(terminated, 0.0, 1.0)
+
+        info = {}
+        done = np.logical_or(terminated, truncated)
+        if done.any():
+            env_ids = np.flatnonzero(done)
+            final_observation = np.full(self.num_envs, None, dtype=object)
+            for env_id in env_ids:
+                final_observation[env_id] = obs[env_id].copy()
+            info["final_observation"] = final_observation
+            info["_final_observation"] = done
+
+            self._reset(env_ids.tolist())
+            obs[env_ids] = self._get_observations()[env_ids]
+
+        return obs, reward.astype(np.float32), terminated, truncated, info
+
+    def close_extras(self, **kwargs):
+        self._kesko = None
+
+    def _reset(self, env_ids: list[int]):
+        self._kesko.restore_snapshot(env_ids, self._snapshot_id)
+        self.step_counts[env_ids] = 0
+
+    def _get_observations(self) -> np.ndarray:
+        return self._kesko.get_observations(AGENT)
@@ -3,7 +3,7 @@ from typing import Any, Optional, Union
 
 import numpy as np
 
-from .config import URL
+from .config import HOST, PORT
 from .backend import Backend, TcpBackend, BindingBackend, RenderMode, BackendType
 from .protocol.commands import ApplyControl, Despawn, DespawnAll, GetState, Command
 from .protocol.response import KeskoResponse, MultibodySpawned
@@ -21,11 +21,15 @@ class Kesko:
         render_mode: RenderMode = RenderMode.WINDOW,
         backend_type: BackendType = BackendType.TCP,
         log_level: int = logging.INFO,
+        encoding: str = "msgpack",
+        free_running: bool = False,
     ) -> None:
         self.render_mode = render_mode
         self.log_level = log_level
         if backend_type == BackendType.TCP:
-            self.backend: Backend = TcpBackend(url=URL, log_level=self.log_level)
+            self.backend: Backend = TcpBackend(
+                host=HOST, port=PORT, log_level=self.log_level, encoding=encoding, free_running=free_running
+            )
         else:
             self.backend: Backend = BindingBackend()
 
@@ -60,12 +64,15 @@ class Kesko:
         for action in actions:
             if isinstance(action, ApplyControl):
                 if isinstance(action.values, np.ndarray):
-                    # convert array to dict
+                    # convert array to dict, spherical joints take their targets with ApplySphericalControl
+                    joint_ids = [
+                        joint_id
+                        for joint_id, joint in self.bodies[action.body_id].joints.items()
+                        if joint.type != "spherical"
+                    ]
                     action.values = {
                         joint_id: val
-                        for joint_id, val in zip(
-                            self.bodies[action.body_id].joints, action.values.tolist()
-                        )
+                        for joint_id, val in zip(joint_ids, action.values.tolist())
                     }
             elif isinstance(action, DespawnAll) or action == DespawnAll:
                 self.bodies = {}
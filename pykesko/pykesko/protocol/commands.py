@@ -1,4 +1,4 @@
-from typing import Union, Protocol
+from typing import Optional, Union, Protocol
 
 import numpy as np
 
@@ -16,11 +16,41 @@ class CheckAlive:
         return "IsAlive"
 
 
+def _collision_json(collision_groups: Optional[tuple[int, int]], self_collision: Optional[bool]) -> dict:
+    groups = None
+    if collision_groups is not None:
+        groups = {"memberships": collision_groups[0], "filter": collision_groups[1]}
+    return {"groups": groups, "self_collision": self_collision}
+
+
+def _filter_json(
+    collision_groups: Optional[tuple[int, int]], exclude_sensors: bool, exclude_body: Optional[int]
+) -> dict:
+    groups = None
+    if collision_groups is not None:
+        groups = {"memberships": collision_groups[0], "filter": collision_groups[1]}
+    return {"groups": groups, "exclude_sensors": exclude_sensors, "exclude_body": exclude_body}
+
+
 class Spawn:
-    def __init__(self, model: Model, position: list[float], color: Union[Rgba, Color]):
+    """
+    Spawns a model, `collision_groups` is a pair of membership and filter bitmasks for all bodies of the model
+    and `self_collision` sets if the bodies of the model can collide with each other
+    """
+
+    def __init__(
+        self,
+        model: Model,
+        position: list[float],
+        color: Union[Rgba, Color],
+        collision_groups: Optional[tuple[int, int]] = None,
+        self_collision: Optional[bool] = None,
+    ):
         self.model = model
         self.position = position
         self.color = color
+        self.collision_groups = collision_groups
+        self.self_collision = self_collision
 
     def to_json(self):
         return {
@@ -28,6 +58,73 @@ class Spawn:
                 "model": self.model.name,
                 "position": self.position,
                 "color": self.color.to_json(),
+                "collision": _collision_json(self.collision_groups, self.self_collision),
+            }
+        }
+
+
+class SpawnUrdf:
+    def __init__(
+        self,
+        path: str,
+        position: list[float],
+        collision_groups: Optional[tuple[int, int]] = None,
+        self_collision: Optional[bool] = None,
+    ):
+        self.path = path
+        self.position = position
+        self.collision_groups = collision_groups
+        self.self_collision = self_collision
+
+    def to_json(self):
+        return {
+            "SpawnUrdf": {
+                "path": self.path,
+                "position": self.position,
+                "collision": _collision_json(self.collision_groups, self.self_collision),
+            }
+        }
+
+
+class SpawnTerrain:
+    """
+    Spawns procedurally generated terrain, `kind` is one of "Rough", "Stairs", "Slope", "SteppingStones"
+    and "Gaps" and `difficulty` goes from 0 for almost flat ground to 1 for the hardest terrain
+    """
+
+    def __init__(
+        self,
+        kind: str,
+        difficulty: float,
+        position: list[float],
+        color: Union[Rgba, Color],
+        seed: int = 0,
+        size: float = 16.0,
+        resolution: float = 0.05,
+    ):
+        self.kind = kind
+        self.difficulty = difficulty
+        self.position = position
+        self.color = color
+        self.seed = seed
+        self.size = size
+        self.resolution = resolution
+
+    def terrain_json(self) -> dict:
+        return {
+            "kind": self.kind,
+            "difficulty": self.difficulty,
+            "seed": self.seed,
+            "size": self.size,
+            "resolution": self.resolution,
+        }
+
+    def to_json(self):
+        return {
+            "SpawnTerrain": {
+                "terrain": self.terrain_json(),
+                "position": self.position,
+                "color": self.color.to_json(),
             }
         }
 
@@ -55,6 +152,11 @@ class GetState:
         return "GetState"
 
 
+class GetDepthImages:
+    def to_json(self):
+        return "GetDepthImages"
+
+
 class ApplyControl:
     def __init__(
         self,
@@ -68,6 +170,280 @@ class ApplyControl:
         return {"ApplyMotorCommand": {"id": self.body_id, "command": self.values}}
 
 
+class ApplySphericalControl:
+    """Targets for the x, y and z axis of spherical joints"""
+
+    def __init__(self, body_id: int, values: dict[np.uint64, list[float]]):
+        self.body_id = body_id
+        self.values = values
+
+    def to_json(self):
+        return {"ApplySphericalMotorCommand": {"id": self.body_id, "command": self.values}}
+
+
+class SaveSnapshot:
+    def to_json(self):
+        return "SaveSnapshot"
+
+
+class RestoreSnapshot:
+    def __init__(self, id: int):
+        self.id = id
+
+    def to_json(self):
+        return {"RestoreSnapshot": {"id": self.id}}
+
+
+class DeleteSnapshot:
+    """Frees the memory of a snapshot that is not needed anymore"""
+
+    def __init__(self, id: int):
+        self.id = id
+
+    def to_json(self):
+        return {"DeleteSnapshot": {"id": self.id}}
+
+
+class Raycast:
+    """
+    Casts a ray against all colliders, `collision_groups` is a pair of membership and filter bitmasks
+    the hit colliders must match
+    """
+
+    def __init__(
+        self,
+        origin: list[float],
+        direction: list[float],
+        max_distance: float,
+        collision_groups: Optional[tuple[int, int]] = None,
+        exclude_sensors: bool = False,
+        exclude_body: Optional[int] = None,
+    ):
+        self.origin = origin
+        self.direction = direction
+        self.max_distance = max_distance
+        self.collision_groups = collision_groups
+        self.exclude_sensors = exclude_sensors
+        self.exclude_body = exclude_body
+
+    def to_json(self):
+        return {
+            "Raycast": {
+                "origin": self.origin,
+                "direction": self.direction,
+                "max_distance": self.max_distance,
+                "filter": _filter_json(self.collision_groups, self.exclude_sensors, self.exclude_body),
+            }
+        }
+
+
+class Overlap:
+    """
+    Finds all colliders overlapping a shape, the shape is given as e.g. `{"Sphere": {"radius": 0.5}}`
+    and the rotation as a quaternion x, y, z, w
+    """
+
+    def __init__(
+        self,
+        shape: dict,
+        position: list[float],
+        rotation: Optional[list[float]] = None,
+        collision_groups: Optional[tuple[int, int]] = None,
+        exclude_sensors: bool = False,
+        exclude_body: Optional[int] = None,
+    ):
+        self.shape = shape
+        self.position = position
+        self.rotation = rotation
+        self.collision_groups = collision_groups
+        self.exclude_sensors = exclude_sensors
+        self.exclude_body = exclude_body
+
+    def to_json(self):
+        return {
+            "Overlap": {
+                "shape": self.shape,
+                "position": self.position,
+                "rotation": self.rotation if self.rotation is not None else [0.0, 0.0, 0.0, 1.0],
+                "filter": _filter_json(self.collision_groups, self.exclude_sensors, self.exclude_body),
+            }
+        }
+
+
+def uniform(low: float, high: float) -> dict:
+    """Distribution uniform between low and high"""
+    return {"Uniform": {"low": low, "high": high}}
+
+
+def normal(mean: float, std: float) -> dict:
+    """Gaussian distribution"""
+    return {"Normal": {"mean": mean, "std": std}}
+
+
+def scale(low: float, high: float) -> dict:
+    """The nominal value scaled with a factor uniform between low and high"""
+    return {"Scale": {"low": low, "high": high}}
+
+
+def jitter(std: float) -> dict:
+    """The nominal value with gaussian noise added"""
+    return {"Jitter": {"std": std}}
+
+
+class SetRandomization:
+    """
+    Sets how the physical parameters are randomized when bodies are spawned and when the world is reset.
+
+    The config can contain `seed`, `all_bodies` and `bodies` by name with distributions for `mass`, `friction` and
+    `restitution`, `all_joints` and `joints` by name with distributions for `stiffness`, `damping` and
+    `max_motor_force`, `gravity` and `integration` with distributions for `erp`, `damping_ratio`, `joint_erp` and
+    `joint_damping_ratio`. The distributions are created with `uniform`, `normal`, `scale` and `jitter`, e.g.
+    `{"seed": 0, "all_bodies": {"friction": scale(0.8, 1.2)}, "gravity": jitter(0.1)}`
+    """
+
+    def __init__(self, config: dict):
+        self.config = config
+
+    def to_json(self):
+        return {"SetRandomization": {"config": self.config}}
+
+
+class Randomize:
+    """Samples new physical parameters for all bodies, joints and the world"""
+
+    def to_json(self):
+        return "Randomize"
+
+
+def _force_json(
+    force_key: str,
+    force: list[float],
+    body_id: int,
+    link: Optional[str],
+    torque: Optional[list[float]],
+    point: Optional[list[float]],
+    local_point: bool,
+) -> dict:
+    force_point = None
+    if point is not None:
+        force_point = {"Local" if local_point else "World": point}
+    return {
+        "id": body_id,
+        "link": link,
+        force_key: force,
+        "torque": torque if torque is not None else [0.0, 0.0, 0.0],
+        "point": force_point,
+    }
+
+
+class ApplyForce:
+    """
+    Applies a force to a body, or to the link named `link` of a multibody. The force acts at the center of mass
+    unless a `point` is given, in world coordinates or relative to the body if `local_point` is set. The force
+    replaces the force from earlier commands on the body and only acts during the next step unless `persistent`
+    is set.
+    """
+
+    def __init__(
+        self,
+        body_id: int,
+        force: list[float],
+        link: Optional[str] = None,
+        torque: Optional[list[float]] = None,
+        point: Optional[list[float]] = None,
+        local_point: bool = False,
+        persistent: bool = False,
+    ):
+        self.body_id = body_id
+        self.force = force
+        self.link = link
+        self.torque = torque
+        self.point = point
+        self.local_point = local_point
+        self.persistent = persistent
+
+    def to_json(self):
+        command = _force_json(
+            "force", self.force, self.body_id, self.link, self.torque, self.point, self.local_point
+        )
+        command["persistent"] = self.persistent
+        return {"ApplyForce": command}
+
+
+class ApplyImpulse:
+    """Applies an impulse to a body, or to the link named `link` of a multibody, see `ApplyForce`"""
+
+    def __init__(
+        self,
+        body_id: int,
+        impulse: list[float],
+        link: Optional[str] = None,
+        torque: Optional[list[float]] = None,
+        point: Optional[list[float]] = None,
+        local_point: bool = False,
+    ):
+        self.body_id = body_id
+        self.impulse = impulse
+        self.link = link
+        self.torque = torque
+        self.point = point
+        self.local_point = local_point
+
+    def to_json(self):
+        return {
+            "ApplyImpulse": _force_json(
+                "impulse", self.impulse, self.body_id, self.link, self.torque, self.point, self.local_point
+            )
+        }
+
+
+class SetForceFields:
+    """
+    Sets the wind and the water volumes.
+
+    The config can contain `wind` with `velocity`, `height_gradient`, `gust`, `gust_period` and `gust_length`, and
+    `water` with a list of volumes with `min` and `max` corners, `density`, `current` and `drag`, e.g.
+    `{"wind": {"velocity": [2.0, 0.0, 0.0]}, "water": [{"min": [-5, -2, -5], "max": [5, 0, 5]}]}`.
+    Only bodies with drag, see `SetDrag`, are affected by the wind.
+    """
+
+    def __init__(self, config: dict):
+        self.config = config
+
+    def to_json(self):
+        return {"SetForceFields": {"fields": self.config}}
+
+
+class SetDrag:
+    """
+    Sets the drag of a body, or of the link named `link` of a multibody. The drag force is
+    `-(linear * v + quadratic * |v| * v)` for the velocity `v` relative to the wind.
+    """
+
+    def __init__(
+        self,
+        body_id: int,
+        linear: float,
+        quadratic: float = 0.0,
+        angular: float = 0.0,
+        link: Optional[str] = None,
+    ):
+        self.body_id = body_id
+        self.linear = linear
+        self.quadratic = quadratic
+        self.angular = angular
+        self.link = link
+
+    def to_json(self):
+        return {
+            "SetDrag": {
+                "id": self.body_id,
+                "link": self.link,
+                "drag": {"linear": self.linear, "quadratic": self.quadratic, "angular": self.angular},
+            }
+        }
+
+
 class PausePhysics:
     def to_json(self):
         return "PausePhysics"
@@ -1,7 +1,12 @@
+import json
 import logging
+import socket
+import struct
+import time
+from typing import Any, Optional
 
-import requests
-from requests.adapters import HTTPAdapter, Retry
+import msgpack
+import numpy as np
 
 from .request import KeskoRequest
 from .commands import GetState, Shutdown
@@ -9,19 +14,122 @@ from .commands import GetState, Shutdown
 
 logger = logging.getLogger(__name__)
 
+# each frame starts with the length of the payload as a big endian u32
+HEADER = struct.Struct(">I")
+ENCODINGS = ("msgpack", "json")
+
 
 class Communicator:
-    def __init__(self, url: str, retries: int = 5, backoff_factor: float = 0.5):
-        self.url = url
-        self.sess = requests.Session()
-        retries = Retry(total=retries, backoff_factor=backoff_factor)
-        self.sess.mount("http://", HTTPAdapter(max_retries=retries))
+    def __init__(
+        self,
+        host: str,
+        port: int,
+        encoding: str = "msgpack",
+        observer: bool = False,
+        retries: int = 5,
+        backoff_factor: float = 0.5,
+    ):
+        """
+        Sends requests to Kesko as length prefixed frames
+
+        Args:
+            host: Host Kesko is listening on.
+            port: Port Kesko is listening on.
+            encoding: Encoding of the frames, either 'msgpack' or 'json'. 'json' is slower but easier to debug.
+            observer: Connect as an observer that only receives the states and collisions for each step,
+                use `receive` instead of `request`. Any number of observers can be connected next to the
+                client that steps the simulation.
+            retries: Number of times to try to connect, Kesko might still be starting up.
+            backoff_factor: Time to wait before the first retry, doubled for each retry.
+        """
+        if encoding not in ENCODINGS:
+            raise ValueError(f"Invalid encoding '{encoding}', use one of {ENCODINGS}")
+
+        self.host = host
+        self.port = port
+        self.encoding = encoding
+        self.observer = observer
+        self.retries = retries
+        self.backoff_factor = backoff_factor
+        self.sock: Optional[socket.socket] = None
+
+    def connect(self):
+        for attempt in range(self.retries + 1):
+            try:
+                self.sock = socket.create_connection((self.host, self.port))
+                break
+            except ConnectionRefusedError:
+                if attempt == self.retries:
+                    raise
+                time.sleep(self.backoff_factor * 2**attempt)
+
+        self.sock.setsockopt(socket.IPPROTO_TCP, socket.TCP_NODELAY, 1)
+
+        # tell Kesko which encoding to use and if we are an observer, it is echoed back when accepted
+        handshake = f"{self.encoding} observer" if self.observer else self.encoding
+        self._send_frame(handshake.encode())
+        accepted = self._recv_frame().decode()
+        if accepted != handshake:
+            raise ConnectionError(f"Kesko did not accept '{handshake}', got '{accepted}'")
+
+    def request(self, request: KeskoRequest) -> Any:
+        if self.observer:
+            raise ValueError("Observers can't send requests")
+        if self.sock is None:
+            self.connect()
 
-    def request(self, request: KeskoRequest):
         msg = request.to_json()
         logger.debug(f"Sending {msg}")
-        res = self.sess.get(self.url, json=msg)
-        return res
+        self._send_frame(self._encode(msg))
+        return self._decode(self._recv_frame())
+
+    def receive(self) -> Any:
+        """Blocks until the states and collisions of the next step are received, only for observers"""
+        if not self.observer:
+            raise ValueError("Only observers receive without sending a request")
+        if self.sock is None:
+            self.connect()
+        return self._decode(self._recv_frame())
+
+    def close(self):
+        if self.sock is not None:
+            self.sock.close()
+            self.sock = None
+
+    def _encode(self, msg: Any) -> bytes:
+        if self.encoding == "json":
+            return json.dumps(msg, default=_to_builtin).encode()
+        return msgpack.packb(msg, default=_to_builtin)
+
+    def _decode(self, data: bytes) -> Any:
+        if self.encoding == "json":
+            return json.loads(data)
+        return msgpack.unpackb(data, strict_map_key=False)
+
+    def _send_frame(self, data: bytes):
+        self.sock.sendall(HEADER.pack(len(data)) + data)
+
+    def _recv_frame(self) -> bytes:
+        (length,) = HEADER.unpack(self._recv_exact(HEADER.size))
+        return self._recv_exact(length)
+
+    def _recv_exact(self, size: int) -> bytes:
+        data = bytearray()
+        while len(data) < size:
+            chunk = self.sock.recv(size - len(data))
+            if not chunk:
+                raise ConnectionError("Connection closed by Kesko")
+            data.extend(chunk)
+        return bytes(data)
+
+
+def _to_builtin(obj: Any) -> Any:
+    """Converts numpy scalars and arrays that can't be serialized as is"""
+    if isinstance(obj, np.generic):
+        return obj.item()
+    if isinstance(obj, np.ndarray):
+        return obj.tolist()
+    raise TypeError(f"Can't serialize {type(obj)}")
 
 
 if __name__ == "__main__":
@@ -1,5 +1,6 @@
 from typing import Optional, Union
 
+import numpy as np
 from pydantic import BaseModel
 
 
@@ -15,6 +16,40 @@ class CollisionStopped(BaseModel):
     flag: dict[str, int]
 
 
+class TriggerEntered(BaseModel):
+    sensor: int
+    entity: int
+    root: Optional[int]
+    root_name: Optional[str]
+    link_name: Optional[str]
+
+
+class TriggerExited(BaseModel):
+    sensor: int
+    entity: int
+    root: Optional[int]
+    root_name: Optional[str]
+    link_name: Optional[str]
+
+
+class ContactPoint(BaseModel):
+    point: list[float]
+    normal: list[float]
+    distance: float
+    impulse: float
+    tangent_impulse: list[float]
+
+
+class ContactForce(BaseModel):
+    entity1: int
+    entity2: int
+    total_force: list[float]
+    total_force_magnitude: float
+    max_force_direction: list[float]
+    max_force_magnitude: float
+    contacts: Optional[list[ContactPoint]]
+
+
 class JointInfo(BaseModel):
     name: str
     type: str
@@ -25,24 +60,102 @@ class JointInfo(BaseModel):
     max_motor_force: float
 
 
+class SphericalJointInfo(BaseModel):
+    name: str
+    type: str
+    x_limits: Optional[list]
+    y_limits: Optional[list]
+    z_limits: Optional[list]
+    damping: list[float]
+    stiffness: list[float]
+
+
 class RevoluteJointState(BaseModel):
     type: str
     axis: str
     angle: float
     angular_velocity: float
+    motor_torque: float = 0.0
+    reaction_force: list = [0.0, 0.0, 0.0]
+    at_lower_limit: bool = False
+    at_upper_limit: bool = False
 
 class PrismaticJointState(BaseModel):
     type: str
     axis: str
     position: float
     velocity: float
+    motor_force: float = 0.0
+    reaction_force: list = [0.0, 0.0, 0.0]
+    at_lower_limit: bool = False
+    at_upper_limit: bool = False
+
+
+class SphericalJointState(BaseModel):
+    type: str
+    angles: list[float]
+    orientation: list[float]
+    angular_velocities: list[float]
+
+
+class RayHit(BaseModel):
+    entity: int
+    distance: float
+    point: list[float]
+    normal: list[float]
+
+
+class RaycastHit(BaseModel):
+    hit: Optional[RayHit]
+
+
+class Overlaps(BaseModel):
+    entities: list[int]
+
+
+class ImuReading(BaseModel):
+    linear_acceleration: list[float]
+    angular_velocity: list[float]
+    orientation: list[float]
+
+    def to_numpy(self) -> np.ndarray:
+        """Acceleration, angular velocity and orientation as one array"""
+        return np.array(
+            self.linear_acceleration + self.angular_velocity + self.orientation, dtype=np.float32
+        )
+
+
+class DepthImage:
+    """
+    Image of a depth camera, `depth` and `segmentation` have the shape (height, width). Pixels where
+    nothing was hit have the far distance and the segmentation `BACKGROUND`
+    """
+
+    BACKGROUND = np.iinfo(np.uint64).max
+
+    def __init__(self, name: str, entity: int, depth: np.ndarray, segmentation: np.ndarray):
+        self.name = name
+        self.entity = entity
+        self.depth = depth
+        self.segmentation = segmentation
+
+    @classmethod
+    def from_json(cls, image: dict) -> "DepthImage":
+        """From the binary pixel buffers of a response, the arrays are views of the buffers"""
+        shape = (image["height"], image["width"])
+        return cls(
+            name=image["name"],
+            entity=image["entity"],
+            depth=np.frombuffer(bytes(image["depth"]), dtype="<f4").reshape(shape),
+            segmentation=np.frombuffer(bytes(image["segmentation"]), dtype="<u8").reshape(shape),
+        )
 
 
 class MultibodySpawned(BaseModel):
     id: int
     entity: int
     name: str
-    joints: dict[int, JointInfo]
+    joints: dict[int, Union[JointInfo, SphericalJointInfo]]
 
 
 class RigidBodySpawned(BaseModel):
@@ -50,6 +163,25 @@ class RigidBodySpawned(BaseModel):
     name: str
 
 
+class SnapshotSaved(BaseModel):
+    id: int
+
+
+class SnapshotRestored(BaseModel):
+    id: int
+
+
+class SnapshotDeleted(BaseModel):
+    id: int
+
+
+class SnapshotFailed(BaseModel):
+    """Saving, restoring or deleting a snapshot failed, `id` is the requested snapshot if any"""
+
+    id: Optional[int] = None
+    error: str
+
+
 class MultibodyStates(BaseModel):
     name: str
     id: int
@@ -58,7 +190,19 @@ class MultibodyStates(BaseModel):
     velocity: list
     angular_velocity: list
     relative_positions: dict[str, list[float]]
-    joint_states: dict[str, Optional[Union[RevoluteJointState, PrismaticJointState]]]
+    joint_states: dict[
+        str,
+        Optional[Union[RevoluteJointState, PrismaticJointState, SphericalJointState]],
+    ]
+    range_sensors: Optional[dict[str, list[float]]] = None
+    imus: Optional[dict[str, ImuReading]] = None
+
+    def range_readings(self) -> dict[str, np.ndarray]:
+        """Latest measurements of the range sensors by link name, one value for each ray"""
+        return {
+            name: np.array(ranges, dtype=np.float32)
+            for name, ranges in (self.range_sensors or {}).items()
+        }
 
 
 class KeskoResponse:
@@ -86,3 +230,27 @@ class KeskoResponse:
                     return resp
 
         return None
+
+    def get_triggers_for_body(self, name: str) -> list[Union[TriggerEntered, TriggerExited]]:
+        """Returns the sensors entered or exited by any link of a given body"""
+        return [
+            resp
+            for resp in self.responses
+            if isinstance(resp, (TriggerEntered, TriggerExited)) and resp.root_name == name
+        ]
+
+    def get_depth_image(self, name: str) -> Optional[DepthImage]:
+        """Returns the image of the depth camera with the given name if any"""
+        for resp in self.responses:
+            if isinstance(resp, DepthImage) and resp.name == name:
+                return resp
+        return None
+
+    def get_contact_forces_on_body(self, entity: int) -> list[ContactForce]:
+        """Returns the contact force responses involving a given body"""
+        return [
+            resp
+            for resp in self.responses
+            if isinstance(resp, ContactForce)
+            and (resp.entity1 == entity or resp.entity2 == entity)
+        ]
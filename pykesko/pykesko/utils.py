@@ -1,5 +1,5 @@
 import numpy as np
-from gym.spaces.box import Box
+from gymnasium.spaces.box import Box
 
 
 def action_space_from_limits(limits: list[list[float]], normalized: bool = True) -> Box:
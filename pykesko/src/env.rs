@@ -0,0 +1,35 @@
+use bevy::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use kesko::physics::multibody::MultiBodyState;
+
+/// Helpers for writing environments, they take a multibody state serialized as json
+fn parse_state(state: &str) -> PyResult<MultiBodyState> {
+    serde_json::from_str::<MultiBodyState>(state)
+        .map_err(|e| PyValueError::new_err(format!("Invalid multibody state: {}", e)))
+}
+
+/// Observation vector of a multibody state
+#[pyfunction]
+pub fn observation(state: &str) -> PyResult<Vec<f32>> {
+    Ok(parse_state(state)?.observation())
+}
+
+/// Velocity of the multibody along the direction
+#[pyfunction]
+pub fn progress(state: &str, direction: Vec<f32>) -> PyResult<f32> {
+    if direction.len() != 3 {
+        return Err(PyValueError::new_err(
+            "Direction should have three elements",
+        ));
+    }
+    let direction = Vec3::from_slice(&direction);
+    Ok(parse_state(state)?.progress(direction))
+}
+
+/// If the multibody is below `min_height` or tilted more than `max_tilt` radians
+#[pyfunction]
+pub fn has_fallen(state: &str, min_height: f32, max_tilt: f32) -> PyResult<bool> {
+    Ok(parse_state(state)?.has_fallen(min_height, max_tilt))
+}
@@ -1,18 +1,39 @@
+mod env;
+mod vec_app;
+
 use std::collections::BTreeMap;
 
 use bevy::log::Level;
 use bevy::prelude::*;
+use numpy::{PyArray, PyArray2};
 use phf::phf_map;
 use pyo3::prelude::*;
 
-use kesko::core::event::{SimulatorRequestEvent, SimulatorResponseEvent};
-use kesko::models::{car::CarPlugin, wheely::WheelyPlugin, Model as KeskoModel, SpawnEvent};
+use kesko::core::event::{
+    motor_command, spherical_motor_commands, SimulatorRequestEvent, SimulatorResponseEvent,
+};
+use kesko::models::{
+    car::CarPlugin, terrain::Terrain, wheely::WheelyPlugin, Model as KeskoModel, SpawnCollision,
+    SpawnEvent,
+};
 use kesko::physics::{
+    collider::{ColliderShape, CollisionGroups},
+    depth_camera::DepthCamera,
     event::{collision::CollisionEvent, PhysicRequestEvent, PhysicResponseEvent},
-    joint::{JointMotorEvent, MotorCommand},
+    force::ForcePoint,
+    force_field::{Drag, ForceFields},
+    joint::{
+        prismatic::PrismaticJoint, revolute::RevoluteJoint, spherical::SphericalJoint,
+        JointMotorEvent,
+    },
+    randomization::RandomizationConfig,
+    scene_query::SceneQueryFilter,
 };
 use kesko::plugins::{CorePlugins, HeadlessRenderPlugins, UIPlugin};
-use kesko::tcp::TcpPlugin;
+use kesko::tcp::{TcpMode, TcpPlugin};
+
+/// Name, entity id, depth and segmentation of a depth camera image
+type DepthImageArrays<'py> = (String, u64, &'py PyArray2<f32>, &'py PyArray2<u64>);
 
 static PYTHON_LOG_TO_BEVY_LOG_LEVEL: phf::Map<i32, Level> = phf_map! {
     10i32 => Level::DEBUG,
@@ -54,15 +75,28 @@ impl From<Model> for KeskoModel {
 #[pymodule]
 fn pykesko(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_class::<KeskoApp>()?;
+    m.add_class::<vec_app::KeskoVecApp>()?;
     m.add_class::<Model>()?;
     m.add_function(wrap_pyfunction!(run_kesko_tcp, m)?)?;
+    m.add_function(wrap_pyfunction!(env::observation, m)?)?;
+    m.add_function(wrap_pyfunction!(env::progress, m)?)?;
+    m.add_function(wrap_pyfunction!(env::has_fallen, m)?)?;
     Ok(())
 }
 
-/// Function to start Kesko with tcp communication
+/// Function to start Kesko with tcp communication, listening on `address`.
+/// When `free_running` the simulation doesn't wait for requests.
 #[pyfunction]
-fn run_kesko_tcp(window: bool, log_level: i32) {
+fn run_kesko_tcp(window: bool, log_level: i32, address: String, free_running: bool) {
     let bevy_log_level = PYTHON_LOG_TO_BEVY_LOG_LEVEL.get(&log_level).unwrap();
+    let tcp_plugin = TcpPlugin {
+        address,
+        mode: if free_running {
+            TcpMode::FreeRunning
+        } else {
+            TcpMode::LockStep
+        },
+    };
 
     if window {
         App::new()
@@ -72,13 +106,13 @@ fn run_kesko_tcp(window: bool, log_level: i32) {
                 },
                 CarPlugin,
                 WheelyPlugin,
-                TcpPlugin,
+                tcp_plugin,
             ))
             .add_systems(Startup, start_scene)
             .run();
     } else {
         App::new()
-            .add_plugins((HeadlessRenderPlugins::default(), TcpPlugin))
+            .add_plugins((HeadlessRenderPlugins::default(), tcp_plugin))
             .add_systems(Startup, start_scene)
             .run();
     }
@@ -130,7 +164,16 @@ impl KeskoApp {
         self.app.update();
     }
 
-    pub fn spawn(&mut self, model: Model, position: Vec<f32>, color: Vec<f32>) {
+    /// `collision_groups` is a pair of membership and filter bitmasks for all bodies of the model
+    #[pyo3(signature = (model, position, color, collision_groups=None, self_collision=None))]
+    pub fn spawn(
+        &mut self,
+        model: Model,
+        position: Vec<f32>,
+        color: Vec<f32>,
+        collision_groups: Option<(u32, u32)>,
+        self_collision: Option<bool>,
+    ) {
         self.app.world.send_event::<SpawnEvent>(SpawnEvent::Spawn {
             model: model.into(),
             transform: Transform::from_xyz(position[0], position[1], position[2]),
@@ -140,9 +183,55 @@ impl KeskoApp {
                 blue: color[2],
                 alpha: 1.0,
             },
+            collision: spawn_collision(collision_groups, self_collision),
         })
     }
 
+    #[pyo3(signature = (path, position, collision_groups=None, self_collision=None))]
+    pub fn spawn_urdf(
+        &mut self,
+        path: String,
+        position: Vec<f32>,
+        collision_groups: Option<(u32, u32)>,
+        self_collision: Option<bool>,
+    ) {
+        self.app
+            .world
+            .send_event::<SpawnEvent>(SpawnEvent::SpawnUrdf {
+                path: path.into(),
+                transform: Transform::from_xyz(position[0], position[1], position[2]),
+                collision: spawn_collision(collision_groups, self_collision),
+            })
+    }
+
+    /// Spawns terrain given as json e.g. `{"kind": "Stairs", "difficulty": 0.5, "seed": 1}`
+    pub fn spawn_terrain(
+        &mut self,
+        terrain: &str,
+        position: Vec<f32>,
+        color: Vec<f32>,
+    ) -> PyResult<()> {
+        let terrain = serde_json::from_str::<Terrain>(terrain).map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(format!("Invalid terrain: {}", e))
+        })?;
+        terrain.validate().map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(format!("Invalid terrain: {}", e))
+        })?;
+        self.app
+            .world
+            .send_event::<SpawnEvent>(SpawnEvent::SpawnTerrain {
+                terrain,
+                transform: Transform::from_xyz(position[0], position[1], position[2]),
+                color: Color::Rgba {
+                    red: color[0],
+                    green: color[1],
+                    blue: color[2],
+                    alpha: 1.0,
+                },
+            });
+        Ok(())
+    }
+
     pub fn despawn(&mut self, body_id: u64) {
         self.app
             .world
@@ -153,6 +242,157 @@ impl KeskoApp {
         self.app.world.send_event(PhysicRequestEvent::DespawnAll);
     }
 
+    pub fn save_snapshot(&mut self) {
+        self.app.world.send_event(PhysicRequestEvent::SaveSnapshot);
+    }
+
+    pub fn restore_snapshot(&mut self, snapshot_id: u64) {
+        self.app
+            .world
+            .send_event(PhysicRequestEvent::RestoreSnapshot(snapshot_id));
+    }
+
+    pub fn delete_snapshot(&mut self, snapshot_id: u64) {
+        self.app
+            .world
+            .send_event(PhysicRequestEvent::DeleteSnapshot(snapshot_id));
+    }
+
+    /// Sets how the physical parameters are randomized, the config is given as json
+    pub fn set_randomization(&mut self, config: &str) -> PyResult<()> {
+        let config = serde_json::from_str::<RandomizationConfig>(config).map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(format!("Invalid randomization config: {}", e))
+        })?;
+        self.app
+            .world
+            .send_event(PhysicRequestEvent::SetRandomization(config));
+        Ok(())
+    }
+
+    /// Samples new physical parameters for all bodies, joints and the world
+    pub fn randomize(&mut self) {
+        self.app.world.send_event(PhysicRequestEvent::Randomize);
+    }
+
+    /// Casts a ray against all colliders, the hit is returned with the physics events
+    #[pyo3(signature = (origin, direction, max_distance, collision_groups=None, exclude_sensors=false, exclude_body=None))]
+    pub fn raycast(
+        &mut self,
+        origin: Vec<f32>,
+        direction: Vec<f32>,
+        max_distance: f32,
+        collision_groups: Option<(u32, u32)>,
+        exclude_sensors: bool,
+        exclude_body: Option<u64>,
+    ) {
+        self.app.world.send_event(PhysicRequestEvent::Raycast {
+            origin: Vec3::from_slice(&origin),
+            direction: Vec3::from_slice(&direction),
+            max_distance,
+            filter: query_filter(collision_groups, exclude_sensors, exclude_body),
+        });
+    }
+
+    /// Finds all colliders overlapping a shape, given as json e.g. `{"Sphere": {"radius": 0.5}}`,
+    /// the rotation is a quaternion given as x, y, z and w
+    #[pyo3(signature = (shape, position, rotation=None, collision_groups=None, exclude_sensors=false, exclude_body=None))]
+    pub fn overlap(
+        &mut self,
+        shape: &str,
+        position: Vec<f32>,
+        rotation: Option<Vec<f32>>,
+        collision_groups: Option<(u32, u32)>,
+        exclude_sensors: bool,
+        exclude_body: Option<u64>,
+    ) -> PyResult<()> {
+        let shape = serde_json::from_str::<ColliderShape>(shape).map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(format!("Invalid shape: {}", e))
+        })?;
+        let rotation = rotation.map_or(Quat::IDENTITY, |rotation| {
+            Quat::from_slice(&rotation).normalize()
+        });
+
+        self.app.world.send_event(PhysicRequestEvent::Overlap {
+            shape,
+            transform: Transform::from_translation(Vec3::from_slice(&position))
+                .with_rotation(rotation),
+            filter: query_filter(collision_groups, exclude_sensors, exclude_body),
+        });
+        Ok(())
+    }
+
+    /// Applies a force to a body, or a named link of a multibody, at the center of mass or at a
+    /// point given in world coordinates, or relative to the body if `local_point` is set
+    #[pyo3(signature = (body_id, force, link=None, torque=None, point=None, local_point=false, persistent=false))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn apply_force(
+        &mut self,
+        body_id: u64,
+        force: Vec<f32>,
+        link: Option<String>,
+        torque: Option<Vec<f32>>,
+        point: Option<Vec<f32>>,
+        local_point: bool,
+        persistent: bool,
+    ) {
+        self.app.world.send_event(PhysicRequestEvent::ApplyForce {
+            id: body_id,
+            link,
+            force: Vec3::from_slice(&force),
+            torque: torque.map_or(Vec3::ZERO, |torque| Vec3::from_slice(&torque)),
+            point: force_point(point, local_point),
+            persistent,
+        });
+    }
+
+    /// Applies an impulse to a body, or a named link of a multibody, see `apply_force`
+    #[pyo3(signature = (body_id, impulse, link=None, torque=None, point=None, local_point=false))]
+    pub fn apply_impulse(
+        &mut self,
+        body_id: u64,
+        impulse: Vec<f32>,
+        link: Option<String>,
+        torque: Option<Vec<f32>>,
+        point: Option<Vec<f32>>,
+        local_point: bool,
+    ) {
+        self.app.world.send_event(PhysicRequestEvent::ApplyImpulse {
+            id: body_id,
+            link,
+            impulse: Vec3::from_slice(&impulse),
+            torque: torque.map_or(Vec3::ZERO, |torque| Vec3::from_slice(&torque)),
+            point: force_point(point, local_point),
+        });
+    }
+
+    /// Sets the wind and water volumes, the config is given as json
+    pub fn set_force_fields(&mut self, config: &str) -> PyResult<()> {
+        let force_fields = serde_json::from_str::<ForceFields>(config).map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(format!("Invalid force fields: {}", e))
+        })?;
+        self.app
+            .world
+            .send_event(PhysicRequestEvent::SetForceFields(force_fields));
+        Ok(())
+    }
+
+    /// Sets the drag of a body, or a named link of a multibody
+    #[pyo3(signature = (body_id, linear, quadratic=0.0, angular=0.0, link=None))]
+    pub fn set_drag(
+        &mut self,
+        body_id: u64,
+        linear: f32,
+        quadratic: f32,
+        angular: f32,
+        link: Option<String>,
+    ) {
+        self.app.world.send_event(PhysicRequestEvent::SetDrag {
+            id: body_id,
+            link,
+            drag: Drag::new(linear, quadratic, angular),
+        });
+    }
+
     pub fn get_physics_events(&mut self) -> PyResult<Option<String>> {
         let events = self.app.world.resource_mut::<Events<PhysicResponseEvent>>();
         if events.is_empty() {
@@ -185,18 +425,69 @@ impl KeskoApp {
         ))
     }
 
+    /// Latest image of each depth camera, the arrays have the shape (height, width). The pixels are
+    /// copied from the camera once and the buffers are handed over to numpy
+    pub fn get_depth_images<'py>(
+        &mut self,
+        py: Python<'py>,
+    ) -> PyResult<Vec<DepthImageArrays<'py>>> {
+        let world = &mut self.app.world;
+        let mut cameras = world.query::<(Entity, &DepthCamera, Option<&Name>)>();
+        cameras
+            .iter(world)
+            .filter(|(_, camera, _)| !camera.depth().is_empty())
+            .map(|(entity, camera, name)| {
+                let shape = [camera.height as usize, camera.width as usize];
+                let name = name.map_or_else(|| entity.index().to_string(), Name::to_string);
+                Ok((
+                    name,
+                    entity.to_bits(),
+                    PyArray::from_vec(py, camera.depth().to_vec()).reshape(shape)?,
+                    PyArray::from_vec(py, camera.segmentation().to_vec()).reshape(shape)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Targets for revolute and prismatic joints, joints whose motor has no stiffness get a
+    /// velocity target in the same way as over TCP
     pub fn apply_motor_commands(&mut self, command: BTreeMap<u64, f32>) {
         let world = &mut self.app.world;
         for (joint_id, val) in command.iter() {
             let entity = Entity::from_bits(*joint_id);
-            world.send_event::<JointMotorEvent>(JointMotorEvent {
-                entity,
-                command: MotorCommand::PositionRevolute {
-                    position: *val,
-                    stiffness: None,
-                    damping: None,
-                },
-            });
+            let command = motor_command(
+                world.get::<RevoluteJoint>(entity),
+                world.get::<PrismaticJoint>(entity),
+                *val,
+            );
+            world.send_event::<JointMotorEvent>(JointMotorEvent { entity, command });
+        }
+    }
+
+    /// Targets for the x, y and z axis of spherical joints, axes whose motor has no stiffness get
+    /// a velocity target in the same way as over TCP
+    pub fn apply_spherical_motor_commands(&mut self, command: BTreeMap<u64, Vec<f32>>) {
+        let world = &mut self.app.world;
+        for (joint_id, vals) in command.iter() {
+            let entity = Entity::from_bits(*joint_id);
+            let [x, y, z] = vals[..] else {
+                error!(
+                    "Spherical motor command for joint {}: expected 3 values, got {}",
+                    joint_id,
+                    vals.len()
+                );
+                continue;
+            };
+            let Some(joint) = world.get::<SphericalJoint>(entity) else {
+                error!(
+                    "Spherical motor command for joint {}: not a spherical joint",
+                    joint_id
+                );
+                continue;
+            };
+            for command in spherical_motor_commands(joint, Vec3::new(x, y, z)) {
+                world.send_event::<JointMotorEvent>(JointMotorEvent { entity, command });
+            }
         }
     }
 
@@ -241,6 +532,41 @@ impl Default for KeskoApp {
     }
 }
 
+fn spawn_collision(
+    collision_groups: Option<(u32, u32)>,
+    self_collision: Option<bool>,
+) -> SpawnCollision {
+    SpawnCollision {
+        groups: collision_groups
+            .map(|(memberships, filter)| CollisionGroups::new(memberships, filter)),
+        self_collision,
+    }
+}
+
+fn query_filter(
+    collision_groups: Option<(u32, u32)>,
+    exclude_sensors: bool,
+    exclude_body: Option<u64>,
+) -> SceneQueryFilter {
+    SceneQueryFilter {
+        groups: collision_groups
+            .map(|(memberships, filter)| CollisionGroups::new(memberships, filter)),
+        exclude_sensors,
+        exclude_body: exclude_body.map(Entity::from_bits),
+    }
+}
+
+fn force_point(point: Option<Vec<f32>>, local: bool) -> Option<ForcePoint> {
+    point.map(|point| {
+        let point = Vec3::from_slice(&point);
+        if local {
+            ForcePoint::Local(point)
+        } else {
+            ForcePoint::World(point)
+        }
+    })
+}
+
 fn start_scene(mut commands: Commands) {
     commands.spawn(DirectionalLightBundle {
         directional_light: DirectionalLight {
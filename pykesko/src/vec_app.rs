@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use numpy::{PyArray2, PyReadonlyArray2};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use kesko::core::event::{motor_command, spherical_motor_commands};
+use kesko::models::SpawnEvent;
+use kesko::physics::{
+    event::{collision::CollisionEvent, PhysicRequestEvent, PhysicResponseEvent},
+    imu::Imu,
+    joint::{
+        prismatic::PrismaticJoint, revolute::RevoluteJoint, spherical::SphericalJoint,
+        JointMotorEvent, JointState,
+    },
+    multibody::MultibodyRoot,
+    range_sensor::RangeSensor,
+};
+use kesko::plugins::HeadlessPhysicsPlugins;
+
+use crate::Model;
+
+/// Runs several independent simulations in the same process.
+///
+/// Each world is a separate Bevy app with its own physics resources. The worlds are stepped
+/// one after the other and actions and observations are exchanged in batches, one row per world.
+#[pyclass(unsendable)]
+pub struct KeskoVecApp {
+    worlds: Vec<App>,
+}
+
+#[pymethods]
+impl KeskoVecApp {
+    #[new]
+    pub fn new(num_envs: usize) -> Self {
+        let worlds = (0..num_envs)
+            .map(|_| {
+                let mut app = App::new();
+                app.add_plugins(HeadlessPhysicsPlugins);
+                app
+            })
+            .collect();
+        Self { worlds }
+    }
+
+    pub fn num_envs(&self) -> usize {
+        self.worlds.len()
+    }
+
+    /// Spawns the model in every world
+    pub fn spawn(&mut self, model: Model, position: Vec<f32>, color: Vec<f32>) {
+        for app in self.worlds.iter_mut() {
+            app.world.send_event(SpawnEvent::Spawn {
+                model: model.clone().into(),
+                transform: Transform::from_xyz(position[0], position[1], position[2]),
+                color: Color::Rgba {
+                    red: color[0],
+                    green: color[1],
+                    blue: color[2],
+                    alpha: 1.0,
+                },
+                collision: default(),
+            });
+        }
+    }
+
+    /// Spawns the URDF in every world
+    pub fn spawn_urdf(&mut self, path: String, position: Vec<f32>) {
+        for app in self.worlds.iter_mut() {
+            app.world.send_event(SpawnEvent::SpawnUrdf {
+                path: path.clone().into(),
+                transform: Transform::from_xyz(position[0], position[1], position[2]),
+                collision: default(),
+            });
+        }
+    }
+
+    /// Saves a snapshot in every world and returns its id, the worlds are updated once to save it
+    pub fn save_snapshot(&mut self) -> PyResult<u64> {
+        let mut ids = Vec::with_capacity(self.worlds.len());
+        for app in self.worlds.iter_mut() {
+            app.world.send_event(PhysicRequestEvent::SaveSnapshot);
+            app.update();
+            let id = app
+                .world
+                .resource::<Events<PhysicResponseEvent>>()
+                .iter_current_update_events()
+                .find_map(|event| match event {
+                    PhysicResponseEvent::SnapshotSaved(id) => Some(*id),
+                    _ => None,
+                })
+                .ok_or_else(|| PyValueError::new_err("Failed to save snapshot"))?;
+            ids.push(id);
+        }
+
+        match ids.first() {
+            Some(id) if ids.iter().all(|other| other == id) => Ok(*id),
+            Some(_) => Err(PyValueError::new_err(
+                "The snapshot got different ids in the envs",
+            )),
+            None => Err(PyValueError::new_err("No envs")),
+        }
+    }
+
+    /// Restores a snapshot in the given worlds, used to reset single environments. The worlds are
+    /// updated once to restore it, so the observations are the ones of the restored state.
+    pub fn restore_snapshot(&mut self, env_ids: Vec<usize>, snapshot_id: u64) -> PyResult<()> {
+        for env_id in env_ids {
+            let app = self
+                .worlds
+                .get_mut(env_id)
+                .ok_or_else(|| PyValueError::new_err(format!("No env with id {}", env_id)))?;
+            app.world
+                .send_event(PhysicRequestEvent::RestoreSnapshot(snapshot_id));
+            app.update();
+            snapshot_result(app)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a snapshot in every world, the worlds are updated once to delete it
+    pub fn delete_snapshot(&mut self, snapshot_id: u64) -> PyResult<()> {
+        for app in self.worlds.iter_mut() {
+            app.world
+                .send_event(PhysicRequestEvent::DeleteSnapshot(snapshot_id));
+            app.update();
+            snapshot_result(app)?;
+        }
+        Ok(())
+    }
+
+    /// Steps all the worlds without applying any actions
+    pub fn update(&mut self) {
+        for app in self.worlds.iter_mut() {
+            app.update();
+        }
+    }
+
+    /// Applies one row of actions to the joints of the agent in each world and steps all worlds.
+    ///
+    /// The agent is the first multibody whose name contains `agent`. Spherical joints take three
+    /// actions, for their x, y and z axes, and the other joints one. Joints whose motor has no
+    /// stiffness get velocity targets, see [`motor_command`]. Returns the observations and if the
+    /// root of the agent started colliding with something during the step.
+    pub fn step<'py>(
+        &mut self,
+        py: Python<'py>,
+        agent: &str,
+        actions: PyReadonlyArray2<'py, f32>,
+    ) -> PyResult<(&'py PyArray2<f32>, Vec<bool>)> {
+        let actions = actions.as_array();
+        if actions.nrows() != self.worlds.len() {
+            return Err(PyValueError::new_err(format!(
+                "Got actions for {} envs, expected {}",
+                actions.nrows(),
+                self.worlds.len()
+            )));
+        }
+
+        // check all rows before stepping any world so a bad row can't leave the worlds out of step
+        let agents = self
+            .worlds
+            .iter_mut()
+            .zip(actions.rows())
+            .map(|(app, action)| {
+                let (root, joints) = agent_joints(&mut app.world, agent)?;
+                let num_actions = joints
+                    .values()
+                    .map(|joint| joint_dofs(&app.world, *joint))
+                    .sum::<usize>();
+                if action.len() != num_actions {
+                    return Err(PyValueError::new_err(format!(
+                        "Got {} actions, the agent takes {}",
+                        action.len(),
+                        num_actions
+                    )));
+                }
+                Ok((root, joints))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let mut observations = Vec::with_capacity(self.worlds.len());
+        let mut collisions = Vec::with_capacity(self.worlds.len());
+        for ((app, action), (root, joints)) in
+            self.worlds.iter_mut().zip(actions.rows()).zip(agents)
+        {
+            let mut action = action.iter().copied();
+            for joint in joints.values() {
+                let world = &mut app.world;
+                let commands = match world.get::<SphericalJoint>(*joint) {
+                    Some(spherical) => {
+                        let vals = Vec3::from_array(std::array::from_fn(|_| {
+                            action.next().expect("actions should have been checked")
+                        }));
+                        spherical_motor_commands(spherical, vals).to_vec()
+                    }
+                    None => vec![motor_command(
+                        world.get::<RevoluteJoint>(*joint),
+                        world.get::<PrismaticJoint>(*joint),
+                        action.next().expect("actions should have been checked"),
+                    )],
+                };
+                for command in commands {
+                    world.send_event(JointMotorEvent {
+                        entity: *joint,
+                        command,
+                    });
+                }
+            }
+
+            app.update();
+
+            let collided = app
+                .world
+                .resource::<Events<CollisionEvent>>()
+                .iter_current_update_events()
+                .any(|event| match event {
+                    CollisionEvent::CollisionStarted(data) => {
+                        data.entity1 == root || data.entity2 == root
+                    }
+                    _ => false,
+                });
+
+            observations.push(observation(&app.world, root, &joints));
+            collisions.push(collided);
+        }
+
+        Ok((to_array(py, &observations)?, collisions))
+    }
+
+    /// Lower and upper limit of each agent action, spherical joints have one for each axis
+    pub fn get_joint_limits(&mut self, agent: &str) -> PyResult<Vec<Vec<f32>>> {
+        let app = self
+            .worlds
+            .first_mut()
+            .ok_or_else(|| PyValueError::new_err("No envs"))?;
+        let (_, joints) = agent_joints(&mut app.world, agent)?;
+
+        let limits = joints.values().flat_map(|entity| {
+            if let Some(joint) = app.world.get::<SphericalJoint>(*entity) {
+                vec![joint.x_ang_limit, joint.y_ang_limit, joint.z_ang_limit]
+            } else if let Some(joint) = app.world.get::<RevoluteJoint>(*entity) {
+                vec![joint.limits]
+            } else {
+                vec![app
+                    .world
+                    .get::<PrismaticJoint>(*entity)
+                    .and_then(|joint| joint.limits)]
+            }
+        });
+
+        Ok(limits
+            .map(|limits| {
+                limits.map_or(vec![f32::NEG_INFINITY, f32::INFINITY], |limits| {
+                    vec![limits.x, limits.y]
+                })
+            })
+            .collect())
+    }
+
+    /// Measurements of all range sensors on the agent in each world, concatenated in the order
+    /// of the link names with the root first
+    pub fn get_range_readings(&mut self, agent: &str) -> PyResult<Vec<Vec<f32>>> {
+        self.worlds
+            .iter_mut()
+            .map(|app| {
+                let (root, child_map) = agent_links(&mut app.world, agent)?;
+                Ok(std::iter::once(root)
+                    .chain(child_map.into_values())
+                    .filter_map(|entity| app.world.get::<RangeSensor>(entity))
+                    .flat_map(|sensor| sensor.ranges().to_vec())
+                    .collect())
+            })
+            .collect()
+    }
+
+    /// Measurements of all imus on the agent in each world, ten values for each imu in the order
+    /// of the link names with the root first: acceleration, angular velocity and orientation
+    pub fn get_imu_readings(&mut self, agent: &str) -> PyResult<Vec<Vec<f32>>> {
+        self.worlds
+            .iter_mut()
+            .map(|app| {
+                let (root, child_map) = agent_links(&mut app.world, agent)?;
+                Ok(std::iter::once(root)
+                    .chain(child_map.into_values())
+                    .filter_map(|entity| app.world.get::<Imu>(entity))
+                    .flat_map(|imu| imu.reading().to_array())
+                    .collect())
+            })
+            .collect()
+    }
+
+    /// Observations of the agent in each world without stepping
+    pub fn get_observations<'py>(
+        &mut self,
+        py: Python<'py>,
+        agent: &str,
+    ) -> PyResult<&'py PyArray2<f32>> {
+        let observations = self
+            .worlds
+            .iter_mut()
+            .map(|app| {
+                let (root, joints) = agent_joints(&mut app.world, agent)?;
+                Ok(observation(&app.world, root, &joints))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        to_array(py, &observations)
+    }
+}
+
+/// Fails if a snapshot request in the last update of the world failed
+fn snapshot_result(app: &App) -> PyResult<()> {
+    let failure = app
+        .world
+        .resource::<Events<PhysicResponseEvent>>()
+        .iter_current_update_events()
+        .find_map(|event| match event {
+            PhysicResponseEvent::SnapshotFailed { error, .. } => Some(error.clone()),
+            _ => None,
+        });
+    match failure {
+        Some(error) => Err(PyValueError::new_err(error)),
+        None => Ok(()),
+    }
+}
+
+/// One row for each world, fails if the rows have different lengths
+fn to_array<'py>(py: Python<'py>, rows: &[Vec<f32>]) -> PyResult<&'py PyArray2<f32>> {
+    PyArray2::from_vec2(py, rows).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Finds the agent root, the first multibody whose name contains `agent`, and its links by name
+fn agent_links(world: &mut World, agent: &str) -> PyResult<(Entity, BTreeMap<String, Entity>)> {
+    world
+        .query::<(Entity, &MultibodyRoot)>()
+        .iter(world)
+        .find(|(_, root)| root.name.contains(agent))
+        .map(|(entity, root)| (entity, root.child_map.clone()))
+        .ok_or_else(|| PyValueError::new_err(format!("Could not find agent '{}'", agent)))
+}
+
+/// Finds the agent root and its actuated joints ordered by entity id, the same order as the
+/// joints in `MultibodySpawned`
+fn agent_joints(world: &mut World, agent: &str) -> PyResult<(Entity, BTreeMap<u64, Entity>)> {
+    let (root, child_map) = agent_links(world, agent)?;
+
+    let joints = child_map
+        .values()
+        .filter(|entity| {
+            world.get::<RevoluteJoint>(**entity).is_some()
+                || world.get::<PrismaticJoint>(**entity).is_some()
+                || world.get::<SphericalJoint>(**entity).is_some()
+        })
+        .map(|entity| (entity.to_bits(), *entity))
+        .collect();
+
+    Ok((root, joints))
+}
+
+/// Number of actions a joint takes
+fn joint_dofs(world: &World, joint: Entity) -> usize {
+    if world.get::<SphericalJoint>(joint).is_some() {
+        3
+    } else {
+        1
+    }
+}
+
+/// Position, orientation, velocity and angular velocity of the agent root followed by
+/// the positions and velocities of its joints
+fn observation(world: &World, root: Entity, joints: &BTreeMap<u64, Entity>) -> Vec<f32> {
+    let transform = world.get::<Transform>(root).cloned().unwrap_or_default();
+    let multibody = world
+        .get::<MultibodyRoot>(root)
+        .expect("agent should be a multibody root");
+
+    let mut obs = Vec::new();
+    obs.extend(transform.translation.to_array());
+    obs.extend(transform.rotation.to_array());
+    obs.extend(multibody.linvel.to_array());
+    obs.extend(multibody.angvel.to_array());
+
+    let joint_states = joints
+        .values()
+        .filter_map(|entity| {
+            if let Some(joint) = world.get::<RevoluteJoint>(*entity) {
+                Some(joint.state())
+            } else if let Some(joint) = world.get::<SphericalJoint>(*entity) {
+                Some(joint.state())
+            } else {
+                world
+                    .get::<PrismaticJoint>(*entity)
+                    .map(|joint| joint.state())
+            }
+        })
+        .collect::<Vec<JointState>>();
+
+    obs.extend(joint_states.iter().flat_map(|state| state.positions()));
+    obs.extend(joint_states.iter().flat_map(|state| state.velocities()));
+
+    obs
+}